@@ -0,0 +1,394 @@
+use crate::ai::provider::{Provider, ProviderError};
+use crate::ai::tools::{self, Message, Tool, ToolCall};
+use crate::digest::{self, MatchData};
+use crate::events::{self, EventKind, ShotOutcome};
+use crate::plot;
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap};
+
+/// Tool-calling turns an agent may take before it has to answer, unless configured otherwise.
+pub const DEFAULT_MAX_STEPS: usize = 8;
+/// Most lines a tool returns at once; the model can narrow its query to see the rest.
+const MAX_RESULT_LINES: usize = 60;
+
+/// Seconds a team had the ball, and each of its players' share of them.
+type TeamPossession<'a> = (f64, Vec<(&'a str, f64)>);
+
+/// Answers the agent's tool calls from a converted match. Times are seconds since the
+/// start of the replay, the same clock the digest uses.
+pub struct MatchTools {
+    data: MatchData,
+    frame_times: BTreeMap<usize, f64>,
+    start: f64,
+    scale: f64,
+}
+
+impl MatchTools {
+    pub fn new(data: MatchData) -> Self {
+        let mut frame_times = BTreeMap::new();
+        for state in &data.frames {
+            frame_times.entry(state.frame).or_insert(state.time);
+        }
+        let start = frame_times.values().copied().fold(f64::INFINITY, f64::min);
+        let scale = events::position_scale(&data.frames);
+        MatchTools {
+            data,
+            frame_times,
+            start: if start.is_finite() { start } else { 0.0 },
+            scale,
+        }
+    }
+
+    /// The tools as offered to the model.
+    pub fn definitions() -> Vec<Tool> {
+        let time_range = json!({
+            "start_time": { "type": "number", "description": "Seconds since the start of the replay." },
+            "end_time": { "type": "number", "description": "Seconds since the start of the replay." }
+        });
+        vec![
+            Tool {
+                name: "get_player_stats".to_string(),
+                description: "Per-player touches, shots, goals, xG, demos, boost, speed and time in each third of the field."
+                    .to_string(),
+                parameters: json!({
+                    "type": "object",
+                    "properties": {
+                        "player": { "type": "string", "description": "Only this player; everyone if left out." }
+                    }
+                }),
+            },
+            Tool {
+                name: "get_events".to_string(),
+                description: "Touches, shots, demolitions or goals in order, with frame numbers and field positions."
+                    .to_string(),
+                parameters: json!({
+                    "type": "object",
+                    "properties": {
+                        "type": { "type": "string", "enum": ["touch", "shot", "demo", "goal"] },
+                        "start_time": time_range["start_time"],
+                        "end_time": time_range["end_time"],
+                        "player": { "type": "string", "description": "Only events by this player." }
+                    },
+                    "required": ["type"]
+                }),
+            },
+            Tool {
+                name: "get_positions".to_string(),
+                description: "Where the ball and every car are at a frame, with velocities and boost.".to_string(),
+                parameters: json!({
+                    "type": "object",
+                    "properties": { "frame": { "type": "integer" } },
+                    "required": ["frame"]
+                }),
+            },
+            Tool {
+                name: "get_possession".to_string(),
+                description: "Share of time each team and player had possession (last touch) over a time range."
+                    .to_string(),
+                parameters: json!({
+                    "type": "object",
+                    "properties": time_range
+                }),
+            },
+        ]
+    }
+
+    /// Runs a tool call, returning its result or what was wrong with it as text for the model.
+    pub fn call(&self, call: &ToolCall) -> String {
+        let args = &call.arguments;
+        let result = match call.name.as_str() {
+            "get_player_stats" => Ok(self.player_stats(args)),
+            "get_events" => self.events(args),
+            "get_positions" => self.positions(args),
+            "get_possession" => Ok(self.possession(args)),
+            name => Err(format!("unknown tool {}", name)),
+        };
+        match result {
+            Ok(lines) if lines.is_empty() => "Nothing found.".to_string(),
+            Ok(lines) if lines.len() > MAX_RESULT_LINES => format!(
+                "{}\n({} more lines; narrow the time range to see them)",
+                lines[..MAX_RESULT_LINES].join("\n"),
+                lines.len() - MAX_RESULT_LINES
+            ),
+            Ok(lines) => lines.join("\n"),
+            Err(e) => format!("Error: {}", e),
+        }
+    }
+
+    fn player_stats(&self, args: &Value) -> Vec<String> {
+        let table = digest::player_table(&self.data.frames, &self.data.events);
+        match args["player"].as_str() {
+            Some(player) => table
+                .into_iter()
+                .enumerate()
+                .filter(|(index, row)| {
+                    *index == 0 || row.split(" | ").next().is_some_and(|name| name.eq_ignore_ascii_case(player))
+                })
+                .map(|(_, row)| row)
+                .collect(),
+            None => table,
+        }
+    }
+
+    fn events(&self, args: &Value) -> Result<Vec<String>, String> {
+        let (from, to) = self.time_range(args);
+        let player = args["player"].as_str();
+        let by = |name: &str| player.is_none_or(|player| name.eq_ignore_ascii_case(player));
+
+        let kind = match args["type"].as_str() {
+            Some("touch") => EventKind::Touch,
+            Some("shot") => EventKind::Shot,
+            Some("demo") => EventKind::Demo,
+            Some("goal") => {
+                return Ok(self
+                    .data
+                    .goals
+                    .iter()
+                    .filter(|goal| by(&goal.player_name))
+                    .filter_map(|goal| {
+                        let time = self.time_at(goal.frame);
+                        (time >= from && time <= to).then(|| {
+                            format!(
+                                "{} goal by {} ({})",
+                                digest::stamp(goal.frame, time - self.start),
+                                goal.player_name,
+                                digest::team_name(goal.team)
+                            )
+                        })
+                    })
+                    .collect());
+            }
+            other => return Err(format!("type must be touch, shot, demo or goal, got {:?}", other)),
+        };
+
+        Ok(self
+            .data
+            .events
+            .iter()
+            .filter(|e| e.kind == kind && by(&e.player_name) && e.time >= from && e.time <= to)
+            .map(|e| {
+                let detail = match e.kind {
+                    EventKind::Touch => String::new(),
+                    EventKind::Shot => format!(
+                        ", {} (xG {:.2})",
+                        match e.outcome {
+                            Some(ShotOutcome::Goal) => "scored",
+                            Some(ShotOutcome::Saved) => "saved",
+                            _ => "missed",
+                        },
+                        e.xg.unwrap_or_default()
+                    ),
+                    EventKind::Demo => format!(", demolished {}", e.victim_name.as_deref().unwrap_or("someone")),
+                };
+                let kind = match e.kind {
+                    EventKind::Touch => "touch",
+                    EventKind::Shot => "shot",
+                    EventKind::Demo => "demo",
+                };
+                format!(
+                    "{} {} by {}{} at ({:.0}, {:.0}, {:.0})",
+                    digest::stamp(e.frame, e.time - self.start),
+                    kind,
+                    e.player_name,
+                    detail,
+                    e.location_x / self.scale,
+                    e.location_y / self.scale,
+                    e.location_z / self.scale
+                )
+            })
+            .collect())
+    }
+
+    fn positions(&self, args: &Value) -> Result<Vec<String>, String> {
+        let frame = args["frame"].as_u64().ok_or("frame must be a frame number")? as usize;
+        let (Some((&first, _)), Some((&last, _))) = (self.frame_times.first_key_value(), self.frame_times.last_key_value())
+        else {
+            return Err("the match has no frames".to_string());
+        };
+        if frame < first || frame > last {
+            return Err(format!("the replay has frames {} to {}", first, last));
+        }
+
+        let mut lines = vec![digest::stamp(frame, self.time_at(frame) - self.start)];
+        for state in plot::snapshot_at(&self.data.frames, frame) {
+            let name = if state.is_ball() {
+                "ball".to_string()
+            } else {
+                format!("{} ({})", state.player_name, state.team.map(digest::team_name).unwrap_or("-"))
+            };
+            let boost = state.boost_percent().map(|b| format!(", boost {}%", b)).unwrap_or_default();
+            lines.push(format!(
+                "{} at ({:.0}, {:.0}, {:.0}) moving ({:.0}, {:.0}, {:.0}) uu/s{}",
+                name,
+                state.location_x / self.scale,
+                state.location_y / self.scale,
+                state.location_z / self.scale,
+                state.linear_velocity_x / self.scale,
+                state.linear_velocity_y / self.scale,
+                state.linear_velocity_z / self.scale,
+                boost
+            ));
+        }
+        Ok(lines)
+    }
+
+    /// Credits the time from each touch to the next to whoever touched it.
+    fn possession(&self, args: &Value) -> Vec<String> {
+        let (from, to) = self.time_range(args);
+        let to = to.min(self.frame_times.values().copied().fold(self.start, f64::max));
+        let mut touches: Vec<_> = self.data.events.iter().filter(|e| e.kind == EventKind::Touch).collect();
+        touches.sort_by(|a, b| a.time.total_cmp(&b.time));
+
+        let mut players: HashMap<&str, (Option<u32>, f64)> = HashMap::new();
+        for (index, touch) in touches.iter().enumerate() {
+            let until = touches.get(index + 1).map(|next| next.time).unwrap_or(to);
+            let held = until.min(to) - touch.time.max(from);
+            if held > 0.0 {
+                players.entry(touch.player_name.as_str()).or_insert((touch.team, 0.0)).1 += held;
+            }
+        }
+        let total: f64 = players.values().map(|(_, time)| time).sum();
+        if total <= 0.0 {
+            return Vec::new();
+        }
+
+        let mut teams: BTreeMap<Option<u32>, TeamPossession> = BTreeMap::new();
+        for (name, (team, time)) in players {
+            let entry = teams.entry(team).or_default();
+            entry.0 += time;
+            entry.1.push((name, time));
+        }
+        teams
+            .into_iter()
+            .map(|(team, (time, mut players))| {
+                players.sort_by(|a, b| b.1.total_cmp(&a.1));
+                let shares: Vec<String> = players
+                    .iter()
+                    .map(|(name, time)| format!("{} {:.0}%", name, time * 100.0 / total))
+                    .collect();
+                format!(
+                    "{} {:.0}% ({})",
+                    team.map(digest::team_name).unwrap_or("-"),
+                    time * 100.0 / total,
+                    shares.join(", ")
+                )
+            })
+            .collect()
+    }
+
+    /// The requested time range as replay times, the whole match by default.
+    fn time_range(&self, args: &Value) -> (f64, f64) {
+        let from = args["start_time"].as_f64().map(|t| self.start + t).unwrap_or(f64::NEG_INFINITY);
+        let to = args["end_time"].as_f64().map(|t| self.start + t).unwrap_or(f64::INFINITY);
+        (from, to)
+    }
+
+    fn time_at(&self, frame: usize) -> f64 {
+        self.frame_times
+            .range(..=frame)
+            .next_back()
+            .map(|(_, time)| *time)
+            .unwrap_or(self.start)
+    }
+}
+
+/// A finished investigation: every message exchanged and the feedback it ended with.
+#[derive(Debug, Clone)]
+pub struct Transcript {
+    pub messages: Vec<Message>,
+    pub answer: String,
+}
+
+/// Lets `provider` investigate the match with `tools` until it answers `task`. After
+/// `max_steps` turns of tool calls it is told to answer with what it has, and offered no tools.
+pub async fn investigate(
+    provider: &dyn Provider,
+    tools: &MatchTools,
+    task: &str,
+    max_steps: usize,
+) -> Result<Transcript, ProviderError> {
+    let mut messages = vec![Message::User(task.to_string())];
+    let answer = respond(provider, tools, &mut messages, max_steps).await?;
+    Ok(Transcript { messages, answer })
+}
+
+/// Continues `messages`, which end with the user's turn, running the tool calls `provider`
+/// makes until it answers, and returns the answer. Every message of the exchange is added to
+/// `messages`. Providers without tool calling answer from the conversation alone.
+pub async fn respond(
+    provider: &dyn Provider,
+    tools: &MatchTools,
+    messages: &mut Vec<Message>,
+    max_steps: usize,
+) -> Result<String, ProviderError> {
+    let definitions = if provider.capabilities().tools {
+        MatchTools::definitions()
+    } else {
+        Vec::new()
+    };
+
+    let mut steps = 0;
+    loop {
+        let last = steps == max_steps;
+        if last {
+            messages.push(Message::User(
+                "You have used all your tool calls. Answer now with what you found.".to_string(),
+            ));
+        }
+        // The last turn offers no tools, so the model has to answer in text
+        let reply = if last {
+            provider.chat(&tools::as_text(messages), &[]).await?
+        } else {
+            provider.chat(messages, &definitions).await?
+        };
+        let calls = if last { Vec::new() } else { reply.tool_calls };
+        messages.push(Message::Assistant {
+            text: reply.text.clone(),
+            tool_calls: calls.clone(),
+        });
+
+        if calls.is_empty() {
+            if reply.text.trim().is_empty() {
+                return Err(format!("{} ended without an answer", provider.name()).into());
+            }
+            return Ok(reply.text);
+        }
+
+        for call in calls {
+            println!("{} calls {}({})", provider.name(), call.name, call.arguments);
+            messages.push(Message::ToolResult {
+                content: tools.call(&call),
+                call_id: call.id,
+                name: call.name,
+            });
+        }
+        steps += 1;
+    }
+}
+
+/// Renders a transcript as markdown, with tool results in code blocks.
+pub fn render_transcript(provider_name: &str, transcript: &Transcript) -> String {
+    format!("## {}\n{}", provider_name, render_messages(provider_name, "Task", &transcript.messages))
+}
+
+/// Renders `messages` as markdown, labelling the user's turns `user_label`.
+pub fn render_messages(provider_name: &str, user_label: &str, messages: &[Message]) -> String {
+    let mut markdown = String::new();
+    for message in messages {
+        match message {
+            Message::User(text) => markdown.push_str(&format!("\n**{}**\n\n{}\n", user_label, text)),
+            Message::Assistant { text, tool_calls } => {
+                if !text.trim().is_empty() {
+                    markdown.push_str(&format!("\n**{}**\n\n{}\n", provider_name, text.trim()));
+                }
+                for call in tool_calls {
+                    markdown.push_str(&format!("\n- calls `{}({})`\n", call.name, call.arguments));
+                }
+            }
+            Message::ToolResult { name, content, .. } => {
+                markdown.push_str(&format!("\n`{}` returned:\n```\n{}\n```\n", name, content));
+            }
+        }
+    }
+    markdown
+}
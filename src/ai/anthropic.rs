@@ -1,93 +1,347 @@
-use reqwest::Client;
+use crate::ai::config::ProviderConfig;
+use crate::ai::http::{self, AiError};
+use crate::ai::image::{self, Image};
+use crate::ai::provider::{Capabilities, OnText, Provider, ProviderError};
+use crate::ai::registry::mask_key;
+use crate::ai::tokens;
+use crate::ai::tools::{Message, Reply, Tool, ToolCall};
+use crate::ai::usage::{Meter, Usage};
+use async_trait::async_trait;
+use reqwest::{Client, RequestBuilder};
 use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
 use std::env;
-use std::error::Error;
+use std::sync::Arc;
+
+/// Claude's tokenizer splits text finer than OpenAI's.
+const CHARS_PER_TOKEN: f64 = 3.2;
 
 #[derive(Debug, Serialize)]
 struct ClaudeRequest {
     model: String,
     max_tokens: u32,
-    messages: Vec<Message>,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-struct Message {
-    role: String,
-    content: String,
+    messages: Vec<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    system: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tools: Option<Vec<Value>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_choice: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stream: Option<bool>,
 }
 
 #[derive(Debug, Deserialize)]
 struct Content {
-    text: String,
+    #[serde(rename = "type")]
+    kind: String,
+    text: Option<String>,
+    id: Option<String>,
+    name: Option<String>,
+    input: Option<Value>,
 }
 
 #[derive(Debug, Deserialize)]
 struct ClaudeResponse {
     content: Vec<Content>,
+    stop_reason: Option<String>,
+    usage: Option<TokenUsage>,
+}
+
+/// The server-sent events of a streamed message that carry text or usage.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum StreamEvent {
+    MessageStart { message: StreamMessage },
+    ContentBlockDelta { delta: TextDelta },
+    MessageDelta { delta: StopDelta, usage: OutputUsage },
+    Error { error: Value },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Deserialize)]
+struct StreamMessage {
+    usage: TokenUsage,
+}
+
+/// Text deltas have `text`; deltas of tool input don't.
+#[derive(Debug, Deserialize)]
+struct TextDelta {
+    text: Option<String>,
 }
 
-/// Sends a query to Claude AI and returns the response.
-///
-/// # Arguments
-/// * `prompt` - The prompt string to send to Claude.
-///
-/// # Returns
-/// A `Result` containing the response string, or an error if the request fails.
-pub async fn query_anthropic(prompt: &str) -> Result<String, Box<dyn Error>> {
-    // Retrieve the API key from the environment
-    let api_key = env::var("ANTHROPIC_API_KEY").expect("ANTHROPIC_API_KEY must be set");
-
-    // Hardcoded configuration
-    let base_url = "https://api.anthropic.com/v1/messages";
-    let model = "claude-3-5-sonnet-20241022";
-    let max_tokens = 8192;
-
-    // Prepare the request body
-    let request_body = ClaudeRequest {
-        model: model.to_string(),
-        max_tokens,
-        messages: vec![Message {
-            role: "user".to_string(),
-            content: prompt.to_string(),
-        }],
-    };
-
-    // Initialize HTTP client
-    let client = Client::new();
-
-    // Send the request
-    let response = client
-        .post(base_url)
-        .header("x-api-key", &api_key)
-        .header("anthropic-version", "2023-06-01")
-        .header("content-type", "application/json")
-        .json(&request_body)
-        .send()
+#[derive(Debug, Deserialize)]
+struct StopDelta {
+    stop_reason: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OutputUsage {
+    output_tokens: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenUsage {
+    input_tokens: u64,
+    output_tokens: u64,
+}
+
+pub struct Anthropic {
+    api_key: String,
+    config: ProviderConfig,
+    client: Client,
+    meter: Arc<Meter>,
+}
+
+impl Anthropic {
+    pub fn with_config(api_key: &str, mut config: ProviderConfig) -> Self {
+        config.base_url = config.base_url.trim_end_matches('/').to_string();
+        Anthropic {
+            api_key: api_key.to_string(),
+            config,
+            client: http::client(),
+            meter: Arc::default(),
+        }
+    }
+
+    /// Counts this provider's usage and cost in `meter`, shared with the rest of the run.
+    pub fn with_meter(mut self, meter: Arc<Meter>) -> Self {
+        self.meter = meter;
+        self
+    }
+
+    /// Configured from `ANTHROPIC_API_KEY`, if set.
+    pub fn from_env(config: &ProviderConfig) -> Option<Self> {
+        let api_key = env::var("ANTHROPIC_API_KEY").ok()?;
+        println!("Using Anthropic {} with key: {}", config.model, mask_key(&api_key));
+        Some(Anthropic::with_config(&api_key, config.clone()))
+    }
+
+    /// Sends a message with `images` attached, optionally forcing Claude to answer through `tool`.
+    async fn send(&self, prompt: &str, images: &[Image], tool: Option<Value>) -> Result<ClaudeResponse, ProviderError> {
+        let messages = vec![json!({ "role": "user", "content": image::anthropic_content(prompt, images) })];
+        let tool_choice = tool.as_ref().map(|tool| json!({ "type": "tool", "name": tool["name"] }));
+        self.request(messages, images, tool.map(|tool| vec![tool]), tool_choice).await
+    }
+
+    /// The request for `messages`.
+    fn prepare(
+        &self,
+        messages: Vec<Value>,
+        tools: Option<Vec<Value>>,
+        tool_choice: Option<Value>,
+    ) -> (RequestBuilder, ClaudeRequest) {
+        let api_url = format!("{}/messages", self.config.base_url);
+
+        // Prepare the request body; the Messages API requires max_tokens
+        let request_body = ClaudeRequest {
+            model: self.config.model.clone(),
+            max_tokens: self.meter.max_tokens(&self.config).unwrap_or(8192),
+            messages,
+            system: self.config.system_prompt.clone(),
+            temperature: self.config.temperature,
+            top_p: self.config.top_p,
+            tools,
+            tool_choice,
+            stream: None,
+        };
+
+        let mut request = self.client.post(&api_url).header("x-api-key", &self.api_key);
+        if let Some(api_version) = &self.config.api_version {
+            request = request.header("anthropic-version", api_version);
+        }
+        (request, request_body)
+    }
+
+    /// Sends `messages`, which carry `images`.
+    async fn request(
+        &self,
+        messages: Vec<Value>,
+        images: &[Image],
+        tools: Option<Vec<Value>>,
+        tool_choice: Option<Value>,
+    ) -> Result<ClaudeResponse, ProviderError> {
+        let (request, request_body) = self.prepare(messages, tools, tool_choice);
+        let body = serde_json::to_string(&request_body)?;
+        let prompt_tokens = image::request_tokens(|text| self.estimate_tokens(text), &body, images);
+        let reservation = self.meter.reserve(self.name(), &self.config, prompt_tokens)?;
+        let response_text = http::send_json(request, &request_body).await?;
+
+        let response_body: ClaudeResponse = http::parse_json(&response_text)?;
+        reservation.record(
+            response_body
+                .usage
+                .as_ref()
+                .map(|usage| Usage {
+                    input_tokens: usage.input_tokens,
+                    output_tokens: usage.output_tokens,
+                })
+                .unwrap_or_default(),
+        );
+        if response_body.stop_reason.as_deref() == Some("refusal") {
+            return Err(AiError::ContentFilter("Claude declined to answer".to_string()).into());
+        }
+        Ok(response_body)
+    }
+}
+
+#[async_trait]
+impl Provider for Anthropic {
+    fn name(&self) -> &str {
+        "Anthropic"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            system_prompt: true,
+            images: true,
+            tools: true,
+            structured_output: true,
+            streaming: true,
+        }
+    }
+
+    fn estimate_tokens(&self, text: &str) -> usize {
+        tokens::estimate(text, CHARS_PER_TOKEN)
+    }
+
+    fn prompt_budget(&self) -> usize {
+        tokens::prompt_budget(&self.config.model, self.config.prompt_tokens, self.config.max_tokens)
+    }
+
+    fn settings(&self) -> String {
+        self.config.fingerprint()
+    }
+
+    /// Streams the answer from the Messages API, passing each text delta to `on_text`, and
+    /// returns the full answer.
+    async fn stream(&self, prompt: &str, images: &[Image], on_text: &OnText<'_>) -> Result<String, ProviderError> {
+        let messages = vec![json!({ "role": "user", "content": image::anthropic_content(prompt, images) })];
+        let (request, mut request_body) = self.prepare(messages, None, None);
+        request_body.stream = Some(true);
+
+        let body = serde_json::to_string(&request_body)?;
+        let prompt_tokens = image::request_tokens(|text| self.estimate_tokens(text), &body, images);
+        let reservation = self.meter.reserve(self.name(), &self.config, prompt_tokens)?;
+        let mut answer = String::new();
+        let mut usage = Usage::default();
+        http::stream_json(request, &request_body, |event| {
+            match http::parse_json(event)? {
+                StreamEvent::MessageStart { message } => usage.input_tokens = message.usage.input_tokens,
+                StreamEvent::ContentBlockDelta { delta } => {
+                    if let Some(text) = delta.text {
+                        on_text(&text);
+                        answer.push_str(&text);
+                    }
+                }
+                StreamEvent::MessageDelta { delta, usage: output } => {
+                    usage.output_tokens = output.output_tokens;
+                    if delta.stop_reason.as_deref() == Some("refusal") {
+                        return Err(AiError::ContentFilter("Claude declined to answer".to_string()).into());
+                    }
+                }
+                // An error after the stream started, such as overloaded_error, stands in for a 5xx
+                StreamEvent::Error { error } => return Err(AiError::Status(500, error.to_string()).into()),
+                StreamEvent::Other => {}
+            }
+            Ok(())
+        })
         .await?;
+        reservation.record(usage);
 
-    // Extract status and response text for debugging
-    let status = response.status();
-    let response_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
+        if answer.is_empty() {
+            return Err(AiError::Malformed("No text in Claude's answer".to_string()).into());
+        }
+        Ok(answer)
+    }
+
+    async fn query(&self, prompt: &str) -> Result<String, ProviderError> {
+        self.query_with_images(prompt, &[]).await
+    }
 
-    if status.is_success() {
-        // eprintln!("Successful response: {}", response_text); // Debugging raw response
-        // Parse the response JSON
-        let response_body: ClaudeResponse = serde_json::from_str(&response_text)?;
+    async fn query_with_images(&self, prompt: &str, images: &[Image]) -> Result<String, ProviderError> {
+        let response_body = self.send(prompt, images, None).await?;
 
         // Extract and concatenate the text from the content array
-        let response_text = response_body
+        Ok(response_body
             .content
             .into_iter()
-            .map(|c| c.text)
+            .filter_map(|c| c.text)
             .collect::<Vec<String>>()
-            .join(" ");
-
-        Ok(response_text)
-    } else {
-        eprintln!("Failed response: {}", response_text); // Debugging raw error response
-        Err(Box::new(std::io::Error::new(
-            std::io::ErrorKind::Other,
-            format!("Request failed with status: {} - {}", status, response_text),
-        )))
+            .join(" "))
+    }
+
+    /// Forces a call to a tool whose input schema is `schema`, and returns the tool input.
+    async fn query_structured(&self, prompt: &str, schema: &Value) -> Result<String, ProviderError> {
+        let tool = json!({
+            "name": "coaching_report",
+            "description": "Record the coaching report for this match.",
+            "input_schema": schema,
+        });
+        let response_body = self.send(prompt, &[], Some(tool)).await?;
+
+        let input = response_body
+            .content
+            .into_iter()
+            .find(|c| c.kind == "tool_use")
+            .and_then(|c| c.input)
+            .ok_or_else(|| AiError::Malformed("Claude did not call the coaching_report tool".to_string()))?;
+        Ok(input.to_string())
+    }
+
+    /// Tool results go back as a user message, one per run of results.
+    async fn chat(&self, messages: &[Message], tools: &[Tool]) -> Result<Reply, ProviderError> {
+        let mut turns: Vec<Value> = Vec::new();
+        for message in messages {
+            match message {
+                Message::User(text) => turns.push(json!({ "role": "user", "content": text })),
+                Message::Assistant { text, tool_calls } => {
+                    let mut blocks = Vec::new();
+                    if !text.is_empty() {
+                        blocks.push(json!({ "type": "text", "text": text }));
+                    }
+                    for call in tool_calls {
+                        blocks.push(json!({ "type": "tool_use", "id": call.id, "name": call.name, "input": call.arguments }));
+                    }
+                    turns.push(json!({ "role": "assistant", "content": blocks }));
+                }
+                Message::ToolResult { call_id, content, .. } => {
+                    let block = json!({ "type": "tool_result", "tool_use_id": call_id, "content": content });
+                    let results = turns
+                        .last_mut()
+                        .filter(|last| last["role"] == "user")
+                        .and_then(|last| last["content"].as_array_mut());
+                    match results {
+                        Some(blocks) => blocks.push(block),
+                        None => turns.push(json!({ "role": "user", "content": [block] })),
+                    }
+                }
+            }
+        }
+        let tools = tools
+            .iter()
+            .map(|tool| json!({ "name": tool.name, "description": tool.description, "input_schema": tool.parameters }))
+            .collect::<Vec<_>>();
+
+        let response_body = self
+            .request(turns, &[], (!tools.is_empty()).then_some(tools), None)
+            .await?;
+        let mut reply = Reply::default();
+        for content in response_body.content {
+            match content.kind.as_str() {
+                "tool_use" => reply.tool_calls.push(ToolCall {
+                    id: content.id.unwrap_or_default(),
+                    name: content.name.unwrap_or_default(),
+                    arguments: content.input.unwrap_or(Value::Null),
+                }),
+                _ => reply.text.push_str(content.text.as_deref().unwrap_or_default()),
+            }
+        }
+        Ok(reply)
     }
 }
@@ -0,0 +1,209 @@
+use crate::ai::image::Image;
+use crate::ai::provider::{Capabilities, OnText, Provider, ProviderError};
+use crate::ai::tools::{Message, Reply, Tool};
+use async_trait::async_trait;
+use serde_json::{json, Value};
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Where answers are cached, one JSON file per request.
+pub const CACHE_DIR: &str = "./output/cache";
+/// How long a cached answer is reused, unless configured otherwise: a week.
+pub const DEFAULT_TTL_SECS: u64 = 7 * 24 * 60 * 60;
+
+/// Whether answers are read from and saved to the cache.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheMode {
+    /// Reuse cached answers that haven't expired, and save new ones.
+    Use,
+    /// Ask the provider again and replace what was cached.
+    Refresh,
+    /// Neither read nor write the cache.
+    Off,
+}
+
+impl CacheMode {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "on" => Ok(CacheMode::Use),
+            "refresh" => Ok(CacheMode::Refresh),
+            "off" => Ok(CacheMode::Off),
+            _ => Err(format!("cache must be on, refresh or off, got {}", value)),
+        }
+    }
+}
+
+/// A provider whose answers are saved to disk and reused for the same request: the same
+/// provider, settings (see `Provider::settings`) and prompt. Each entry keeps its request,
+/// so an entry whose file name merely hashes the same isn't reused. Failed requests aren't
+/// cached.
+pub struct Cached {
+    inner: Box<dyn Provider>,
+    dir: PathBuf,
+    mode: CacheMode,
+    ttl: Duration,
+}
+
+impl Cached {
+    pub fn new(inner: Box<dyn Provider>, dir: &str, mode: CacheMode, ttl: Duration) -> Self {
+        Cached {
+            inner,
+            dir: PathBuf::from(dir),
+            mode,
+            ttl,
+        }
+    }
+
+    /// The cached answer to `request`, if there is one to use.
+    fn load(&self, request: &str) -> Option<Value> {
+        if self.mode != CacheMode::Use {
+            return None;
+        }
+        let content = fs::read_to_string(self.path(request)).ok()?;
+        let entry: Value = serde_json::from_str(&content).ok()?;
+        if entry["provider"] != self.inner.name()
+            || entry["settings"] != self.inner.settings()
+            || entry["request"] != request
+        {
+            return None;
+        }
+        let age = now().saturating_sub(entry["created"].as_u64()?);
+        if age >= self.ttl.as_secs() {
+            return None;
+        }
+        println!("Using the answer {} gave {} ago (cached)", self.inner.name(), age_label(age));
+        Some(entry["response"].clone())
+    }
+
+    /// Saves the answer to `request`, reporting rather than failing if it can't.
+    fn save(&self, request: &str, response: Value) {
+        if self.mode == CacheMode::Off {
+            return;
+        }
+        let entry = json!({
+            "provider": self.inner.name(),
+            "settings": self.inner.settings(),
+            "request": request,
+            "created": now(),
+            "response": response,
+        });
+        let path = self.path(request);
+        if let Err(e) = fs::create_dir_all(&self.dir).and_then(|_| fs::write(&path, entry.to_string())) {
+            eprintln!("Failed to cache the answer from {} in {}: {}", self.inner.name(), path.display(), e);
+        }
+    }
+
+    fn path(&self, request: &str) -> PathBuf {
+        let key = format!("{}\n{}\n{}", self.inner.name(), self.inner.settings(), request);
+        self.dir.join(format!("{:016x}.json", fnv1a(key.as_bytes())))
+    }
+}
+
+#[async_trait]
+impl Provider for Cached {
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        self.inner.capabilities()
+    }
+
+    fn estimate_tokens(&self, text: &str) -> usize {
+        self.inner.estimate_tokens(text)
+    }
+
+    fn prompt_budget(&self) -> usize {
+        self.inner.prompt_budget()
+    }
+
+    fn settings(&self) -> String {
+        self.inner.settings()
+    }
+
+    async fn query(&self, prompt: &str) -> Result<String, ProviderError> {
+        let request = format!("query\n{}", prompt);
+        if let Some(Value::String(answer)) = self.load(&request) {
+            return Ok(answer);
+        }
+        let answer = self.inner.query(prompt).await?;
+        self.save(&request, json!(answer));
+        Ok(answer)
+    }
+
+    async fn query_with_images(&self, prompt: &str, images: &[Image]) -> Result<String, ProviderError> {
+        let request = images_request(prompt, images);
+        if let Some(Value::String(answer)) = self.load(&request) {
+            return Ok(answer);
+        }
+        let answer = self.inner.query_with_images(prompt, images).await?;
+        self.save(&request, json!(answer));
+        Ok(answer)
+    }
+
+    /// Shares cached answers with `query` and `query_with_images`; a cached answer is passed
+    /// on whole.
+    async fn query_streaming_with_images(
+        &self,
+        prompt: &str,
+        images: &[Image],
+        on_text: &OnText<'_>,
+    ) -> Result<String, ProviderError> {
+        let request = images_request(prompt, images);
+        if let Some(Value::String(answer)) = self.load(&request) {
+            on_text(&answer);
+            return Ok(answer);
+        }
+        let answer = self.inner.query_streaming_with_images(prompt, images, on_text).await?;
+        self.save(&request, json!(answer));
+        Ok(answer)
+    }
+
+    async fn query_structured(&self, prompt: &str, schema: &Value) -> Result<String, ProviderError> {
+        let request = format!("structured\n{}\n{}", schema, prompt);
+        if let Some(Value::String(answer)) = self.load(&request) {
+            return Ok(answer);
+        }
+        let answer = self.inner.query_structured(prompt, schema).await?;
+        self.save(&request, json!(answer));
+        Ok(answer)
+    }
+
+    async fn chat(&self, messages: &[Message], tools: &[Tool]) -> Result<Reply, ProviderError> {
+        let request = format!("chat\n{:?}\n{:?}", tools, messages);
+        if let Some(reply) = self.load(&request).and_then(|reply| serde_json::from_value(reply).ok()) {
+            return Ok(reply);
+        }
+        let reply = self.inner.chat(messages, tools).await?;
+        self.save(&request, json!(reply));
+        Ok(reply)
+    }
+}
+
+/// The cache key of a query: the prompt, and a hash of each image attached to it.
+fn images_request(prompt: &str, images: &[Image]) -> String {
+    let hashes: Vec<String> = images.iter().map(|image| format!("{:016x}", fnv1a(image.data.as_bytes()))).collect();
+    if hashes.is_empty() {
+        format!("query\n{}", prompt)
+    } else {
+        format!("query\n{}\nimages {}", prompt, hashes.join(" "))
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default()
+}
+
+fn age_label(secs: u64) -> String {
+    match secs {
+        0..=119 => format!("{}s", secs),
+        120..=7199 => format!("{}m", secs / 60),
+        _ => format!("{}h", secs / 3600),
+    }
+}
+
+/// 64-bit FNV-1a, which unlike `DefaultHasher` gives the same hash on every build.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, &byte| (hash ^ byte as u64).wrapping_mul(0x100000001b3))
+}
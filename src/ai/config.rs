@@ -0,0 +1,407 @@
+use crate::agent::DEFAULT_MAX_STEPS;
+use crate::ai::cache::{CacheMode, DEFAULT_TTL_SECS};
+use crate::ai::usage::{self, Price};
+use crate::profile::Profile;
+use crate::templates;
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::env;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+/// Config file read when neither `--config` nor `RATTLEBRAIN_CONFIG` is given.
+const DEFAULT_CONFIG_FILE: &str = "./rattlebrain.json";
+
+const COACH_SYSTEM_PROMPT: &str = "You are a world-class Rocket League coach.";
+
+/// Request settings for one provider.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProviderConfig {
+    pub model: String,
+    pub max_tokens: Option<u32>,
+    /// Tokens the prompt may use; by default the model's context window less `max_tokens`.
+    pub prompt_tokens: Option<usize>,
+    pub temperature: Option<f64>,
+    pub top_p: Option<f64>,
+    pub system_prompt: Option<String>,
+    pub base_url: String,
+    /// Sent as the `anthropic-version` header; unused by the other providers.
+    pub api_version: Option<String>,
+    /// US dollars per million prompt tokens, when the model's list price isn't known or is wrong.
+    pub input_price: Option<f64>,
+    /// US dollars per million answer tokens.
+    pub output_price: Option<f64>,
+}
+
+impl ProviderConfig {
+    pub fn openai() -> Self {
+        ProviderConfig {
+            model: "gpt-4o".to_string(),
+            max_tokens: None,
+            prompt_tokens: None,
+            temperature: None,
+            top_p: None,
+            system_prompt: Some(COACH_SYSTEM_PROMPT.to_string()),
+            base_url: "https://api.openai.com/v1".to_string(),
+            api_version: None,
+            input_price: None,
+            output_price: None,
+        }
+    }
+
+    pub fn anthropic() -> Self {
+        ProviderConfig {
+            model: "claude-3-5-sonnet-20241022".to_string(),
+            max_tokens: Some(8192),
+            prompt_tokens: None,
+            temperature: None,
+            top_p: None,
+            system_prompt: None,
+            base_url: "https://api.anthropic.com/v1".to_string(),
+            api_version: Some("2023-06-01".to_string()),
+            input_price: None,
+            output_price: None,
+        }
+    }
+
+    pub fn gemini() -> Self {
+        ProviderConfig {
+            model: "gemini-1.5-flash-latest".to_string(),
+            max_tokens: None,
+            prompt_tokens: None,
+            temperature: None,
+            top_p: None,
+            system_prompt: None,
+            base_url: "https://generativelanguage.googleapis.com/v1beta".to_string(),
+            api_version: None,
+            input_price: None,
+            output_price: None,
+        }
+    }
+
+    /// An OpenAI-compatible server such as Ollama on its default port.
+    pub fn local() -> Self {
+        ProviderConfig {
+            model: "llama3.1".to_string(),
+            base_url: "http://localhost:11434/v1".to_string(),
+            ..ProviderConfig::openai()
+        }
+    }
+
+    /// Sets a single field by name, parsing `value` as the field's type.
+    pub fn set(&mut self, field: &str, value: &str) -> Result<(), Box<dyn Error>> {
+        match field {
+            "model" => self.model = value.to_string(),
+            "max_tokens" => self.max_tokens = Some(value.parse()?),
+            "prompt_tokens" => self.prompt_tokens = Some(value.parse()?),
+            "temperature" => self.temperature = Some(value.parse()?),
+            "top_p" => self.top_p = Some(value.parse()?),
+            "system_prompt" => self.system_prompt = Some(value.to_string()),
+            "base_url" => self.base_url = value.to_string(),
+            "api_version" => self.api_version = Some(value.to_string()),
+            "input_price" => self.input_price = Some(value.parse()?),
+            "output_price" => self.output_price = Some(value.parse()?),
+            _ => return Err(format!("Unknown provider setting: {}", field).into()),
+        }
+        Ok(())
+    }
+
+    /// Checks that every value is within the range `provider` accepts.
+    pub fn validate(&self, provider: &str) -> Result<(), String> {
+        if self.model.trim().is_empty() {
+            return Err("model must not be empty".to_string());
+        }
+        if self.max_tokens == Some(0) {
+            return Err("max_tokens must be greater than 0".to_string());
+        }
+        if self.prompt_tokens == Some(0) {
+            return Err("prompt_tokens must be greater than 0".to_string());
+        }
+        if let Some(temperature) = self.temperature {
+            // Anthropic takes at most 1, the others 2
+            let max_temperature = if provider == "anthropic" { 1.0 } else { 2.0 };
+            if !(0.0..=max_temperature).contains(&temperature) {
+                return Err(format!("temperature must be between 0 and {}, got {}", max_temperature, temperature));
+            }
+        }
+        if let Some(top_p) = self.top_p {
+            if top_p <= 0.0 || top_p > 1.0 {
+                return Err(format!("top_p must be greater than 0 and at most 1, got {}", top_p));
+            }
+        }
+        for (name, price) in [("input_price", self.input_price), ("output_price", self.output_price)] {
+            if price.is_some_and(|price| price < 0.0) {
+                return Err(format!("{} must not be negative", name));
+            }
+        }
+        if !self.base_url.starts_with("http://") && !self.base_url.starts_with("https://") {
+            return Err(format!("base_url must start with http:// or https://, got {}", self.base_url));
+        }
+        Ok(())
+    }
+
+    /// What the model costs: the configured prices, falling back to its list price.
+    pub fn price(&self) -> Option<Price> {
+        let listed = usage::price(&self.model);
+        if self.input_price.is_none() && self.output_price.is_none() {
+            return listed;
+        }
+        Some(Price {
+            input: self.input_price.or(listed.map(|price| price.input)).unwrap_or_default(),
+            output: self.output_price.or(listed.map(|price| price.output)).unwrap_or_default(),
+        })
+    }
+
+    /// Everything here that shapes an answer, to tell cached answers apart.
+    pub fn fingerprint(&self) -> String {
+        format!(
+            "{} max_tokens={:?} temperature={:?} top_p={:?} system_prompt={:?} base_url={} api_version={:?}",
+            self.model, self.max_tokens, self.temperature, self.top_p, self.system_prompt, self.base_url, self.api_version
+        )
+    }
+
+    fn apply(&mut self, overrides: ProviderOverrides) {
+        if let Some(model) = overrides.model {
+            self.model = model;
+        }
+        if overrides.max_tokens.is_some() {
+            self.max_tokens = overrides.max_tokens;
+        }
+        if overrides.prompt_tokens.is_some() {
+            self.prompt_tokens = overrides.prompt_tokens;
+        }
+        if overrides.temperature.is_some() {
+            self.temperature = overrides.temperature;
+        }
+        if overrides.top_p.is_some() {
+            self.top_p = overrides.top_p;
+        }
+        if overrides.system_prompt.is_some() {
+            self.system_prompt = overrides.system_prompt;
+        }
+        if let Some(base_url) = overrides.base_url {
+            self.base_url = base_url;
+        }
+        if overrides.api_version.is_some() {
+            self.api_version = overrides.api_version;
+        }
+        if overrides.input_price.is_some() {
+            self.input_price = overrides.input_price;
+        }
+        if overrides.output_price.is_some() {
+            self.output_price = overrides.output_price;
+        }
+    }
+}
+
+/// A provider section of the config file; anything left out keeps its default.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ProviderOverrides {
+    model: Option<String>,
+    max_tokens: Option<u32>,
+    prompt_tokens: Option<usize>,
+    temperature: Option<f64>,
+    top_p: Option<f64>,
+    system_prompt: Option<String>,
+    base_url: Option<String>,
+    api_version: Option<String>,
+    input_price: Option<f64>,
+    output_price: Option<f64>,
+}
+
+/// Settings for every built-in provider and for how their answers are combined.
+/// API keys stay in the environment.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Config {
+    pub openai: ProviderConfig,
+    pub anthropic: ProviderConfig,
+    pub gemini: ProviderConfig,
+    /// Only set when a local server is configured, since it needs no API key to enable it.
+    pub local: Option<ProviderConfig>,
+    /// The provider that merges all answers into one report; the first to answer if unset.
+    pub synthesizer: Option<String>,
+    /// How many turns of tool calls an agent gets before it has to answer.
+    pub agent_steps: usize,
+    /// Whether answers are read from and saved to `./output/cache`.
+    pub cache: CacheMode,
+    /// How long a cached answer is reused, in seconds.
+    pub cache_ttl: u64,
+    /// The most a run may spend on requests, in US dollars; requests that could go over
+    /// it aren't sent.
+    pub max_cost: Option<f64>,
+    /// The directory of prompt templates, one `<focus>.md` per focus; see `templates::Template`.
+    pub templates: String,
+    /// Whether the match's plots are attached for providers that accept images.
+    pub attach_images: bool,
+    /// Who the coaching is pitched at; see `profile::Profile`.
+    pub profile: Profile,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            openai: ProviderConfig::openai(),
+            anthropic: ProviderConfig::anthropic(),
+            gemini: ProviderConfig::gemini(),
+            local: None,
+            synthesizer: None,
+            agent_steps: DEFAULT_MAX_STEPS,
+            cache: CacheMode::Use,
+            cache_ttl: DEFAULT_TTL_SECS,
+            max_cost: None,
+            templates: templates::DEFAULT_DIR.to_string(),
+            attach_images: false,
+            profile: Profile::default(),
+        }
+    }
+}
+
+impl Config {
+    /// Builds the config from, in increasing priority: the defaults, the config file
+    /// (`path`, else `RATTLEBRAIN_CONFIG`, else `./rattlebrain.json` if present), the
+    /// `LOCAL_AI_BASE_URL` / `LOCAL_AI_MODEL` variables, and `provider.field=value` overrides.
+    pub fn load(path: Option<&str>, overrides: &[&str]) -> Result<Self, Box<dyn Error>> {
+        let path = path
+            .map(str::to_string)
+            .or_else(|| env::var("RATTLEBRAIN_CONFIG").ok())
+            .or_else(|| Path::new(DEFAULT_CONFIG_FILE).exists().then(|| DEFAULT_CONFIG_FILE.to_string()));
+
+        let mut config = match path {
+            Some(path) => {
+                let content = fs::read_to_string(&path)
+                    .map_err(|e| format!("Error reading config file {}: {}", path, e))?;
+                Config::from_json(&content).map_err(|e| format!("Error in config file {}: {}", path, e))?
+            }
+            None => Config::default(),
+        };
+
+        if let Ok(base_url) = env::var("LOCAL_AI_BASE_URL") {
+            config.provider_mut("local")?.base_url = base_url;
+        }
+        if let Ok(model) = env::var("LOCAL_AI_MODEL") {
+            config.provider_mut("local")?.model = model;
+        }
+
+        for setting in overrides {
+            config.set(setting)?;
+        }
+
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Parses a config file such as
+    /// `{"synthesizer": "anthropic", "openai": {"model": "gpt-4o-mini", "temperature": 0.3}}`.
+    pub fn from_json(content: &str) -> Result<Self, Box<dyn Error>> {
+        let mut sections: HashMap<String, Value> = serde_json::from_str(content)?;
+        let mut config = Config::default();
+        if let Some(synthesizer) = sections.remove("synthesizer") {
+            config.synthesizer = Some(serde_json::from_value(synthesizer)?);
+        }
+        if let Some(agent_steps) = sections.remove("agent_steps") {
+            config.agent_steps = serde_json::from_value(agent_steps)?;
+        }
+        if let Some(cache) = sections.remove("cache") {
+            config.cache = CacheMode::parse(&serde_json::from_value::<String>(cache)?)?;
+        }
+        if let Some(cache_ttl) = sections.remove("cache_ttl") {
+            config.cache_ttl = serde_json::from_value(cache_ttl)?;
+        }
+        if let Some(max_cost) = sections.remove("max_cost") {
+            config.max_cost = Some(serde_json::from_value(max_cost)?);
+        }
+        if let Some(templates) = sections.remove("templates") {
+            config.templates = serde_json::from_value(templates)?;
+        }
+        if let Some(attach_images) = sections.remove("attach_images") {
+            config.attach_images = serde_json::from_value(attach_images)?;
+        }
+        if let Some(profile) = sections.remove("profile") {
+            config.profile = Profile::from_json(profile)?;
+        }
+        for (name, section) in sections {
+            let overrides: ProviderOverrides = serde_json::from_value(section)?;
+            config.provider_mut(&name)?.apply(overrides);
+        }
+        Ok(config)
+    }
+
+    /// Applies a `provider.field=value` override, e.g. `anthropic.max_tokens=4096`,
+    /// or a top-level `setting=value` such as `synthesizer=gemini`, `agent_steps=12`,
+    /// `cache=refresh`, `cache_ttl=86400`, `max_cost=0.50`, `templates=./team-templates` or `attach_images=true`.
+    /// `profile.rank=Diamond`, `profile.mode=2v2` and `profile.goals=improve rotations;kickoffs`
+    /// set the coaching profile.
+    pub fn set(&mut self, setting: &str) -> Result<(), Box<dyn Error>> {
+        let (key, value) = setting
+            .split_once('=')
+            .ok_or_else(|| format!("Expected provider.field=value, got {}", setting))?;
+        match key.split_once('.') {
+            Some(("profile", field)) => self.profile.set(field, value),
+            Some((name, field)) => self.provider_mut(name)?.set(field, value),
+            None if key == "synthesizer" => {
+                self.synthesizer = Some(value.to_string());
+                Ok(())
+            }
+            None if key == "agent_steps" => {
+                self.agent_steps = value.parse()?;
+                Ok(())
+            }
+            None if key == "cache" => {
+                self.cache = CacheMode::parse(value)?;
+                Ok(())
+            }
+            None if key == "cache_ttl" => {
+                self.cache_ttl = value.parse()?;
+                Ok(())
+            }
+            None if key == "max_cost" => {
+                self.max_cost = Some(value.parse()?);
+                Ok(())
+            }
+            None if key == "templates" => {
+                self.templates = value.to_string();
+                Ok(())
+            }
+            None if key == "attach_images" => {
+                self.attach_images = value.parse()?;
+                Ok(())
+            }
+            None => Err(format!("Unknown setting: {}", key).into()),
+        }
+    }
+
+    /// Validates every provider, naming the first one that is misconfigured.
+    pub fn validate(&self) -> Result<(), Box<dyn Error>> {
+        if self.agent_steps == 0 {
+            return Err("agent_steps must be greater than 0".into());
+        }
+        if self.max_cost.is_some_and(|max_cost| max_cost <= 0.0) {
+            return Err("max_cost must be greater than 0".into());
+        }
+        let providers = [
+            ("openai", Some(&self.openai)),
+            ("anthropic", Some(&self.anthropic)),
+            ("gemini", Some(&self.gemini)),
+            ("local", self.local.as_ref()),
+        ];
+        for (name, provider) in providers {
+            if let Some(provider) = provider {
+                provider.validate(name).map_err(|e| format!("Invalid {} config: {}", name, e))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn provider_mut(&mut self, name: &str) -> Result<&mut ProviderConfig, Box<dyn Error>> {
+        match name.to_lowercase().as_str() {
+            "openai" => Ok(&mut self.openai),
+            "anthropic" => Ok(&mut self.anthropic),
+            "gemini" => Ok(&mut self.gemini),
+            "local" => Ok(self.local.get_or_insert_with(ProviderConfig::local)),
+            _ => Err(format!("Unknown AI provider in config: {}", name).into()),
+        }
+    }
+}
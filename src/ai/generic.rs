@@ -1,3 +0,0 @@
-pub trait AIInterface {
-    fn query(&self, input: &str) -> Result<String, String>;
-}
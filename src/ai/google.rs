@@ -1,94 +1,357 @@
+use crate::ai::config::ProviderConfig;
+use crate::ai::http::{self, AiError};
+use crate::ai::image::{self, Image};
+use crate::ai::provider::{Capabilities, OnText, Provider, ProviderError};
+use crate::ai::registry::mask_key;
+use crate::ai::tokens;
+use crate::ai::tools::{Message, Reply, Tool, ToolCall};
+use crate::ai::usage::{Meter, Usage};
+use async_trait::async_trait;
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
 use std::env;
-use std::error::Error;
+use std::sync::Arc;
+
+/// Gemini's tokenizer packs about four characters into a token.
+const CHARS_PER_TOKEN: f64 = 4.0;
 
 #[derive(Debug, Serialize)]
 struct GeminiRequest {
-    contents: Vec<Content>,
+    contents: Vec<Value>,
+    #[serde(rename = "systemInstruction", skip_serializing_if = "Option::is_none")]
+    system_instruction: Option<Value>,
+    #[serde(rename = "generationConfig")]
+    generation_config: GenerationConfig,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tools: Vec<Value>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct GenerationConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_output_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response_mime_type: Option<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize)] // Added Deserialize for request and response
+#[derive(Debug, Deserialize)]
 struct Content {
+    #[serde(default)]
     parts: Vec<Part>,
 }
 
-#[derive(Debug, Serialize, Deserialize)] // Added Deserialize for request and response
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
 struct Part {
-    text: String,
+    text: Option<String>,
+    function_call: Option<FunctionCall>,
 }
 
 #[derive(Debug, Deserialize)]
+struct FunctionCall {
+    name: String,
+    #[serde(default)]
+    args: Value,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
 struct GeminiResponse {
+    #[serde(default)]
     candidates: Vec<Candidate>,
+    prompt_feedback: Option<PromptFeedback>,
+    usage_metadata: Option<UsageMetadata>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct UsageMetadata {
+    #[serde(default)]
+    prompt_token_count: u64,
+    #[serde(default)]
+    candidates_token_count: u64,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PromptFeedback {
+    block_reason: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
 struct Candidate {
-    content: Content,
+    content: Option<Content>,
+    finish_reason: Option<String>,
 }
 
-/// Sends a query to Gemini AI and returns the response.
-///
-/// # Arguments
-/// * `prompt` - The prompt string to send to Gemini.
-///
-/// # Returns
-/// A `Result` containing the response string, or an error if the request fails.
-pub async fn query_gemini(prompt: &str) -> Result<String, Box<dyn Error>> {
-    // Retrieve the API key from the environment
-    let api_key = env::var("GEMINI_API_KEY").expect("GEMINI_API_KEY must be set");
-
-    // API URL
-    let base_url = format!(
-        "https://generativelanguage.googleapis.com/v1beta/models/gemini-1.5-flash-latest:generateContent?key={}",
-        api_key
-    );
-
-    // Prepare the request body
-    let request_body = GeminiRequest {
-        contents: vec![Content {
-            parts: vec![Part {
-                text: prompt.to_string(),
-            }],
-        }],
-    };
-
-    // Initialize HTTP client
-    let client = Client::new();
-
-    // Send the request
-    let response = client
-        .post(&base_url)
-        .header("Content-Type", "application/json")
-        .json(&request_body)
-        .send()
-        .await?;
+pub struct Gemini {
+    api_key: String,
+    config: ProviderConfig,
+    client: Client,
+    meter: Arc<Meter>,
+}
+
+impl Gemini {
+    pub fn with_config(api_key: &str, mut config: ProviderConfig) -> Self {
+        config.base_url = config.base_url.trim_end_matches('/').to_string();
+        Gemini {
+            api_key: api_key.to_string(),
+            config,
+            client: http::client(),
+            meter: Arc::default(),
+        }
+    }
 
-    // Extract status and response text for debugging
-    let status = response.status();
-    let response_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
+    /// Counts this provider's usage and cost in `meter`, shared with the rest of the run.
+    pub fn with_meter(mut self, meter: Arc<Meter>) -> Self {
+        self.meter = meter;
+        self
+    }
+
+    /// Configured from `GEMINI_API_KEY`, if set.
+    pub fn from_env(config: &ProviderConfig) -> Option<Self> {
+        let api_key = env::var("GEMINI_API_KEY").ok()?;
+        println!("Using Google {} with key: {}", config.model, mask_key(&api_key));
+        Some(Gemini::with_config(&api_key, config.clone()))
+    }
 
-    if status.is_success() {
-        // eprintln!("Successful response: {}", response_text); // Debugging raw response
-        // Parse the response JSON
-        let response_body: GeminiResponse = serde_json::from_str(&response_text)?;
+    /// Generates an answer looking at `images`, in the given `response_mime_type` if set.
+    async fn generate(
+        &self,
+        prompt: &str,
+        images: &[Image],
+        response_mime_type: Option<&str>,
+    ) -> Result<String, ProviderError> {
+        let contents = vec![json!({ "role": "user", "parts": image::gemini_parts(prompt, images) })];
+        let parts = self.request(contents, images, Vec::new(), response_mime_type).await?;
+        Ok(parts.into_iter().filter_map(|part| part.text).collect::<Vec<String>>().join("\n"))
+    }
 
-        // Extract and concatenate text from the candidate's content parts
-        let output = response_body
+    /// The request body for the conversation in `contents`.
+    fn body(&self, contents: Vec<Value>, tools: Vec<Value>, response_mime_type: Option<&str>) -> GeminiRequest {
+        GeminiRequest {
+            contents,
+            system_instruction: self
+                .config
+                .system_prompt
+                .as_ref()
+                .map(|text| json!({ "parts": [{ "text": text }] })),
+            generation_config: GenerationConfig {
+                max_output_tokens: self.meter.max_tokens(&self.config),
+                temperature: self.config.temperature,
+                top_p: self.config.top_p,
+                response_mime_type: response_mime_type.map(str::to_string),
+            },
+            tools,
+        }
+    }
+
+    /// Sends the conversation in `contents`, which carries `images`, and returns the parts of
+    /// every candidate.
+    async fn request(
+        &self,
+        contents: Vec<Value>,
+        images: &[Image],
+        tools: Vec<Value>,
+        response_mime_type: Option<&str>,
+    ) -> Result<Vec<Part>, ProviderError> {
+        // API URL
+        let base_url = format!("{}/models/{}:generateContent", self.config.base_url, self.config.model);
+        let request_body = self.body(contents, tools, response_mime_type);
+
+        // The key goes in a header: a URL can end up in error messages
+        let request = self.client.post(&base_url).header("x-goog-api-key", &self.api_key);
+        let body = serde_json::to_string(&request_body)?;
+        let prompt_tokens = image::request_tokens(|text| self.estimate_tokens(text), &body, images);
+        let reservation = self.meter.reserve(self.name(), &self.config, prompt_tokens)?;
+        let response_text = http::send_json(request, &request_body).await?;
+
+        let response_body: GeminiResponse = http::parse_json(&response_text)?;
+        reservation.record(response_body.usage());
+        response_body.check()?;
+        if response_body.candidates.is_empty() {
+            return Err(AiError::Malformed("No candidates in Gemini response".to_string()).into());
+        }
+
+        Ok(response_body
             .candidates
             .into_iter()
-            .flat_map(|candidate| candidate.content.parts)
-            .map(|part| part.text)
-            .collect::<Vec<String>>()
-            .join("\n");
-
-        Ok(output)
-    } else {
-        eprintln!("Failed response: {}", response_text); // Debugging raw error response
-        Err(Box::new(std::io::Error::new(
-            std::io::ErrorKind::Other,
-            format!("Request failed with status: {} - {}", status, response_text),
-        )))
+            .filter_map(|candidate| candidate.content)
+            .flat_map(|content| content.parts)
+            .collect())
+    }
+}
+
+impl GeminiResponse {
+    fn usage(&self) -> Usage {
+        self.usage_metadata
+            .as_ref()
+            .map(|usage| Usage {
+                input_tokens: usage.prompt_token_count,
+                output_tokens: usage.candidates_token_count,
+            })
+            .unwrap_or_default()
+    }
+
+    /// Fails if Gemini blocked the prompt or stopped the answer on safety grounds.
+    fn check(&self) -> Result<(), AiError> {
+        if let Some(reason) = self.prompt_feedback.as_ref().and_then(|feedback| feedback.block_reason.as_ref()) {
+            return Err(AiError::ContentFilter(format!("Gemini blocked the prompt: {}", reason)));
+        }
+        if let Some(reason) = self
+            .candidates
+            .iter()
+            .find_map(|candidate| candidate.finish_reason.as_deref().filter(|r| *r == "SAFETY"))
+        {
+            return Err(AiError::ContentFilter(format!("Gemini stopped the answer: {}", reason)));
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Provider for Gemini {
+    fn name(&self) -> &str {
+        "Gemini"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            system_prompt: true,
+            images: true,
+            tools: true,
+            structured_output: true,
+            streaming: true,
+        }
+    }
+
+    fn estimate_tokens(&self, text: &str) -> usize {
+        tokens::estimate(text, CHARS_PER_TOKEN)
+    }
+
+    fn prompt_budget(&self) -> usize {
+        tokens::prompt_budget(&self.config.model, self.config.prompt_tokens, self.config.max_tokens)
+    }
+
+    fn settings(&self) -> String {
+        self.config.fingerprint()
+    }
+
+    async fn stream(&self, prompt: &str, images: &[Image], on_text: &OnText<'_>) -> Result<String, ProviderError> {
+        let base_url = format!("{}/models/{}:streamGenerateContent?alt=sse", self.config.base_url, self.config.model);
+        let contents = vec![json!({ "role": "user", "parts": image::gemini_parts(prompt, images) })];
+        let request_body = self.body(contents, Vec::new(), None);
+
+        let request = self.client.post(&base_url).header("x-goog-api-key", &self.api_key);
+        let body = serde_json::to_string(&request_body)?;
+        let prompt_tokens = image::request_tokens(|text| self.estimate_tokens(text), &body, images);
+        let reservation = self.meter.reserve(self.name(), &self.config, prompt_tokens)?;
+        let mut answer = String::new();
+        let mut usage = Usage::default();
+        // Each event is a response of its own, with the next piece of the answer
+        http::stream_json(request, &request_body, |event| {
+            let chunk: GeminiResponse = http::parse_json(event)?;
+            chunk.check()?;
+            if chunk.usage_metadata.is_some() {
+                usage = chunk.usage();
+            }
+            let parts = chunk.candidates.into_iter().filter_map(|c| c.content).flat_map(|c| c.parts);
+            for text in parts.filter_map(|part| part.text) {
+                on_text(&text);
+                answer.push_str(&text);
+            }
+            Ok(())
+        })
+        .await?;
+        reservation.record(usage);
+
+        if answer.is_empty() {
+            return Err(AiError::Malformed("No text in Gemini's answer".to_string()).into());
+        }
+        Ok(answer)
+    }
+
+    /// Sends a query to Gemini AI and returns the response.
+    async fn query(&self, prompt: &str) -> Result<String, ProviderError> {
+        self.generate(prompt, &[], None).await
+    }
+
+    async fn query_with_images(&self, prompt: &str, images: &[Image]) -> Result<String, ProviderError> {
+        self.generate(prompt, images, None).await
+    }
+
+    /// Gemini's `responseSchema` only takes a subset of JSON Schema (no
+    /// `additionalProperties`), so the schema goes in the prompt and JSON mode
+    /// keeps the answer parseable.
+    async fn query_structured(&self, prompt: &str, schema: &Value) -> Result<String, ProviderError> {
+        let prompt = format!(
+            "{}\n\nRespond with a JSON object matching this JSON Schema:\n{}",
+            prompt, schema
+        );
+        self.generate(&prompt, &[], Some("application/json")).await
+    }
+
+    /// Gemini has no call ids; calls and their results are matched by name and order.
+    async fn chat(&self, messages: &[Message], tools: &[Tool]) -> Result<Reply, ProviderError> {
+        let mut contents: Vec<Value> = Vec::new();
+        for message in messages {
+            let (role, part) = match message {
+                Message::User(text) => ("user", vec![json!({ "text": text })]),
+                Message::Assistant { text, tool_calls } => {
+                    let mut parts = Vec::new();
+                    if !text.is_empty() {
+                        parts.push(json!({ "text": text }));
+                    }
+                    for call in tool_calls {
+                        parts.push(json!({ "functionCall": { "name": call.name, "args": call.arguments } }));
+                    }
+                    ("model", parts)
+                }
+                Message::ToolResult { name, content, .. } => (
+                    "user",
+                    vec![json!({ "functionResponse": { "name": name, "response": { "content": content } } })],
+                ),
+            };
+            // Consecutive turns from the same side are merged, as Gemini expects turns to alternate
+            match contents.last_mut() {
+                Some(last) if last["role"] == role => {
+                    if let Some(parts) = last["parts"].as_array_mut() {
+                        parts.extend(part);
+                    }
+                }
+                _ => contents.push(json!({ "role": role, "parts": part })),
+            }
+        }
+        let tools = if tools.is_empty() {
+            Vec::new()
+        } else {
+            let declarations: Vec<Value> = tools
+                .iter()
+                .map(|tool| json!({ "name": tool.name, "description": tool.description, "parameters": tool.parameters }))
+                .collect();
+            vec![json!({ "functionDeclarations": declarations })]
+        };
+
+        let mut reply = Reply::default();
+        for part in self.request(contents, &[], tools, None).await? {
+            if let Some(text) = part.text {
+                reply.text.push_str(&text);
+            }
+            if let Some(call) = part.function_call {
+                reply.tool_calls.push(ToolCall {
+                    id: call.name.clone(),
+                    name: call.name,
+                    arguments: call.args,
+                });
+            }
+        }
+        Ok(reply)
     }
 }
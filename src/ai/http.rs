@@ -0,0 +1,237 @@
+use crate::ai::provider::ProviderError;
+use reqwest::header::RETRY_AFTER;
+use reqwest::{Client, RequestBuilder, Response, StatusCode};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::env;
+use std::fmt;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+/// Seconds to wait for a whole request, including the model's answer, unless
+/// `RATTLEBRAIN_HTTP_TIMEOUT` says otherwise. Long prompts can take minutes.
+const DEFAULT_TIMEOUT_SECS: u64 = 300;
+const CONNECT_TIMEOUT_SECS: u64 = 10;
+
+/// Why an AI request failed, so callers can tell a bad key from a flaky network.
+#[derive(Debug)]
+pub enum AiError {
+    /// The API key was missing, wrong or not allowed to use the model (401/403).
+    Auth(String),
+    /// The account is out of credits or over its plan's quota; retrying won't help.
+    Quota(String),
+    /// Still rate limited (429) after every retry.
+    RateLimited(String),
+    /// The provider refused the prompt or the answer on safety grounds.
+    ContentFilter(String),
+    /// The response didn't have the shape the provider's API documents.
+    Malformed(String),
+    /// The request took longer than the timeout.
+    Timeout,
+    /// Any other non-2xx response, after retries for 5xx.
+    Status(u16, String),
+    /// The connection failed before a response arrived.
+    Network(String),
+}
+
+impl fmt::Display for AiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AiError::Auth(body) => write!(f, "Authentication failed: {}", body),
+            AiError::Quota(body) => write!(f, "Quota exhausted: {}", body),
+            AiError::RateLimited(body) => write!(f, "Rate limited: {}", body),
+            AiError::ContentFilter(reason) => write!(f, "Blocked by content filter: {}", reason),
+            AiError::Malformed(reason) => write!(f, "Malformed response: {}", reason),
+            AiError::Timeout => write!(f, "Request timed out"),
+            AiError::Status(status, body) => write!(f, "Request failed with status: {} - {}", status, body),
+            AiError::Network(reason) => write!(f, "Network error: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for AiError {}
+
+impl From<reqwest::Error> for AiError {
+    fn from(error: reqwest::Error) -> Self {
+        if error.is_timeout() {
+            AiError::Timeout
+        } else {
+            // Without the URL, which may carry an API key in its query
+            AiError::Network(error.without_url().to_string())
+        }
+    }
+}
+
+/// How often and how long to wait before retrying a 429 or 5xx response.
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_retries: 3,
+            base_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(30),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Exponential backoff: `base_delay * 2^attempt`, capped at `max_delay`.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        self.base_delay
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.max_delay)
+    }
+}
+
+/// The HTTP client shared by every provider, so connections are pooled across requests.
+pub fn client() -> Client {
+    static CLIENT: OnceLock<Client> = OnceLock::new();
+    CLIENT
+        .get_or_init(|| {
+            let timeout = env::var("RATTLEBRAIN_HTTP_TIMEOUT")
+                .ok()
+                .and_then(|secs| secs.parse().ok())
+                .unwrap_or(DEFAULT_TIMEOUT_SECS);
+            Client::builder()
+                .connect_timeout(Duration::from_secs(CONNECT_TIMEOUT_SECS))
+                .timeout(Duration::from_secs(timeout))
+                .build()
+                .unwrap_or_default()
+        })
+        .clone()
+}
+
+/// Sends a JSON request with the default retry policy and returns the response body.
+pub(crate) async fn send_json<T: Serialize>(request: RequestBuilder, body: &T) -> Result<String, ProviderError> {
+    send_json_with(request, body, &RetryPolicy::default()).await
+}
+
+/// Sends a JSON request, retrying 429 and 5xx responses with exponential backoff
+/// (or as long as `Retry-After` asks), and returns the response body.
+pub(crate) async fn send_json_with<T: Serialize>(
+    request: RequestBuilder,
+    body: &T,
+    policy: &RetryPolicy,
+) -> Result<String, ProviderError> {
+    let response = send_with_retries(request, body, policy).await?;
+    Ok(response.text().await.map_err(AiError::from)?)
+}
+
+/// Sends a JSON request like `send_json` and hands the `data` of each server-sent event to
+/// `on_event` as it arrives, until the stream ends or sends `[DONE]`.
+pub(crate) async fn stream_json<T: Serialize>(
+    request: RequestBuilder,
+    body: &T,
+    mut on_event: impl FnMut(&str) -> Result<(), ProviderError> + Send,
+) -> Result<(), ProviderError> {
+    let mut response = send_with_retries(request, body, &RetryPolicy::default()).await?;
+
+    let mut pending: Vec<u8> = Vec::new();
+    let mut data: Vec<String> = Vec::new();
+    loop {
+        let chunk = response.chunk().await.map_err(AiError::from)?;
+        let finished = chunk.is_none();
+        match chunk {
+            Some(chunk) => pending.extend_from_slice(&chunk),
+            // A last event may end without a blank line
+            None => pending.extend_from_slice(b"\n\n"),
+        }
+
+        while let Some(end) = pending.iter().position(|&byte| byte == b'\n') {
+            let line: Vec<u8> = pending.drain(..=end).collect();
+            let line = String::from_utf8_lossy(&line);
+            let line = line.trim_end_matches(['\r', '\n']);
+            if let Some(value) = line.strip_prefix("data:") {
+                data.push(value.strip_prefix(' ').unwrap_or(value).to_string());
+            } else if line.is_empty() && !data.is_empty() {
+                let event = data.join("\n");
+                data.clear();
+                if event == "[DONE]" {
+                    return Ok(());
+                }
+                on_event(&event)?;
+            }
+        }
+        if finished {
+            return Ok(());
+        }
+    }
+}
+
+/// Sends the request until it succeeds or fails for good, see `send_json_with`.
+async fn send_with_retries<T: Serialize>(
+    request: RequestBuilder,
+    body: &T,
+    policy: &RetryPolicy,
+) -> Result<Response, ProviderError> {
+    let request = request.header("content-type", "application/json").json(body);
+
+    let mut attempt = 0;
+    loop {
+        let attempt_request = request
+            .try_clone()
+            .ok_or_else(|| AiError::Network("request body cannot be retried".to_string()))?;
+        let response = attempt_request.send().await.map_err(AiError::from)?;
+
+        let status = response.status();
+        if status.is_success() {
+            return Ok(response);
+        }
+
+        let retry_after = retry_after(&response);
+        let response_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
+        let error = classify(status, response_text);
+
+        let retryable = matches!(error, AiError::RateLimited(_))
+            || matches!(error, AiError::Status(code, _) if code >= 500);
+        if !retryable || attempt >= policy.max_retries {
+            return Err(error.into());
+        }
+
+        let delay = retry_after.unwrap_or_else(|| policy.delay(attempt)).min(policy.max_delay);
+        eprintln!("{} - retrying in {:.1}s", error, delay.as_secs_f64());
+        tokio::time::sleep(delay).await;
+        attempt += 1;
+    }
+}
+
+/// Parses a successful response body, reporting a mismatch as `AiError::Malformed`.
+pub(crate) fn parse_json<T: DeserializeOwned>(response_text: &str) -> Result<T, AiError> {
+    serde_json::from_str(response_text).map_err(|e| AiError::Malformed(e.to_string()))
+}
+
+fn classify(status: StatusCode, body: String) -> AiError {
+    let lowercase = body.to_lowercase();
+    match status.as_u16() {
+        401 | 403 => AiError::Auth(body),
+        429 if lowercase.contains("insufficient_quota") || lowercase.contains("billing") => AiError::Quota(body),
+        429 => AiError::RateLimited(body),
+        400 if lowercase.contains("content_filter")
+            || lowercase.contains("content_policy")
+            || lowercase.contains("safety") =>
+        {
+            AiError::ContentFilter(body)
+        }
+        code => AiError::Status(code, body),
+    }
+}
+
+/// `Retry-After` in seconds; the HTTP-date form isn't used by the AI providers.
+fn retry_after(response: &Response) -> Option<Duration> {
+    response
+        .headers()
+        .get(RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse::<f64>()
+        .ok()
+        .filter(|secs| secs.is_finite() && *secs >= 0.0)
+        .map(Duration::from_secs_f64)
+}
@@ -0,0 +1,137 @@
+use crate::plot;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use serde_json::{json, Value};
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+/// The most plots attached to one request.
+pub const MAX_IMAGES: usize = 8;
+/// Images larger than this are left out; Anthropic takes at most 5 MB per image.
+pub const MAX_IMAGE_BYTES: usize = 5 * 1024 * 1024;
+/// Roughly what one plot costs in prompt tokens. Providers scale images to their own
+/// tile sizes, so this is an estimate for the spending cap rather than a count.
+pub const IMAGE_TOKENS: usize = 1600;
+
+/// An image attached to a prompt.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Image {
+    /// What the image shows, e.g. `heatmap` or `ball side`.
+    pub label: String,
+    pub media_type: String,
+    /// The image file, base64 encoded.
+    pub data: String,
+}
+
+impl Image {
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let media_type = match path.extension().and_then(|extension| extension.to_str()) {
+            Some("png") => "image/png",
+            Some("jpg") | Some("jpeg") => "image/jpeg",
+            Some("gif") => "image/gif",
+            _ => return Err(format!("{} is not a PNG, JPEG or GIF image", path.display()).into()),
+        };
+        let bytes = fs::read(path)?;
+        if bytes.len() > MAX_IMAGE_BYTES {
+            return Err(format!("{} is larger than {} bytes", path.display(), MAX_IMAGE_BYTES).into());
+        }
+        let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+        Ok(Image {
+            label: plot::label(&file_name),
+            media_type: media_type.to_string(),
+            data: STANDARD.encode(bytes),
+        })
+    }
+
+    /// A `data:` URL, as OpenAI takes images.
+    pub fn data_url(&self) -> String {
+        format!("data:{};base64,{}", self.media_type, self.data)
+    }
+}
+
+/// The plots of a match (see `plot::plot_csv_with_options` and `plot::plot_event_maps`),
+/// at most `MAX_IMAGES` of them, leaving out the snapshots and clips of cited moments.
+/// Plots that can't be read are reported and skipped.
+pub fn plots(match_guid: &str) -> Vec<Image> {
+    let mut paths: Vec<_> = match glob::glob(&format!("./output/{}*.png", match_guid)) {
+        Ok(paths) => paths
+            .filter_map(Result::ok)
+            .filter(|path| !path.to_string_lossy().contains(".evidence_"))
+            .collect(),
+        Err(e) => {
+            eprintln!("Error finding plots: {}", e);
+            Vec::new()
+        }
+    };
+    paths.sort();
+    if paths.len() > MAX_IMAGES {
+        println!("Attaching the first {} of {} plots", MAX_IMAGES, paths.len());
+        paths.truncate(MAX_IMAGES);
+    }
+    paths
+        .iter()
+        .filter_map(|path| Image::load(path).inspect_err(|e| eprintln!("Skipping plot: {}", e)).ok())
+        .collect()
+}
+
+/// The line telling the model what it's looking at, or nothing without images.
+pub fn caption(images: &[Image]) -> Option<String> {
+    if images.is_empty() {
+        return None;
+    }
+    let labels: Vec<&str> = images.iter().map(|image| image.label.as_str()).collect();
+    Some(format!(
+        "The attached images are plots of this match, in order: {}. Refer to what they show where it supports your points.",
+        labels.join(", ")
+    ))
+}
+
+/// Prompt tokens for a request whose JSON `body` carries `images`, counting each image as
+/// `IMAGE_TOKENS` rather than by the length of its data.
+pub fn request_tokens(estimate: impl Fn(&str) -> usize, body: &str, images: &[Image]) -> usize {
+    if images.is_empty() {
+        return estimate(body);
+    }
+    let text = images.iter().fold(body.to_string(), |text, image| text.replace(&image.data, ""));
+    estimate(&text) + images.len() * IMAGE_TOKENS
+}
+
+/// The content of an OpenAI user message: the prompt alone, or text and image parts.
+pub fn openai_content(prompt: &str, images: &[Image]) -> Value {
+    if images.is_empty() {
+        return json!(prompt);
+    }
+    let mut parts = vec![json!({ "type": "text", "text": prompt })];
+    parts.extend(images.iter().map(|image| json!({ "type": "image_url", "image_url": { "url": image.data_url() } })));
+    Value::Array(parts)
+}
+
+/// The content of an Anthropic user message, images first as Anthropic recommends.
+pub fn anthropic_content(prompt: &str, images: &[Image]) -> Value {
+    if images.is_empty() {
+        return json!(prompt);
+    }
+    let mut parts: Vec<Value> = images
+        .iter()
+        .map(|image| {
+            json!({
+                "type": "image",
+                "source": { "type": "base64", "media_type": image.media_type, "data": image.data },
+            })
+        })
+        .collect();
+    parts.push(json!({ "type": "text", "text": prompt }));
+    Value::Array(parts)
+}
+
+/// The parts of a Gemini user turn.
+pub fn gemini_parts(prompt: &str, images: &[Image]) -> Value {
+    let mut parts = vec![json!({ "text": prompt })];
+    parts.extend(
+        images
+            .iter()
+            .map(|image| json!({ "inline_data": { "mime_type": image.media_type, "data": image.data } })),
+    );
+    Value::Array(parts)
+}
@@ -1,3 +1,12 @@
 pub mod openai;
 pub mod anthropic;
-pub mod google;
\ No newline at end of file
+pub mod google;
+pub mod cache;
+pub mod config;
+pub mod http;
+pub mod image;
+pub mod provider;
+pub mod registry;
+pub mod tokens;
+pub mod tools;
+pub mod usage;
@@ -1,75 +1,366 @@
-use std::env;
-use reqwest::Client;
+use crate::ai::config::ProviderConfig;
+use crate::ai::http::{self, AiError};
+use crate::ai::image::{self, Image};
+use crate::ai::provider::{self, Capabilities, OnText, Provider, ProviderError};
+use crate::ai::registry::mask_key;
+use crate::ai::tokens;
+use crate::ai::tools::{Message, Reply, Tool, ToolCall};
+use crate::ai::usage::{Meter, Usage};
+use async_trait::async_trait;
+use reqwest::{Client, RequestBuilder};
 use serde::{Deserialize, Serialize};
-use std::error::Error;
+use serde_json::{json, Value};
+use std::env;
+use std::sync::Arc;
 
 #[derive(Serialize)]
 struct OpenAIRequest {
     model: String,
-    messages: Vec<Message>,
-}
-
-#[derive(Serialize)]
-struct Message {
-    role: String,
-    content: String,
+    messages: Vec<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response_format: Option<Value>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tools: Vec<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stream: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stream_options: Option<Value>,
 }
 
 #[derive(Deserialize)]
 struct OpenAIResponse {
     choices: Vec<Choice>,
+    usage: Option<TokenUsage>,
+}
+
+/// One server-sent event of a streamed completion. With `include_usage`, the last one
+/// has no choices and the usage of the whole request.
+#[derive(Deserialize)]
+struct StreamChunk {
+    #[serde(default)]
+    choices: Vec<StreamChoice>,
+    usage: Option<TokenUsage>,
+}
+
+#[derive(Deserialize)]
+struct StreamChoice {
+    delta: Delta,
+    finish_reason: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Delta {
+    content: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct TokenUsage {
+    prompt_tokens: u64,
+    completion_tokens: u64,
 }
 
 #[derive(Deserialize)]
 struct Choice {
     message: MessageResponse,
+    finish_reason: Option<String>,
 }
 
 #[derive(Deserialize)]
 struct MessageResponse {
-    content: String,
+    content: Option<String>,
+    #[serde(default)]
+    tool_calls: Vec<ToolCallResponse>,
 }
 
-pub async fn query_openai(prompt: &str) -> Result<String, Box<dyn Error>> {
-    // Get the OpenAI API key from the environment
-    let api_key = env::var("OPENAI_API_KEY").expect("OPENAI_API_KEY must be set");
-    let api_url = "https://api.openai.com/v1/chat/completions";
-
-    // Create the OpenAI request payload
-    let request_body = OpenAIRequest {
-        model: "gpt-4o".to_string(),
-        messages: vec![
-            Message {
-                role: "system".to_string(),
-                content: "You are a world-class Rocket League coach.".to_string(),
-            },
-            Message {
-                role: "user".to_string(),
-                content: prompt.to_string(),
+#[derive(Deserialize)]
+struct ToolCallResponse {
+    id: String,
+    function: FunctionCall,
+}
+
+/// `arguments` is JSON encoded as a string.
+#[derive(Deserialize)]
+struct FunctionCall {
+    name: String,
+    arguments: String,
+}
+
+/// What a local server can be relied on for: most implement plain and streamed chat
+/// completions, but few take images, tools or `json_schema` answers.
+const LOCAL_CAPABILITIES: Capabilities = Capabilities {
+    system_prompt: true,
+    images: false,
+    tools: false,
+    structured_output: false,
+    streaming: true,
+};
+
+/// Talks to the OpenAI chat completions API, or to any server that implements it
+/// (Ollama, llama.cpp, vLLM, ...).
+pub struct OpenAI {
+    name: String,
+    api_key: Option<String>,
+    config: ProviderConfig,
+    capabilities: Capabilities,
+    client: Client,
+    meter: Arc<Meter>,
+}
+
+impl OpenAI {
+    /// `name` is how the provider is listed and selected, e.g. `OpenAI` or `Local`.
+    pub fn with_config(name: &str, api_key: Option<&str>, mut config: ProviderConfig) -> Self {
+        config.base_url = config.base_url.trim_end_matches('/').to_string();
+        OpenAI {
+            name: name.to_string(),
+            api_key: api_key.map(str::to_string),
+            config,
+            capabilities: Capabilities {
+                system_prompt: true,
+                images: true,
+                tools: true,
+                structured_output: true,
+                streaming: true,
             },
-        ],
-    };
-
-    // Send the request to OpenAI
-    let client = Client::new();
-    let response = client
-        .post(api_url)
-        .header("Authorization", format!("Bearer {}", api_key))
-        .json(&request_body)
-        .send()
+            client: http::client(),
+            meter: Arc::default(),
+        }
+    }
+
+    /// Counts this provider's usage and cost in `meter`, shared with the rest of the run.
+    pub fn with_meter(mut self, meter: Arc<Meter>) -> Self {
+        self.meter = meter;
+        self
+    }
+
+    /// Configured from `OPENAI_API_KEY`, if set.
+    pub fn from_env(config: &ProviderConfig) -> Option<Self> {
+        let api_key = env::var("OPENAI_API_KEY").ok()?;
+        println!("Using OpenAI {} with key: {}", config.model, mask_key(&api_key));
+        Some(OpenAI::with_config("OpenAI", Some(&api_key), config.clone()))
+    }
+
+    /// A local server, if one is configured (see `Config::load`), with an optional
+    /// `LOCAL_AI_API_KEY`. It is only trusted with what every such server supports, see
+    /// `LOCAL_CAPABILITIES`, so plots, tools and schemas go to the other providers.
+    pub fn local_from_env(config: Option<&ProviderConfig>) -> Option<Self> {
+        let config = config?;
+        let api_key = env::var("LOCAL_AI_API_KEY").ok();
+        println!("Using local model {} at {}", config.model, config.base_url);
+        Some(OpenAI {
+            capabilities: LOCAL_CAPABILITIES,
+            ..OpenAI::with_config("Local", api_key.as_deref(), config.clone())
+        })
+    }
+
+    /// Sends a chat completion with `images` attached, optionally constraining the answer
+    /// with a `response_format`.
+    async fn complete(
+        &self,
+        prompt: &str,
+        images: &[Image],
+        response_format: Option<Value>,
+    ) -> Result<String, ProviderError> {
+        let messages = vec![json!({ "role": "user", "content": image::openai_content(prompt, images) })];
+        self.send(messages, images, response_format, Vec::new())
+            .await?
+            .content
+            .ok_or_else(|| AiError::Malformed(format!("No response content from {}.", self.name)).into())
+    }
+
+    /// The request for `messages` after the system prompt.
+    fn prepare(
+        &self,
+        messages: Vec<Value>,
+        response_format: Option<Value>,
+        tools: Vec<Value>,
+    ) -> (RequestBuilder, OpenAIRequest) {
+        let api_url = format!("{}/chat/completions", self.config.base_url);
+
+        // Create the OpenAI request payload
+        let mut all_messages = Vec::new();
+        if let Some(system_prompt) = &self.config.system_prompt {
+            all_messages.push(json!({ "role": "system", "content": system_prompt }));
+        }
+        all_messages.extend(messages);
+        let request_body = OpenAIRequest {
+            model: self.config.model.clone(),
+            messages: all_messages,
+            max_tokens: self.meter.max_tokens(&self.config),
+            temperature: self.config.temperature,
+            top_p: self.config.top_p,
+            response_format,
+            tools,
+            stream: None,
+            stream_options: None,
+        };
+
+        let mut request = self.client.post(&api_url);
+        if let Some(api_key) = &self.api_key {
+            request = request.header("Authorization", format!("Bearer {}", api_key));
+        }
+        (request, request_body)
+    }
+
+    /// Sends `messages`, carrying `images`, after the system prompt and returns the first choice.
+    async fn send(
+        &self,
+        messages: Vec<Value>,
+        images: &[Image],
+        response_format: Option<Value>,
+        tools: Vec<Value>,
+    ) -> Result<MessageResponse, ProviderError> {
+        let (request, request_body) = self.prepare(messages, response_format, tools);
+        let body = serde_json::to_string(&request_body)?;
+        let prompt_tokens = image::request_tokens(|text| self.estimate_tokens(text), &body, images);
+        let reservation = self.meter.reserve(&self.name, &self.config, prompt_tokens)?;
+        let response_text = http::send_json(request, &request_body).await?;
+
+        let openai_response: OpenAIResponse = http::parse_json(&response_text)?;
+        reservation.record(
+            openai_response
+                .usage
+                .map(|usage| Usage {
+                    input_tokens: usage.prompt_tokens,
+                    output_tokens: usage.completion_tokens,
+                })
+                .unwrap_or_default(),
+        );
+        let choice = openai_response
+            .choices
+            .into_iter()
+            .next()
+            .ok_or_else(|| AiError::Malformed(format!("No response content from {}.", self.name)))?;
+        if choice.finish_reason.as_deref() == Some("content_filter") {
+            return Err(AiError::ContentFilter(format!("{} filtered the answer", self.name)).into());
+        }
+        Ok(choice.message)
+    }
+}
+
+#[async_trait]
+impl Provider for OpenAI {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        self.capabilities
+    }
+
+    fn prompt_budget(&self) -> usize {
+        tokens::prompt_budget(&self.config.model, self.config.prompt_tokens, self.config.max_tokens)
+    }
+
+    fn settings(&self) -> String {
+        self.config.fingerprint()
+    }
+
+    async fn query(&self, prompt: &str) -> Result<String, ProviderError> {
+        self.complete(prompt, &[], None).await
+    }
+
+    async fn query_with_images(&self, prompt: &str, images: &[Image]) -> Result<String, ProviderError> {
+        self.complete(prompt, images, None).await
+    }
+
+    async fn stream(&self, prompt: &str, images: &[Image], on_text: &OnText<'_>) -> Result<String, ProviderError> {
+        let messages = vec![json!({ "role": "user", "content": image::openai_content(prompt, images) })];
+        let (request, mut request_body) = self.prepare(messages, None, Vec::new());
+        request_body.stream = Some(true);
+        request_body.stream_options = Some(json!({ "include_usage": true }));
+
+        let body = serde_json::to_string(&request_body)?;
+        let prompt_tokens = image::request_tokens(|text| self.estimate_tokens(text), &body, images);
+        let reservation = self.meter.reserve(&self.name, &self.config, prompt_tokens)?;
+        let mut answer = String::new();
+        let mut usage = Usage::default();
+        http::stream_json(request, &request_body, |event| {
+            let chunk: StreamChunk = http::parse_json(event)?;
+            if let Some(tokens) = chunk.usage {
+                usage = Usage {
+                    input_tokens: tokens.prompt_tokens,
+                    output_tokens: tokens.completion_tokens,
+                };
+            }
+            for choice in chunk.choices {
+                if choice.finish_reason.as_deref() == Some("content_filter") {
+                    return Err(AiError::ContentFilter(format!("{} filtered the answer", self.name)).into());
+                }
+                if let Some(text) = choice.delta.content.filter(|text| !text.is_empty()) {
+                    on_text(&text);
+                    answer.push_str(&text);
+                }
+            }
+            Ok(())
+        })
         .await?;
+        reservation.record(usage);
+
+        if answer.is_empty() {
+            return Err(AiError::Malformed(format!("No response content from {}.", self.name)).into());
+        }
+        Ok(answer)
+    }
 
-    // Handle the response
-    if response.status().is_success() {
-        let openai_response: OpenAIResponse = response.json().await?;
-        if let Some(choice) = openai_response.choices.first() {
-            Ok(choice.message.content.clone())
-        } else {
-            Err("No response content from OpenAI.".into())
+    async fn query_structured(&self, prompt: &str, schema: &Value) -> Result<String, ProviderError> {
+        if !self.capabilities.structured_output {
+            return self.complete(&provider::schema_prompt(prompt, schema), &[], None).await;
         }
-    } else {
-        let status = response.status();
-        let error_text = response.text().await?;
-        Err(format!("OpenAI API Error ({}): {}", status, error_text).into())
+        let response_format = json!({
+            "type": "json_schema",
+            "json_schema": { "name": "coaching_report", "strict": true, "schema": schema },
+        });
+        self.complete(prompt, &[], Some(response_format)).await
+    }
+
+    async fn chat(&self, messages: &[Message], tools: &[Tool]) -> Result<Reply, ProviderError> {
+        let messages = messages
+            .iter()
+            .map(|message| match message {
+                Message::User(text) => json!({ "role": "user", "content": text }),
+                Message::Assistant { text, tool_calls } if tool_calls.is_empty() => {
+                    json!({ "role": "assistant", "content": text })
+                }
+                Message::Assistant { text, tool_calls } => json!({
+                    "role": "assistant",
+                    "content": text,
+                    "tool_calls": tool_calls.iter().map(|call| json!({
+                        "id": call.id,
+                        "type": "function",
+                        "function": { "name": call.name, "arguments": call.arguments.to_string() },
+                    })).collect::<Vec<_>>(),
+                }),
+                Message::ToolResult { call_id, content, .. } => {
+                    json!({ "role": "tool", "tool_call_id": call_id, "content": content })
+                }
+            })
+            .collect();
+        let tools = tools
+            .iter()
+            .map(|tool| json!({
+                "type": "function",
+                "function": { "name": tool.name, "description": tool.description, "parameters": tool.parameters },
+            }))
+            .collect();
+
+        let message = self.send(messages, &[], None, tools).await?;
+        Ok(Reply {
+            text: message.content.unwrap_or_default(),
+            tool_calls: message
+                .tool_calls
+                .into_iter()
+                .map(|call| ToolCall {
+                    id: call.id,
+                    name: call.function.name,
+                    arguments: serde_json::from_str(&call.function.arguments).unwrap_or(Value::Null),
+                })
+                .collect(),
+        })
     }
 }
@@ -0,0 +1,137 @@
+use crate::ai::image::Image;
+use crate::ai::tokens;
+use crate::ai::tools::{Message, Reply, Tool};
+use async_trait::async_trait;
+use serde_json::Value;
+use std::error::Error;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+pub type ProviderError = Box<dyn Error + Send + Sync>;
+
+/// Receives a streamed answer piece by piece.
+pub type OnText<'a> = dyn Fn(&str) + Send + Sync + 'a;
+
+/// Features of a provider's API that callers may rely on.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Capabilities {
+    pub system_prompt: bool,
+    pub images: bool,
+    pub tools: bool,
+    pub structured_output: bool,
+    pub streaming: bool,
+}
+
+/// An AI service that can answer a coaching prompt.
+#[async_trait]
+pub trait Provider: Send + Sync {
+    /// Display name, also used to enable and order providers (case-insensitive).
+    fn name(&self) -> &str;
+
+    fn capabilities(&self) -> Capabilities;
+
+    /// Roughly how many of this provider's tokens `text` takes.
+    fn estimate_tokens(&self, text: &str) -> usize {
+        tokens::estimate(text, tokens::DEFAULT_CHARS_PER_TOKEN)
+    }
+
+    /// How many tokens the prompt may take, leaving room for the answer.
+    fn prompt_budget(&self) -> usize {
+        tokens::DEFAULT_CONTEXT_WINDOW - tokens::DEFAULT_ANSWER_TOKENS
+    }
+
+    /// The model and request settings that, along with the prompt, shape the answer.
+    /// Cached answers are only reused for the same settings.
+    fn settings(&self) -> String {
+        String::new()
+    }
+
+    /// Sends the prompt and returns the text of the answer.
+    async fn query(&self, prompt: &str) -> Result<String, ProviderError>;
+
+    /// Like `query`, with `images` attached. Only providers whose `capabilities().images` is
+    /// set can take images; the others only answer prompts without any.
+    async fn query_with_images(&self, prompt: &str, images: &[Image]) -> Result<String, ProviderError> {
+        if images.is_empty() {
+            return self.query(prompt).await;
+        }
+        Err(format!("{} does not accept images", self.name()).into())
+    }
+
+    /// Like `query`, but passes the answer to `on_text` piece by piece as it arrives. Providers
+    /// that can't stream, or whose stream fails before any text arrived, answer through
+    /// `query` and pass the whole answer at once.
+    async fn query_streaming(&self, prompt: &str, on_text: &OnText<'_>) -> Result<String, ProviderError> {
+        self.query_streaming_with_images(prompt, &[], on_text).await
+    }
+
+    /// Like `query_streaming`, with `images` attached as in `query_with_images`.
+    async fn query_streaming_with_images(
+        &self,
+        prompt: &str,
+        images: &[Image],
+        on_text: &OnText<'_>,
+    ) -> Result<String, ProviderError> {
+        if self.capabilities().streaming {
+            let received = AtomicBool::new(false);
+            let forward = |text: &str| {
+                received.store(true, Ordering::Relaxed);
+                on_text(text);
+            };
+            match self.stream(prompt, images, &forward).await {
+                Ok(answer) => return Ok(answer),
+                Err(e) if received.load(Ordering::Relaxed) => return Err(e),
+                Err(e) => eprintln!("Streaming from {} failed ({}), asking again without streaming", self.name(), e),
+            }
+        }
+        let answer = self.query_with_images(prompt, images).await?;
+        on_text(&answer);
+        Ok(answer)
+    }
+
+    /// Streams the answer to `on_text` as server-sent events arrive and returns it in full.
+    /// Only providers whose `capabilities().streaming` is set support this; callers use
+    /// `query_streaming`.
+    async fn stream(&self, _prompt: &str, _images: &[Image], _on_text: &OnText<'_>) -> Result<String, ProviderError> {
+        Err(format!("{} does not support streaming", self.name()).into())
+    }
+
+    /// Asks for an answer matching `schema` (a JSON Schema) and returns it as JSON text.
+    /// Providers without native structured output get the schema in the prompt instead.
+    async fn query_structured(&self, prompt: &str, schema: &Value) -> Result<String, ProviderError> {
+        self.query(&schema_prompt(prompt, schema)).await
+    }
+
+    /// Continues `messages`, letting the model call any of `tools`. Only providers whose
+    /// `capabilities().tools` is set support tools; the others can continue a conversation
+    /// without them, sent as a single prompt.
+    async fn chat(&self, messages: &[Message], tools: &[Tool]) -> Result<Reply, ProviderError> {
+        if !tools.is_empty() {
+            return Err(format!("{} does not support tool calling", self.name()).into());
+        }
+        Ok(Reply {
+            text: self.query(&conversation_prompt(messages)).await?,
+            tool_calls: Vec::new(),
+        })
+    }
+}
+
+/// `messages` as a single prompt, for providers without a chat API of their own.
+pub fn conversation_prompt(messages: &[Message]) -> String {
+    let mut prompt = String::from("Continue this conversation with your next answer.\n");
+    for message in messages {
+        match message {
+            Message::User(text) => prompt.push_str(&format!("\nUser:\n{}\n", text)),
+            Message::Assistant { text, .. } => prompt.push_str(&format!("\nYou:\n{}\n", text)),
+            Message::ToolResult { name, content, .. } => prompt.push_str(&format!("\n{} returned:\n{}\n", name, content)),
+        }
+    }
+    prompt
+}
+
+/// `prompt` asking for an answer matching `schema`, for providers without native structured output.
+pub fn schema_prompt(prompt: &str, schema: &Value) -> String {
+    format!(
+        "{}\n\nRespond only with a JSON object matching this JSON Schema, without any other text:\n{}",
+        prompt, schema
+    )
+}
@@ -0,0 +1,98 @@
+use crate::ai::anthropic::Anthropic;
+use crate::ai::cache::{self, CacheMode, Cached};
+use crate::ai::config::Config;
+use crate::ai::google::Gemini;
+use crate::ai::openai::OpenAI;
+use crate::ai::provider::Provider;
+use crate::ai::usage::Meter;
+use std::env;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// The providers a query is sent to, in order.
+#[derive(Default)]
+pub struct Registry {
+    providers: Vec<Box<dyn Provider>>,
+    meter: Arc<Meter>,
+}
+
+impl Registry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers the built-in providers whose API keys are set in the environment, with
+    /// the settings from `config`. `RATTLEBRAIN_PROVIDERS` (e.g. `anthropic,openai`)
+    /// limits and orders them. Unless `config.cache` is off, their answers are cached. Their
+    /// usage is counted in one meter, capped at `config.max_cost`.
+    pub fn from_config(config: &Config) -> Self {
+        let mut registry = Registry::new();
+        registry.meter = Arc::new(Meter::new(config.max_cost));
+        let meter = registry.meter.clone();
+
+        if let Some(provider) = OpenAI::from_env(&config.openai) {
+            registry.register(provider.with_meter(meter.clone()));
+        }
+        if let Some(provider) = Anthropic::from_env(&config.anthropic) {
+            registry.register(provider.with_meter(meter.clone()));
+        }
+        if let Some(provider) = Gemini::from_env(&config.gemini) {
+            registry.register(provider.with_meter(meter.clone()));
+        }
+        if let Some(provider) = OpenAI::local_from_env(config.local.as_ref()) {
+            registry.register(provider.with_meter(meter));
+        }
+
+        if let Ok(selection) = env::var("RATTLEBRAIN_PROVIDERS") {
+            let names: Vec<&str> = selection.split(',').map(str::trim).filter(|n| !n.is_empty()).collect();
+            registry.select(&names);
+        }
+
+        if config.cache != CacheMode::Off {
+            let ttl = Duration::from_secs(config.cache_ttl);
+            registry.providers = std::mem::take(&mut registry.providers)
+                .into_iter()
+                .map(|provider| {
+                    Box::new(Cached::new(provider, cache::CACHE_DIR, config.cache, ttl)) as Box<dyn Provider>
+                })
+                .collect();
+        }
+
+        registry
+    }
+
+    /// Adds a provider after the ones already registered.
+    pub fn register<P: Provider + 'static>(&mut self, provider: P) {
+        self.providers.push(Box::new(provider));
+    }
+
+    /// Keeps only the named providers, in the order given.
+    pub fn select(&mut self, names: &[&str]) {
+        let mut available = std::mem::take(&mut self.providers);
+        for name in names {
+            if let Some(index) = available.iter().position(|p| p.name().eq_ignore_ascii_case(name)) {
+                self.providers.push(available.remove(index));
+            } else {
+                eprintln!("Unknown or unconfigured AI provider: {}", name);
+            }
+        }
+    }
+
+    pub fn providers(&self) -> &[Box<dyn Provider>] {
+        &self.providers
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.providers.is_empty()
+    }
+
+    /// What the providers from `from_config` have used so far.
+    pub fn meter(&self) -> &Meter {
+        &self.meter
+    }
+}
+
+/// Shows the first characters of an API key for logging.
+pub fn mask_key(key: &str) -> String {
+    format!("{}****", key.chars().take(8).collect::<String>())
+}
@@ -0,0 +1,43 @@
+/// Characters per token for English prose with GPT-style tokenizers. CSV rows and numbers
+/// tokenize worse, so estimates err on the high side by using fewer characters per token.
+pub const DEFAULT_CHARS_PER_TOKEN: f64 = 3.5;
+
+/// Context window assumed for models not in `context_window`, e.g. local models.
+pub const DEFAULT_CONTEXT_WINDOW: usize = 8192;
+
+/// Tokens kept free for the answer when a provider sets no `max_tokens`.
+pub const DEFAULT_ANSWER_TOKENS: usize = 4096;
+
+/// Estimates how many tokens `text` takes, given a tokenizer's average characters per token.
+pub fn estimate(text: &str, chars_per_token: f64) -> usize {
+    (text.chars().count() as f64 / chars_per_token).ceil() as usize
+}
+
+/// The context window of well-known models, by model name prefix.
+pub fn context_window(model: &str) -> usize {
+    const WINDOWS: [(&str, usize); 9] = [
+        ("gpt-4o", 128_000),
+        ("gpt-4.1", 1_000_000),
+        ("gpt-4-turbo", 128_000),
+        ("o1", 200_000),
+        ("o3", 200_000),
+        ("claude-", 200_000),
+        ("gemini-1.5-pro", 2_000_000),
+        ("gemini-1.5-flash", 1_000_000),
+        ("gemini-2", 1_000_000),
+    ];
+    WINDOWS
+        .iter()
+        .find(|(prefix, _)| model.starts_with(prefix))
+        .map(|(_, window)| *window)
+        .unwrap_or(DEFAULT_CONTEXT_WINDOW)
+}
+
+/// Tokens left for the prompt: the configured budget, or the model's context window
+/// minus room for the answer.
+pub fn prompt_budget(model: &str, prompt_tokens: Option<usize>, max_tokens: Option<u32>) -> usize {
+    prompt_tokens.unwrap_or_else(|| {
+        let answer = max_tokens.map(|t| t as usize).unwrap_or(DEFAULT_ANSWER_TOKENS);
+        context_window(model).saturating_sub(answer)
+    })
+}
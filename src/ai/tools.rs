@@ -0,0 +1,62 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// A function the model may call, with a JSON Schema for its arguments.
+#[derive(Debug, Clone)]
+pub struct Tool {
+    pub name: String,
+    pub description: String,
+    pub parameters: Value,
+}
+
+/// A call the model asked for. Providers without call ids use the tool name.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToolCall {
+    pub id: String,
+    pub name: String,
+    pub arguments: Value,
+}
+
+/// One message of a conversation in which the model may call tools.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Message {
+    User(String),
+    Assistant { text: String, tool_calls: Vec<ToolCall> },
+    ToolResult { call_id: String, name: String, content: String },
+}
+
+/// The model's turn: text, calls to make before it continues, or both.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Reply {
+    pub text: String,
+    pub tool_calls: Vec<ToolCall>,
+}
+
+/// `messages` with the tool calls and their results written out as text, for a request that
+/// offers no tools: the APIs reject tool calls in a conversation that doesn't define them.
+pub fn as_text(messages: &[Message]) -> Vec<Message> {
+    let mut text_messages: Vec<Message> = Vec::new();
+    for message in messages {
+        let message = match message {
+            Message::User(text) => Message::User(text.clone()),
+            Message::Assistant { text, tool_calls } => {
+                let calls = tool_calls.iter().map(|call| format!("Called {}({})", call.name, call.arguments));
+                let lines: Vec<String> = (!text.is_empty()).then(|| text.clone()).into_iter().chain(calls).collect();
+                Message::Assistant {
+                    text: lines.join("\n"),
+                    tool_calls: Vec::new(),
+                }
+            }
+            Message::ToolResult { name, content, .. } => Message::User(format!("{} returned:\n{}", name, content)),
+        };
+        // Results of several calls go back as one turn
+        match (text_messages.last_mut(), message) {
+            (Some(Message::User(last)), Message::User(text)) => {
+                last.push_str("\n\n");
+                last.push_str(&text);
+            }
+            (_, message) => text_messages.push(message),
+        }
+    }
+    text_messages
+}
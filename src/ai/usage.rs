@@ -0,0 +1,214 @@
+use crate::ai::config::ProviderConfig;
+use crate::ai::provider::ProviderError;
+use crate::ai::tokens;
+use std::sync::Mutex;
+
+/// Tokens one request used, as the provider reported them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Usage {
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+}
+
+/// US dollars per million tokens.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Price {
+    pub input: f64,
+    pub output: f64,
+}
+
+impl Price {
+    pub fn cost(&self, usage: Usage) -> f64 {
+        (usage.input_tokens as f64 * self.input + usage.output_tokens as f64 * self.output) / 1_000_000.0
+    }
+}
+
+/// List prices of well-known models, by model name prefix. Local and unknown models have
+/// no price; set `input_price` and `output_price` in the config to count them.
+pub fn price(model: &str) -> Option<Price> {
+    const PRICES: [(&str, f64, f64); 17] = [
+        ("gpt-4o-mini", 0.15, 0.60),
+        ("gpt-4o", 2.50, 10.00),
+        ("gpt-4.1-nano", 0.10, 0.40),
+        ("gpt-4.1-mini", 0.40, 1.60),
+        ("gpt-4.1", 2.00, 8.00),
+        ("gpt-4-turbo", 10.00, 30.00),
+        ("o1-mini", 1.10, 4.40),
+        ("o1", 15.00, 60.00),
+        ("o3-mini", 1.10, 4.40),
+        ("claude-3-5-haiku", 0.80, 4.00),
+        ("claude-3-5-sonnet", 3.00, 15.00),
+        ("claude-3-haiku", 0.25, 1.25),
+        ("claude-3-opus", 15.00, 75.00),
+        ("gemini-1.5-flash", 0.075, 0.30),
+        ("gemini-1.5-pro", 1.25, 5.00),
+        ("gemini-2.0-flash-lite", 0.075, 0.30),
+        ("gemini-2.0-flash", 0.10, 0.40),
+    ];
+    PRICES
+        .iter()
+        .find(|(prefix, _, _)| model.starts_with(prefix))
+        .map(|&(_, input, output)| Price { input, output })
+}
+
+/// One request that got an answer.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Call {
+    pub provider: String,
+    pub model: String,
+    pub usage: Usage,
+    /// `None` when the model's price is unknown.
+    pub cost: Option<f64>,
+}
+
+/// Tallies what the requests of a run used and cost, shared by every provider of the run,
+/// and keeps the run under an optional spending cap.
+#[derive(Debug, Default)]
+pub struct Meter {
+    cap: Option<f64>,
+    state: Mutex<MeterState>,
+}
+
+#[derive(Debug, Default)]
+struct MeterState {
+    calls: Vec<Call>,
+    /// The most the requests in flight may still cost.
+    reserved: f64,
+}
+
+impl Meter {
+    /// `cap` is the most the run may spend, in US dollars.
+    pub fn new(cap: Option<f64>) -> Self {
+        Meter {
+            cap,
+            state: Mutex::default(),
+        }
+    }
+
+    /// The answer length to ask a provider for: its `max_tokens`, or under a cap the length
+    /// `reserve` assumes, so that no answer can cost more than was reserved for it.
+    pub fn max_tokens(&self, config: &ProviderConfig) -> Option<u32> {
+        match self.cap {
+            Some(_) => Some(config.max_tokens.unwrap_or(tokens::DEFAULT_ANSWER_TOKENS as u32)),
+            None => config.max_tokens,
+        }
+    }
+
+    /// Holds the most a request to `provider` with `prompt_tokens` of prompt could cost (a
+    /// full-length answer of `max_tokens` included), or refuses if the run could go over its
+    /// cap with what was spent, what's in flight and this request together.
+    pub fn reserve(
+        &self,
+        provider: &str,
+        config: &ProviderConfig,
+        prompt_tokens: usize,
+    ) -> Result<Reservation<'_>, ProviderError> {
+        let price = config.price();
+        let answer_tokens = self.max_tokens(config).map(|t| t as usize).unwrap_or(tokens::DEFAULT_ANSWER_TOKENS);
+        let most = Usage {
+            input_tokens: prompt_tokens as u64,
+            output_tokens: answer_tokens as u64,
+        };
+        let estimate = price.map(|price| price.cost(most)).unwrap_or_default();
+
+        let mut state = self.state.lock().unwrap();
+        if let Some(cap) = self.cap {
+            let committed = total_cost(&state.calls) + state.reserved;
+            if committed + estimate > cap {
+                return Err(format!(
+                    "Not sending to {}: it could cost up to ${:.4}, and the run has ${:.4} of its ${:.2} cap left",
+                    provider,
+                    estimate,
+                    (cap - committed).max(0.0),
+                    cap
+                )
+                .into());
+            }
+        }
+        state.reserved += estimate;
+        Ok(Reservation {
+            meter: self,
+            provider: provider.to_string(),
+            model: config.model.clone(),
+            price,
+            estimate,
+        })
+    }
+
+    pub fn calls(&self) -> Vec<Call> {
+        self.state.lock().unwrap().calls.clone()
+    }
+
+    /// A markdown table of calls, tokens and estimated cost per provider and model, with a
+    /// total. Empty when nothing was sent.
+    pub fn summary(&self) -> String {
+        let calls = self.calls();
+        if calls.is_empty() {
+            return String::new();
+        }
+
+        let mut rows: Vec<(String, String, Vec<&Call>)> = Vec::new();
+        for call in &calls {
+            match rows.iter_mut().find(|(provider, model, _)| *provider == call.provider && *model == call.model) {
+                Some(row) => row.2.push(call),
+                None => rows.push((call.provider.clone(), call.model.clone(), vec![call])),
+            }
+        }
+
+        let mut summary = String::from(
+            "| Provider | Model | Calls | Input tokens | Output tokens | Estimated cost |\n|---|---|---|---|---|---|\n",
+        );
+        for (provider, model, row) in &rows {
+            summary.push_str(&row_line(provider, model, row));
+        }
+        let all: Vec<&Call> = calls.iter().collect();
+        summary.push_str(&row_line("**Total**", "", &all));
+        summary
+    }
+}
+
+/// Money held for a request in flight, released when the request ends. `record` books
+/// what it actually used.
+pub struct Reservation<'a> {
+    meter: &'a Meter,
+    provider: String,
+    model: String,
+    price: Option<Price>,
+    estimate: f64,
+}
+
+impl Reservation<'_> {
+    pub fn record(self, usage: Usage) {
+        let call = Call {
+            provider: self.provider.clone(),
+            model: self.model.clone(),
+            usage,
+            cost: self.price.map(|price| price.cost(usage)),
+        };
+        self.meter.state.lock().unwrap().calls.push(call);
+    }
+}
+
+impl Drop for Reservation<'_> {
+    fn drop(&mut self) {
+        let mut state = self.meter.state.lock().unwrap();
+        state.reserved = (state.reserved - self.estimate).max(0.0);
+    }
+}
+
+fn total_cost(calls: &[Call]) -> f64 {
+    calls.iter().filter_map(|call| call.cost).sum()
+}
+
+fn row_line(provider: &str, model: &str, calls: &[&Call]) -> String {
+    let input: u64 = calls.iter().map(|call| call.usage.input_tokens).sum();
+    let output: u64 = calls.iter().map(|call| call.usage.output_tokens).sum();
+    let cost = if calls.iter().all(|call| call.cost.is_none()) {
+        "unknown".to_string()
+    } else {
+        let known: f64 = calls.iter().filter_map(|call| call.cost).sum();
+        let partial = if calls.iter().any(|call| call.cost.is_none()) { " + unknown" } else { "" };
+        format!("${:.4}{}", known, partial)
+    };
+    format!("| {} | {} | {} | {} | {} | {} |\n", provider, model, calls.len(), input, output, cost)
+}
@@ -0,0 +1,126 @@
+use crate::agent::{self, MatchTools};
+use crate::ai::config::Config;
+use crate::ai::provider::Provider;
+use crate::ai::registry::Registry;
+use crate::ai::tools::Message;
+use crate::digest::{self, MatchData};
+use crate::prompt::{self, Priority, Section};
+use crate::query;
+use std::error::Error;
+use std::fs::{self, OpenOptions};
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+
+/// Starts a conversation about a converted match with `provider_name`, or the first
+/// configured provider, reading questions from stdin. The conversation is saved to
+/// `./output/<output_name>.chat.md`.
+pub async fn chat(
+    match_guid: &str,
+    provider_name: Option<&str>,
+    player: Option<&str>,
+    config: &Config,
+) -> Result<(), Box<dyn Error>> {
+    let mut registry = Registry::from_config(config);
+    if let Some(name) = provider_name {
+        registry.select(&[name]);
+    }
+    let Some(provider) = registry.providers().first() else {
+        return Err("No AI provider to chat with. Set one of OPENAI_API_KEY, ANTHROPIC_API_KEY, GEMINI_API_KEY, LOCAL_AI_BASE_URL.".into());
+    };
+
+    let data = digest::load_match(match_guid)?;
+    let context = context(provider.as_ref(), &data, player);
+    let tools = MatchTools::new(data);
+
+    fs::create_dir_all("./output")?;
+    let log_path = format!("./output/{}.chat.md", query::output_name(match_guid, player));
+    if !Path::new(&log_path).exists() {
+        fs::write(&log_path, format!("# Chat about match {}\n", match_guid))?;
+    }
+
+    println!(
+        "Chatting with {} about match {}. Ask a question, or type exit to stop.",
+        provider.name(),
+        match_guid
+    );
+    converse(provider.as_ref(), &tools, &context, io::stdin().lock(), &log_path, config.agent_steps).await?;
+    println!("Conversation saved to: {}", log_path);
+    let summary = registry.meter().summary();
+    if !summary.is_empty() {
+        println!("Usage:\n{}", summary);
+    }
+    Ok(())
+}
+
+/// What the conversation opens with: the coach's brief and the match digest, cut to half of
+/// what `provider` can take so that the conversation has room to grow.
+pub fn context(provider: &dyn Provider, data: &MatchData, player: Option<&str>) -> String {
+    let mut brief = vec![
+        "You are a world-class Rocket League team coach answering questions about the match below.".to_string(),
+        "The digest is computed from the replay frames; times are match clock, f = frame number. Look up anything it doesn't cover with the tools.".to_string(),
+        "Cite the moments you refer to as [frames START-END], or [frame N] for a single instant.".to_string(),
+    ];
+    if let Some(player) = player {
+        brief.push(format!("You are talking to {}; answer from their point of view.", player));
+    }
+
+    let mut sections = vec![Section::new("", Priority::Required, brief)];
+    sections.extend(digest::sections(&data.frames, &data.goals, &data.events, player));
+    let assembled = prompt::assemble(&sections, provider.prompt_budget() / 2, &|text| provider.estimate_tokens(text));
+    for note in &assembled.dropped {
+        println!("  {} to fit {}", note, provider.name());
+    }
+    assembled.text
+}
+
+/// Answers the questions read from `input`, one per line, until it ends or reads `exit` or
+/// `quit`. The first question carries `context`; every later one has the whole conversation
+/// before it. Each exchange, tool calls included, is appended to `log_path` as soon as it is
+/// answered. A question the provider fails to answer is reported and left out of the history.
+pub async fn converse(
+    provider: &dyn Provider,
+    tools: &MatchTools,
+    context: &str,
+    input: impl BufRead,
+    log_path: &str,
+    max_steps: usize,
+) -> Result<Vec<Message>, Box<dyn Error>> {
+    let mut log = OpenOptions::new().append(true).create(true).open(log_path)?;
+    write!(log, "\n## {}\n", provider.name())?;
+
+    let mut messages = Vec::new();
+    let mut lines = input.lines();
+    loop {
+        print!("> ");
+        io::stdout().flush()?;
+        let Some(line) = lines.next() else { break };
+        let question = line?;
+        let question = question.trim();
+        if question.is_empty() {
+            continue;
+        }
+        if question.eq_ignore_ascii_case("exit") || question.eq_ignore_ascii_case("quit") {
+            break;
+        }
+
+        let start = messages.len();
+        messages.push(Message::User(if start == 0 {
+            format!("{}\n\nQuestion: {}", context, question)
+        } else {
+            question.to_string()
+        }));
+        match agent::respond(provider, tools, &mut messages, max_steps).await {
+            Ok(answer) => {
+                println!("\n{}\n", answer.trim());
+                write!(log, "\n**You**\n\n{}\n", question)?;
+                log.write_all(agent::render_messages(provider.name(), "You", &messages[start + 1..]).as_bytes())?;
+            }
+            Err(e) => {
+                eprintln!("Error from {}: {}", provider.name(), e);
+                messages.truncate(start);
+            }
+        }
+    }
+    println!();
+    Ok(messages)
+}
@@ -0,0 +1,167 @@
+use jsonschema::JSONSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::error::Error;
+
+/// Coaching for a whole match, as requested from the providers in structured mode.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CoachingReport {
+    pub summary: String,
+    pub players: Vec<PlayerCoaching>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlayerCoaching {
+    pub player_name: String,
+    pub strengths: Vec<Observation>,
+    pub weaknesses: Vec<Observation>,
+    pub drills: Vec<Drill>,
+}
+
+/// A point about a player, backed by the moments of the match where it shows.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Observation {
+    pub point: String,
+    pub frames: Vec<FrameRange>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FrameRange {
+    pub start_frame: usize,
+    pub end_frame: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Drill {
+    pub name: String,
+    pub description: String,
+}
+
+/// JSON Schema for `CoachingReport`, as sent to the providers. Every property is required
+/// and no others are allowed, which is what OpenAI's strict mode expects; it also rejects
+/// keywords such as `minimum`, so those are only checked locally, see `parse_report`.
+pub fn report_schema() -> Value {
+    schema(json!({ "type": "integer" }))
+}
+
+/// `report_schema` with the checks the providers don't take, for validating answers.
+fn validation_schema() -> Value {
+    schema(json!({ "type": "integer", "minimum": 0 }))
+}
+
+/// The report schema with `frame` as the schema of a frame number.
+fn schema(frame: Value) -> Value {
+    let frame_range = json!({
+        "type": "object",
+        "additionalProperties": false,
+        "required": ["start_frame", "end_frame"],
+        "properties": {
+            "start_frame": frame,
+            "end_frame": frame
+        }
+    });
+    let observation = json!({
+        "type": "object",
+        "additionalProperties": false,
+        "required": ["point", "frames"],
+        "properties": {
+            "point": { "type": "string" },
+            "frames": { "type": "array", "items": frame_range }
+        }
+    });
+
+    json!({
+        "type": "object",
+        "additionalProperties": false,
+        "required": ["summary", "players"],
+        "properties": {
+            "summary": { "type": "string" },
+            "players": {
+                "type": "array",
+                "items": {
+                    "type": "object",
+                    "additionalProperties": false,
+                    "required": ["player_name", "strengths", "weaknesses", "drills"],
+                    "properties": {
+                        "player_name": { "type": "string" },
+                        "strengths": { "type": "array", "items": observation },
+                        "weaknesses": { "type": "array", "items": observation },
+                        "drills": {
+                            "type": "array",
+                            "items": {
+                                "type": "object",
+                                "additionalProperties": false,
+                                "required": ["name", "description"],
+                                "properties": {
+                                    "name": { "type": "string" },
+                                    "description": { "type": "string" }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    })
+}
+
+/// Parses a provider's answer, validating it against `report_schema` and the checks it
+/// leaves out, such as frame numbers not being negative. Tolerates the
+/// answer being wrapped in a markdown code fence.
+pub fn parse_report(response: &str) -> Result<CoachingReport, Box<dyn Error + Send + Sync>> {
+    let text = response.trim();
+    let text = text
+        .strip_prefix("```json")
+        .or_else(|| text.strip_prefix("```"))
+        .and_then(|rest| rest.trim_end().strip_suffix("```"))
+        .unwrap_or(text);
+    let instance: Value = serde_json::from_str(text.trim())?;
+
+    let schema = validation_schema();
+    let compiled = JSONSchema::compile(&schema).map_err(|e| format!("Invalid coaching schema: {}", e))?;
+    if let Err(errors) = compiled.validate(&instance) {
+        let messages: Vec<String> = errors
+            .map(|e| format!("{} at {}", e, e.instance_path))
+            .collect();
+        return Err(format!("Answer does not match the coaching schema: {}", messages.join("; ")).into());
+    }
+
+    Ok(serde_json::from_value(instance)?)
+}
+
+/// Renders a report as markdown for `feedback.md`.
+pub fn render_markdown(report: &CoachingReport) -> String {
+    let mut markdown = format!("{}\n", report.summary);
+
+    for player in &report.players {
+        markdown.push_str(&format!("\n### {}\n", player.player_name));
+        for (title, observations) in [("Strengths", &player.strengths), ("Weaknesses", &player.weaknesses)] {
+            if observations.is_empty() {
+                continue;
+            }
+            markdown.push_str(&format!("\n**{}**\n", title));
+            for observation in observations {
+                markdown.push_str(&format!("- {}{}\n", observation.point, cite_frames(&observation.frames)));
+            }
+        }
+        if !player.drills.is_empty() {
+            markdown.push_str("\n**Drills**\n");
+            for drill in &player.drills {
+                markdown.push_str(&format!("- **{}**: {}\n", drill.name, drill.description));
+            }
+        }
+    }
+
+    markdown
+}
+
+fn cite_frames(frames: &[FrameRange]) -> String {
+    if frames.is_empty() {
+        return String::new();
+    }
+    let ranges: Vec<String> = frames
+        .iter()
+        .map(|range| format!("{}-{}", range.start_frame, range.end_frame))
+        .collect();
+    format!(" [frames {}]", ranges.join(", "))
+}
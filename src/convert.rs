@@ -4,6 +4,7 @@ use std::io::Write;
 use std::collections::HashMap;
 use std::io::BufWriter;
 use std::path::Path;
+use crate::extract;
 
 // Main function to analyze replay data
 pub fn convert_replay(data: Value, filename: &str) -> Result<(), Box<dyn std::error::Error>> {
@@ -33,11 +34,31 @@ pub fn convert_replay(data: Value, filename: &str) -> Result<(), Box<dyn std::er
     Ok(())
 }
 
+/// Converts every file `extract` wrote for a match, see `extract::SECTIONS`.
+pub fn convert_match(match_guid: &str) -> Result<(), Box<dyn std::error::Error>> {
+    for section in extract::SECTIONS {
+        let file_path = format!("./output/{}.{}.json", match_guid, section);
+        println!("Converting replay data to CSV: {}", file_path);
+        let data: Value = serde_json::from_str(&fs::read_to_string(&file_path)?)?;
+        convert_replay(data, &file_path)?;
+    }
+    Ok(())
+}
+
 fn sanitize_filename(filename: &str) -> String {
     Path::new(filename)
         .file_name()
         .map(|os_str| os_str.to_string_lossy().to_string())
-        .unwrap_or_else(|| "".to_string()) // Return an empty string if file_name() is None
+        .unwrap_or_default() // Return an empty string if file_name() is None
+}
+
+/// Maps a team archetype object name to its team index (0 = blue, 1 = orange).
+fn team_index(object_name: &str) -> Option<u32> {
+    match object_name.trim_matches('"') {
+        "Archetypes.Teams.Team0" => Some(0),
+        "Archetypes.Teams.Team1" => Some(1),
+        _ => None,
+    }
 }
 
 fn handle_header(data: &Value, filename: &str) -> Result<(), Box<dyn std::error::Error>> {
@@ -188,6 +209,13 @@ fn handle_frames(data: &Value, filename: &str) -> Result<(), Box<dyn std::error:
 
     parse_frames(data, &mut file)?;
 
+    let match_name = trimmed_file_name
+        .strip_suffix(".replay.frames.json")
+        .unwrap_or(trimmed_file_name);
+    let demos_path = format!("output/{}.demos.csv", match_name);
+    let mut demos_file = File::create(demos_path)?;
+    parse_demos(data, &mut demos_file)?;
+
     println!("Processed frames: {}", filename);
     Ok(())
 }
@@ -198,6 +226,7 @@ pub fn parse_frames(data: &Value, file: &mut dyn Write) -> Result<(), Box<dyn st
     let mut player_map: HashMap<String, String> = HashMap::new();
     let mut player_actor_map: HashMap<String, String> = HashMap::new();
     let mut team_map: HashMap<String, String> = HashMap::new();
+    let mut team_actor_map: HashMap<String, String> = HashMap::new();
     let mut car_map: HashMap<String, String> = HashMap::new();
     let mut car_boost_map: HashMap<String, i64> = HashMap::new();
     let mut lines: Vec<String> = Vec::new();
@@ -220,6 +249,8 @@ pub fn parse_frames(data: &Value, file: &mut dyn Write) -> Result<(), Box<dyn st
                     let obj_name = spawned.get("object_name").unwrap_or(&Value::Null).to_string();
                     if obj_name.starts_with(ball_prefix) {
                         ball_id = actor_id.clone();
+                    } else if let Some(team_index) = team_index(&obj_name) {
+                        team_actor_map.insert(actor_id.clone(), team_index.to_string());
                     }
                 }
 
@@ -241,11 +272,15 @@ pub fn parse_frames(data: &Value, file: &mut dyn Write) -> Result<(), Box<dyn st
                                 .and_then(|flagged_int| flagged_int.get("int"))
                                 .and_then(|int_value| int_value.as_i64()) 
                             {
-                                team_map.insert(actor_id.clone(), value_int.to_string());
+                                // Resolve the team actor to its team index (0 = blue, 1 = orange)
+                                let team = team_actor_map
+                                    .get(&value_int.to_string())
+                                    .cloned()
+                                    .unwrap_or_else(|| value_int.to_string());
+                                team_map.insert(actor_id.clone(), team);
                             }
 
-                        } else if name == "Engine.Pawn:PlayerReplicationInfo" || 
-                            name == "Engine.Pawn:PlayerReplicationInfo"  {
+                        } else if name == "Engine.Pawn:PlayerReplicationInfo" {
                             if let Some(value_int) = update
                                 .get("value")
                                 .and_then(|value| value.get("flagged_int"))
@@ -499,3 +534,109 @@ pub fn parse_frames(data: &Value, file: &mut dyn Write) -> Result<(), Box<dyn st
 
     Ok(())
 }
+
+/// Writes one CSV row per demolition found in the frames, located at the victim's last known position.
+pub fn parse_demos(data: &Value, file: &mut dyn Write) -> Result<(), Box<dyn std::error::Error>> {
+    let empty_array: Vec<Value> = vec![];
+    let frames = data.as_array().unwrap_or(&empty_array);
+    let mut player_map: HashMap<String, String> = HashMap::new();
+    let mut player_actor_map: HashMap<String, String> = HashMap::new();
+    let mut team_map: HashMap<String, String> = HashMap::new();
+    let mut team_actor_map: HashMap<String, String> = HashMap::new();
+    let mut last_location: HashMap<String, (i64, i64, i64)> = HashMap::new();
+    let mut last_demo: HashMap<(String, String), usize> = HashMap::new();
+
+    let mut writer = BufWriter::new(file);
+    writeln!(writer, "frame,time,attacker_name,attacker_team,victim_name,victim_team,location_x,location_y,location_z")?;
+
+    for (frame_index, frame) in frames.iter().enumerate() {
+        let time = frame.get("time").unwrap_or(&Value::Null).to_string();
+
+        for replication in frame.get("replications").and_then(|r| r.as_array()).unwrap_or(&empty_array) {
+            let actor_id = replication
+                .pointer("/actor_id/value")
+                .unwrap_or(&Value::Null)
+                .to_string();
+
+            if let Some(spawned) = replication.pointer("/value/spawned") {
+                let obj_name = spawned.get("object_name").unwrap_or(&Value::Null).to_string();
+                if let Some(team_index) = team_index(&obj_name) {
+                    team_actor_map.insert(actor_id.clone(), team_index.to_string());
+                }
+            }
+
+            for update in replication.pointer("/value/updated").and_then(|u| u.as_array()).unwrap_or(&empty_array) {
+                let name = update.get("name").and_then(Value::as_str).unwrap_or("");
+                let value = update.get("value").unwrap_or(&Value::Null);
+
+                if name == "Engine.PlayerReplicationInfo:PlayerName" {
+                    if let Some(player_name) = value.get("string").and_then(Value::as_str) {
+                        player_map.insert(actor_id.clone(), player_name.to_string());
+                    }
+                } else if name == "Engine.PlayerReplicationInfo:Team" {
+                    if let Some(team_actor) = value.pointer("/flagged_int/int").and_then(Value::as_i64) {
+                        let team = team_actor_map
+                            .get(&team_actor.to_string())
+                            .cloned()
+                            .unwrap_or_else(|| team_actor.to_string());
+                        team_map.insert(actor_id.clone(), team);
+                    }
+                } else if name == "Engine.Pawn:PlayerReplicationInfo" {
+                    if let Some(pri) = value.pointer("/flagged_int/int").and_then(Value::as_i64) {
+                        if pri > 0 {
+                            player_actor_map.insert(actor_id.clone(), pri.to_string());
+                        }
+                    }
+                } else if name == "TAGame.RBActor_TA:ReplicatedRBState" {
+                    let location = value.pointer("/rigid_body_state/location").unwrap_or(&Value::Null);
+                    last_location.insert(
+                        actor_id.clone(),
+                        (
+                            location.get("x").and_then(Value::as_i64).unwrap_or(0),
+                            location.get("y").and_then(Value::as_i64).unwrap_or(0),
+                            location.get("z").and_then(Value::as_i64).unwrap_or(0),
+                        ),
+                    );
+                } else if name.starts_with("TAGame.Car_TA:ReplicatedDemolish") {
+                    let demolish = value
+                        .get("demolish")
+                        .or_else(|| value.pointer("/custom_demolish/demolish"))
+                        .or_else(|| value.pointer("/custom_demolish_extended/custom_demolish/demolish"));
+                    let Some(demolish) = demolish else { continue };
+
+                    let attacker = demolish.get("attacker_actor_id").unwrap_or(&Value::Null).to_string();
+                    let victim = demolish.get("victim_actor_id").unwrap_or(&Value::Null).to_string();
+
+                    // The demolish attribute is replicated more than once per demolition
+                    let key = (attacker.clone(), victim.clone());
+                    if let Some(&previous) = last_demo.get(&key) {
+                        if frame_index - previous < 90 {
+                            continue;
+                        }
+                    }
+                    last_demo.insert(key, frame_index);
+
+                    let attacker_pri = player_actor_map.get(&attacker).map(String::as_str).unwrap_or("Unknown");
+                    let victim_pri = player_actor_map.get(&victim).map(String::as_str).unwrap_or("Unknown");
+                    let (location_x, location_y, location_z) = last_location.get(&victim).copied().unwrap_or((0, 0, 0));
+
+                    writeln!(
+                        writer,
+                        "{},{},\"{}\",{},\"{}\",{},{},{},{}",
+                        frame_index,
+                        time,
+                        player_map.get(attacker_pri).map(String::as_str).unwrap_or("Unknown"),
+                        team_map.get(attacker_pri).map(String::as_str).unwrap_or(""),
+                        player_map.get(victim_pri).map(String::as_str).unwrap_or("Unknown"),
+                        team_map.get(victim_pri).map(String::as_str).unwrap_or(""),
+                        location_x,
+                        location_y,
+                        location_z
+                    )?;
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
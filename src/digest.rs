@@ -0,0 +1,503 @@
+use crate::events::{self, Event, EventKind, Goal, ShotOutcome};
+use crate::plot::{self, GameState};
+use crate::prompt::{Priority, Section};
+use crate::stats;
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::path::Path;
+
+/// Cars above this speed, in unreal units per second, are supersonic.
+const SUPERSONIC_SPEED: f64 = 2200.0;
+/// How often the rotation summary looks at the field, in seconds.
+const ROTATION_SAMPLE_INTERVAL: f64 = 1.0;
+/// How long before a goal its key-moment snapshot is taken, in seconds.
+const SNAPSHOT_LEAD_TIME: f64 = 2.0;
+
+/// Everything the digest is computed from.
+pub struct MatchData {
+    pub frames: Vec<GameState>,
+    pub goals: Vec<Goal>,
+    pub events: Vec<Event>,
+}
+
+/// Loads a converted match from the files in `./output`, detecting events from the
+/// frames if `events` hasn't been run yet.
+pub fn load_match(match_guid: &str) -> Result<MatchData, Box<dyn Error>> {
+    let frames_csv_path = format!("./output/{}.replay.frames.json.csv", match_guid);
+    let goals_csv_path = format!("./output/{}.goals.json.csv", match_guid);
+    let demos_csv_path = format!("./output/{}.demos.csv", match_guid);
+    let events_csv_path = format!("./output/{}.events.csv", match_guid);
+
+    let frames = plot::load_frames(&frames_csv_path)?;
+    let goals = if Path::new(&goals_csv_path).exists() {
+        events::load_goals(&goals_csv_path)?
+    } else {
+        Vec::new()
+    };
+    let events = if Path::new(&events_csv_path).exists() {
+        events::load_events(&events_csv_path)?
+    } else {
+        let demos = if Path::new(&demos_csv_path).exists() {
+            events::load_demos(&demos_csv_path)?
+        } else {
+            Vec::new()
+        };
+        events::find_events(&frames, &goals, &demos)
+    };
+
+    Ok(MatchData { frames, goals, events })
+}
+
+/// The digest as prompt sections: an overview, per-player stats and rotation roles
+/// (stats), a timeline of kickoffs, goals, shots and demos (events), and the positions
+/// of everyone just before each goal (frames). With a `player`, their stats are compared
+/// with teammates and opponents, and the timeline keeps only the shots and demos they
+/// were part of.
+pub fn sections(data: &[GameState], goals: &[Goal], match_events: &[Event], player: Option<&str>) -> Vec<Section> {
+    let scale = events::position_scale(data);
+    let start = data.iter().map(|s| s.time).fold(f64::INFINITY, f64::min);
+    let start = if start.is_finite() { start } else { 0.0 };
+    let summaries = summaries(data, match_events, scale);
+    let involved: Vec<Event> = match player {
+        Some(player) => match_events
+            .iter()
+            .filter(|e| e.player_name == player || e.victim_name.as_deref() == Some(player))
+            .cloned()
+            .collect(),
+        None => match_events.to_vec(),
+    };
+
+    vec![
+        Section::new("Match overview", Priority::Stats, overview(data, goals, start)),
+        Section::new("Player stats", Priority::Stats, player_stats(&summaries)),
+        Section::new(
+            &format!("{} compared with teammates and opponents", player.unwrap_or_default()),
+            Priority::Stats,
+            player.map(|player| comparison(&summaries, player)).unwrap_or_default(),
+        ),
+        Section::new(
+            "Rotation roles (share of time as the closest, 2nd and 3rd closest teammate to the ball)",
+            Priority::Stats,
+            rotation_roles(data),
+        ),
+        Section::new("Timeline", Priority::Events, timeline(data, goals, match_events, &involved, start)),
+        Section::new(
+            "Key moments (positions in unreal units 2s before each goal)",
+            Priority::Frames,
+            key_moments(data, goals, scale, start),
+        ),
+    ]
+    .into_iter()
+    .filter(|section| !section.lines.is_empty())
+    .collect()
+}
+
+/// Every player with their team, blue first and by name within a team. A player's id
+/// is their position in this list, starting at 1.
+pub fn roster(data: &[GameState]) -> Vec<(u32, String)> {
+    let mut players: Vec<(u32, String)> = data
+        .iter()
+        .filter(|s| !s.is_ball())
+        .filter_map(|s| Some((s.team?, s.player_name.clone())))
+        .collect();
+    players.sort();
+    players.dedup();
+    players
+}
+
+/// Finds a player by name (ignoring case) or by id, see `roster`.
+pub fn find_player(data: &[GameState], name_or_id: &str) -> Result<String, Box<dyn Error>> {
+    let players = roster(data);
+    let by_id = name_or_id
+        .parse::<usize>()
+        .ok()
+        .and_then(|id| id.checked_sub(1))
+        .and_then(|index| players.get(index));
+    let by_name = players.iter().find(|(_, name)| name.eq_ignore_ascii_case(name_or_id));
+    match by_name.or(by_id) {
+        Some((_, name)) => Ok(name.clone()),
+        None => {
+            let listed: Vec<String> = players
+                .iter()
+                .enumerate()
+                .map(|(index, (team, name))| format!("{} {} ({})", index + 1, name, team_name(*team)))
+                .collect();
+            Err(format!("No player {} in this match. Players: {}", name_or_id, listed.join(", ")).into())
+        }
+    }
+}
+
+fn overview(data: &[GameState], goals: &[Goal], start: f64) -> Vec<String> {
+    let end = data.iter().map(|s| s.time).fold(start, f64::max);
+    let mut lines = vec![format!("Duration: {}", clock(end - start))];
+
+    let mut rosters: BTreeMap<u32, Vec<String>> = BTreeMap::new();
+    for (team, name) in roster(data) {
+        rosters.entry(team).or_default().push(name);
+    }
+    for (team, names) in &rosters {
+        lines.push(format!("{} team: {}", team_name(*team), names.join(", ")));
+    }
+
+    let blue = goals.iter().filter(|g| g.team == 0).count();
+    let orange = goals.iter().filter(|g| g.team == 1).count();
+    lines.push(format!("Score: Blue {} - {} Orange", blue, orange));
+    lines
+}
+
+/// What `player_stats` reports for one player.
+#[derive(Default)]
+struct PlayerSummary {
+    team: Option<u32>,
+    touches: usize,
+    shots: usize,
+    goals: usize,
+    xg: f64,
+    demos: usize,
+    avg_boost: Option<f64>,
+    avg_speed: f64,
+    supersonic: f64,
+    thirds: Option<(f64, f64, f64)>,
+}
+
+fn summaries(data: &[GameState], match_events: &[Event], scale: f64) -> BTreeMap<String, PlayerSummary> {
+    #[derive(Default)]
+    struct Totals {
+        team: Option<u32>,
+        boost_sum: u64,
+        boost_samples: u64,
+        speed_sum: f64,
+        supersonic: u64,
+        samples: u64,
+    }
+
+    let mut totals: BTreeMap<&str, Totals> = BTreeMap::new();
+    for state in data.iter().filter(|s| !s.is_ball()) {
+        if state.location_x == 0.0 && state.location_y == 0.0 && state.location_z == 0.0 {
+            continue;
+        }
+        let entry = totals.entry(state.player_name.as_str()).or_default();
+        entry.team = state.team;
+        if let Some(boost) = state.boost_percent() {
+            entry.boost_sum += boost as u64;
+            entry.boost_samples += 1;
+        }
+        let speed = (state.linear_velocity_x.powi(2) + state.linear_velocity_y.powi(2) + state.linear_velocity_z.powi(2))
+            .sqrt()
+            / scale;
+        entry.speed_sum += speed;
+        entry.samples += 1;
+        if speed >= SUPERSONIC_SPEED {
+            entry.supersonic += 1;
+        }
+    }
+
+    let mut normalized = data.to_vec();
+    plot::normalize_sides(&mut normalized);
+    let thirds: HashMap<String, stats::FieldThirds> = stats::field_thirds(&normalized)
+        .into_iter()
+        .map(|row| (row.player_name.clone(), row))
+        .collect();
+
+    totals
+        .into_iter()
+        .map(|(name, totals)| {
+            let mine = |kind: EventKind| match_events.iter().filter(move |e| e.kind == kind && e.player_name == name);
+            let shots: Vec<&Event> = mine(EventKind::Shot).collect();
+            let samples = totals.samples.max(1) as f64;
+            let summary = PlayerSummary {
+                team: totals.team,
+                touches: mine(EventKind::Touch).count(),
+                shots: shots.len(),
+                goals: shots.iter().filter(|e| e.outcome == Some(ShotOutcome::Goal)).count(),
+                xg: shots.iter().filter_map(|e| e.xg).fold(0.0, |total, xg| total + xg),
+                demos: mine(EventKind::Demo).count(),
+                avg_boost: totals
+                    .boost_sum
+                    .checked_div(totals.boost_samples)
+                    .map(|boost| boost as f64),
+                avg_speed: totals.speed_sum / samples,
+                supersonic: totals.supersonic as f64 * 100.0 / samples,
+                thirds: thirds
+                    .get(name)
+                    .map(|t| (t.defensive_third * 100.0, t.neutral_third * 100.0, t.offensive_third * 100.0)),
+            };
+            (name.to_string(), summary)
+        })
+        .collect()
+}
+
+/// The digest's player stats table: a header, then one row per player.
+pub fn player_table(data: &[GameState], match_events: &[Event]) -> Vec<String> {
+    player_stats(&summaries(data, match_events, events::position_scale(data)))
+}
+
+fn player_stats(summaries: &BTreeMap<String, PlayerSummary>) -> Vec<String> {
+    let mut lines = vec![
+        "player | team | touches | shots | goals | xG | demos | avg boost | avg speed | supersonic | def/neutral/off third"
+            .to_string(),
+    ];
+    for (name, summary) in summaries {
+        lines.push(format!(
+            "{} | {} | {} | {} | {} | {:.2} | {} | {} | {:.0} uu/s | {:.0}% | {}",
+            name,
+            summary.team.map(team_name).unwrap_or("-"),
+            summary.touches,
+            summary.shots,
+            summary.goals,
+            summary.xg,
+            summary.demos,
+            summary
+                .avg_boost
+                .map(|boost| format!("{:.0}%", boost))
+                .unwrap_or_else(|| "-".to_string()),
+            summary.avg_speed,
+            summary.supersonic,
+            summary
+                .thirds
+                .map(|(def, neutral, off)| format!("{:.0}/{:.0}/{:.0}%", def, neutral, off))
+                .unwrap_or_else(|| "-".to_string())
+        ));
+    }
+    lines
+}
+
+/// Reads one number off a player's summary, if they have it.
+type Metric = fn(&PlayerSummary) -> Option<f64>;
+
+/// `player`'s numbers next to the average of their teammates and of their opponents.
+fn comparison(summaries: &BTreeMap<String, PlayerSummary>, player: &str) -> Vec<String> {
+    let Some(mine) = summaries.get(player) else { return Vec::new() };
+    let (teammates, opponents): (Vec<&PlayerSummary>, Vec<&PlayerSummary>) = summaries
+        .iter()
+        .filter(|(name, _)| name.as_str() != player)
+        .map(|(_, summary)| summary)
+        .partition(|summary| summary.team == mine.team);
+
+    let metrics: [(&str, Metric); 10] = [
+        ("touches", |s| Some(s.touches as f64)),
+        ("shots", |s| Some(s.shots as f64)),
+        ("goals", |s| Some(s.goals as f64)),
+        ("xG", |s| Some(s.xg)),
+        ("demos", |s| Some(s.demos as f64)),
+        ("avg boost %", |s| s.avg_boost),
+        ("avg speed uu/s", |s| Some(s.avg_speed)),
+        ("supersonic %", |s| Some(s.supersonic)),
+        ("defensive third %", |s| s.thirds.map(|t| t.0)),
+        ("offensive third %", |s| s.thirds.map(|t| t.2)),
+    ];
+    let average = |group: &[&PlayerSummary], metric: Metric| {
+        let values: Vec<f64> = group.iter().filter_map(|s| metric(s)).collect();
+        if values.is_empty() {
+            "-".to_string()
+        } else {
+            format!("{:.1}", values.iter().sum::<f64>() / values.len() as f64)
+        }
+    };
+
+    let mut lines = vec![format!("stat | {} | teammates avg | opponents avg", player)];
+    for (label, metric) in metrics {
+        lines.push(format!(
+            "{} | {} | {} | {}",
+            label,
+            metric(mine).map(|value| format!("{:.1}", value)).unwrap_or_else(|| "-".to_string()),
+            average(&teammates, metric),
+            average(&opponents, metric)
+        ));
+    }
+    lines
+}
+
+/// For each team, how often each player was the closest to the ball (1st man), next
+/// closest (2nd) and so on, sampled once per second.
+fn rotation_roles(data: &[GameState]) -> Vec<String> {
+    let mut rows: Vec<&GameState> = data.iter().collect();
+    rows.sort_by(|a, b| a.time.total_cmp(&b.time));
+
+    let mut latest: HashMap<&str, &GameState> = HashMap::new();
+    let mut ball: Option<&GameState> = None;
+    let mut roles: BTreeMap<&str, (Option<u32>, Vec<usize>)> = BTreeMap::new();
+    let mut next_sample = rows.first().map(|s| s.time).unwrap_or(0.0);
+
+    for state in rows {
+        if state.time >= next_sample {
+            if let Some(ball) = ball {
+                let mut by_team: BTreeMap<u32, Vec<(&str, f64)>> = BTreeMap::new();
+                for car in latest.values() {
+                    if let Some(team) = car.team {
+                        let distance = (car.location_x - ball.location_x).hypot(car.location_y - ball.location_y);
+                        by_team.entry(team).or_default().push((car.player_name.as_str(), distance));
+                    }
+                }
+                for (team, mut cars) in by_team {
+                    cars.sort_by(|a, b| a.1.total_cmp(&b.1));
+                    for (rank, (name, _)) in cars.iter().enumerate() {
+                        let entry = roles.entry(name).or_insert((Some(team), Vec::new()));
+                        if entry.1.len() <= rank {
+                            entry.1.resize(rank + 1, 0);
+                        }
+                        entry.1[rank] += 1;
+                    }
+                }
+            }
+            next_sample = state.time + ROTATION_SAMPLE_INTERVAL;
+        }
+
+        if state.is_ball() {
+            ball = Some(state);
+        } else if !(state.location_x == 0.0 && state.location_y == 0.0 && state.location_z == 0.0) {
+            latest.insert(state.player_name.as_str(), state);
+        }
+    }
+
+    roles
+        .into_iter()
+        .map(|(name, (team, counts))| {
+            let total = counts.iter().sum::<usize>().max(1) as f64;
+            let shares: Vec<String> = counts
+                .iter()
+                .enumerate()
+                .map(|(rank, count)| format!("{} {:.0}%", ordinal(rank + 1), *count as f64 * 100.0 / total))
+                .collect();
+            format!("{} ({}): {}", name, team.map(team_name).unwrap_or("-"), shares.join(", "))
+        })
+        .collect()
+}
+
+/// Kickoffs and goals, and the shots and demos in `shown`. `match_events` is only used
+/// to find who took each kickoff.
+fn timeline(data: &[GameState], goals: &[Goal], match_events: &[Event], shown: &[Event], start: f64) -> Vec<String> {
+    let frame_times: HashMap<usize, f64> = data.iter().map(|s| (s.frame, s.time)).collect();
+    let mut entries: Vec<(usize, String)> = Vec::new();
+
+    for kickoff in find_kickoffs(data) {
+        let taker = match_events
+            .iter()
+            .find(|e| e.kind == EventKind::Touch && e.frame >= kickoff.frame)
+            .map(|e| format!(", first touch {}", e.player_name))
+            .unwrap_or_default();
+        entries.push((kickoff.frame, format!("{} kickoff{}", stamp(kickoff.frame, kickoff.time - start), taker)));
+    }
+
+    for goal in goals {
+        let time = frame_times.get(&goal.frame).copied().unwrap_or(start);
+        entries.push((
+            goal.frame,
+            format!("{} GOAL {} ({})", stamp(goal.frame, time - start), goal.player_name, team_name(goal.team)),
+        ));
+    }
+
+    for event in shown {
+        let description = match event.kind {
+            EventKind::Touch => continue,
+            EventKind::Shot => format!(
+                "shot by {}, {} (xG {:.2})",
+                event.player_name,
+                match event.outcome {
+                    Some(ShotOutcome::Goal) => "scored",
+                    Some(ShotOutcome::Saved) => "saved",
+                    _ => "missed",
+                },
+                event.xg.unwrap_or_default()
+            ),
+            EventKind::Demo => format!(
+                "{} demolished {}",
+                event.player_name,
+                event.victim_name.as_deref().unwrap_or("someone")
+            ),
+        };
+        entries.push((event.frame, format!("{} {}", stamp(event.frame, event.time - start), description)));
+    }
+
+    entries.sort_by_key(|(frame, _)| *frame);
+    entries.into_iter().map(|(_, line)| line).collect()
+}
+
+/// The ball resting on the center spot, once per kickoff.
+fn find_kickoffs(data: &[GameState]) -> Vec<&GameState> {
+    let scale = events::position_scale(data);
+    let mut balls: Vec<&GameState> = data.iter().filter(|s| s.is_ball()).collect();
+    balls.sort_by_key(|s| s.frame);
+
+    let mut kickoffs = Vec::new();
+    let mut on_spot = false;
+    for ball in balls {
+        let at_center = ball.location_x.abs() / scale < 1.0 && ball.location_y.abs() / scale < 1.0;
+        let still = ball.linear_velocity_x == 0.0 && ball.linear_velocity_y == 0.0;
+        if at_center && still {
+            if !on_spot {
+                kickoffs.push(ball);
+            }
+            on_spot = true;
+        } else {
+            on_spot = false;
+        }
+    }
+    kickoffs
+}
+
+fn key_moments(data: &[GameState], goals: &[Goal], scale: f64, start: f64) -> Vec<String> {
+    let frame_times: HashMap<usize, f64> = data.iter().map(|s| (s.frame, s.time)).collect();
+    let mut lines = Vec::new();
+
+    for goal in goals {
+        let Some(&goal_time) = frame_times.get(&goal.frame) else { continue };
+        let Some(frame) = data
+            .iter()
+            .filter(|s| s.time <= goal_time - SNAPSHOT_LEAD_TIME)
+            .max_by(|a, b| a.time.total_cmp(&b.time))
+            .map(|s| s.frame)
+        else {
+            continue;
+        };
+
+        let positions: Vec<String> = plot::snapshot_at(data, frame)
+            .into_iter()
+            .map(|s| {
+                let name = if s.is_ball() { "ball" } else { s.player_name.as_str() };
+                let boost = s.boost_percent().map(|b| format!(" {}%", b)).unwrap_or_default();
+                format!(
+                    "{} ({:.0}, {:.0}, {:.0}){}",
+                    name,
+                    s.location_x / scale,
+                    s.location_y / scale,
+                    s.location_z / scale,
+                    boost
+                )
+            })
+            .collect();
+        lines.push(format!(
+            "{} before {}'s goal: {}",
+            stamp(frame, goal_time - SNAPSHOT_LEAD_TIME - start),
+            goal.player_name,
+            positions.join("; ")
+        ));
+    }
+    lines
+}
+
+pub(crate) fn team_name(team: u32) -> &'static str {
+    if team == 0 {
+        "Blue"
+    } else {
+        "Orange"
+    }
+}
+
+fn ordinal(rank: usize) -> String {
+    match rank {
+        1 => "1st".to_string(),
+        2 => "2nd".to_string(),
+        3 => "3rd".to_string(),
+        n => format!("{}th", n),
+    }
+}
+
+pub(crate) fn clock(seconds: f64) -> String {
+    let seconds = seconds.max(0.0) as u64;
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
+
+pub(crate) fn stamp(frame: usize, seconds: f64) -> String {
+    format!("[{} f{}]", clock(seconds), frame)
+}
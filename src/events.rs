@@ -0,0 +1,351 @@
+use crate::plot::{self, GameState};
+use csv::{Reader, Writer};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::error::Error;
+use std::path::Path;
+
+/// Distance from the center of the field to each goal line, in unreal units.
+pub const GOAL_LINE_Y: f64 = 5120.0;
+/// Half the width of the goal mouth, in unreal units.
+pub const GOAL_HALF_WIDTH: f64 = 893.0;
+/// How far the goal extends behind the goal line, in unreal units.
+pub const GOAL_DEPTH: f64 = 880.0;
+/// Distance from the center of the field to each side wall, in unreal units.
+pub const FIELD_HALF_WIDTH: f64 = 4096.0;
+/// How close a car has to be to the ball to be credited with a touch, in unreal units.
+const TOUCH_RADIUS: f64 = 300.0;
+/// Minimum time between two touches by the same player, in seconds.
+const TOUCH_DEBOUNCE: f64 = 0.25;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EventKind {
+    Touch,
+    Shot,
+    Demo,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ShotOutcome {
+    Goal,
+    Saved,
+    Missed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Event {
+    pub frame: usize,
+    pub time: f64,
+    pub kind: EventKind,
+    pub player_name: String,
+    pub team: Option<u32>,
+    pub location_x: f64,
+    pub location_y: f64,
+    pub location_z: f64,
+    pub outcome: Option<ShotOutcome>,
+    pub xg: Option<f64>,
+    pub victim_name: Option<String>,
+}
+
+impl Event {
+    /// Rotates the event 180 degrees about the center of the field, see `GameState::mirror`.
+    pub fn mirror(&mut self) {
+        self.location_x = -self.location_x;
+        self.location_y = -self.location_y;
+    }
+}
+
+/// A goal as recorded in the replay header.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Goal {
+    #[serde(rename = "PlayerName")]
+    pub player_name: String,
+    #[serde(rename = "PlayerTeam")]
+    pub team: u32,
+    #[serde(rename = "Frame")]
+    pub frame: usize,
+}
+
+/// A demolition as written by `convert::parse_demos`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Demo {
+    pub frame: usize,
+    pub time: f64,
+    pub attacker_name: String,
+    #[serde(deserialize_with = "csv::invalid_option")]
+    pub attacker_team: Option<u32>,
+    pub victim_name: String,
+    pub location_x: f64,
+    pub location_y: f64,
+    pub location_z: f64,
+}
+
+/// Detects touches, shots and demolitions for a converted match and writes them to
+/// `./output/<match_guid>.events.csv`.
+pub fn detect_events(match_guid: &str) -> Result<Vec<Event>, Box<dyn Error>> {
+    let frames_csv_path = format!("./output/{}.replay.frames.json.csv", match_guid);
+    let goals_csv_path = format!("./output/{}.goals.json.csv", match_guid);
+    let demos_csv_path = format!("./output/{}.demos.csv", match_guid);
+    let events_csv_path = format!("./output/{}.events.csv", match_guid);
+
+    let data = plot::load_frames(&frames_csv_path)?;
+    let goals = if Path::new(&goals_csv_path).exists() {
+        load_goals(&goals_csv_path)?
+    } else {
+        Vec::new()
+    };
+    let demos = if Path::new(&demos_csv_path).exists() {
+        load_demos(&demos_csv_path)?
+    } else {
+        Vec::new()
+    };
+
+    let events = find_events(&data, &goals, &demos);
+    save_events(&events, &events_csv_path)?;
+
+    println!("Saved {} events: {}", events.len(), events_csv_path);
+    Ok(events)
+}
+
+/// Combines touches, shots and demolitions into a single timeline ordered by frame.
+pub fn find_events(data: &[GameState], goals: &[Goal], demos: &[Demo]) -> Vec<Event> {
+    let touches = find_touches(data);
+    let shots = find_shots(data, &touches, goals);
+
+    let mut events = touches;
+    events.extend(shots);
+    events.extend(demos.iter().map(|demo| Event {
+        frame: demo.frame,
+        time: demo.time,
+        kind: EventKind::Demo,
+        player_name: demo.attacker_name.clone(),
+        team: demo.attacker_team,
+        location_x: demo.location_x,
+        location_y: demo.location_y,
+        location_z: demo.location_z,
+        outcome: None,
+        xg: None,
+        victim_name: Some(demo.victim_name.clone()),
+    }));
+    events.sort_by_key(|event| event.frame);
+    events
+}
+
+/// Mirrors every event by an orange player so that both teams attack +Y.
+pub fn normalize_sides(events: &mut [Event]) {
+    for event in events.iter_mut().filter(|e| e.team == Some(1)) {
+        event.mirror();
+    }
+}
+
+/// Rattletrap reports locations of modern replays in hundredths of an unreal unit.
+/// Returns the factor to divide raw coordinates by to get unreal units.
+pub fn position_scale(data: &[GameState]) -> f64 {
+    let max_y = data
+        .iter()
+        .map(|s| s.location_y.abs())
+        .fold(0.0, f64::max);
+    if max_y > GOAL_LINE_Y * 2.0 {
+        100.0
+    } else {
+        1.0
+    }
+}
+
+/// The direction along the Y axis a team attacks: blue (0) attacks +Y, orange (1) attacks -Y.
+pub fn attack_direction(team: u32) -> f64 {
+    if team == 0 {
+        1.0
+    } else {
+        -1.0
+    }
+}
+
+/// Finds ball touches: the ball changes direction or speeds up while a car is within reach.
+pub fn find_touches(data: &[GameState]) -> Vec<Event> {
+    let scale = position_scale(data);
+    let mut rows: Vec<&GameState> = data.iter().collect();
+    rows.sort_by_key(|s| s.frame);
+
+    let mut latest_cars: HashMap<&str, &GameState> = HashMap::new();
+    let mut last_touch: HashMap<&str, f64> = HashMap::new();
+    let mut previous_ball: Option<&GameState> = None;
+    let mut touches = Vec::new();
+
+    for state in rows {
+        if !state.is_ball() {
+            latest_cars.insert(state.player_name.as_str(), state);
+            continue;
+        }
+
+        let Some(previous) = previous_ball.replace(state) else { continue };
+        if !is_deflection(velocity(previous), velocity(state)) {
+            continue;
+        }
+
+        let nearest = latest_cars
+            .values()
+            .filter(|car| state.frame.saturating_sub(car.frame) <= 5)
+            .map(|car| (car, distance(car, state) / scale))
+            .filter(|&(_, d)| d <= TOUCH_RADIUS)
+            .min_by(|a, b| a.1.total_cmp(&b.1));
+
+        if let Some((car, _)) = nearest {
+            let name = car.player_name.as_str();
+            if let Some(&time) = last_touch.get(name) {
+                if state.time - time < TOUCH_DEBOUNCE {
+                    continue;
+                }
+            }
+            last_touch.insert(name, state.time);
+
+            touches.push(Event {
+                frame: state.frame,
+                time: state.time,
+                kind: EventKind::Touch,
+                player_name: car.player_name.clone(),
+                team: car.team,
+                location_x: state.location_x,
+                location_y: state.location_y,
+                location_z: state.location_z,
+                outcome: None,
+                xg: None,
+                victim_name: None,
+            });
+        }
+    }
+
+    touches
+}
+
+/// Finds shots: touches in the attacking half after which the ball is heading into the
+/// opponent's goal mouth. The outcome is a goal if the shooter's team scores before anyone
+/// else touches the ball, saved if an opponent touches it next, and missed otherwise.
+pub fn find_shots(data: &[GameState], touches: &[Event], goals: &[Goal]) -> Vec<Event> {
+    let scale = position_scale(data);
+    let ball_by_frame: HashMap<usize, &GameState> = data
+        .iter()
+        .filter(|s| s.is_ball())
+        .map(|s| (s.frame, s))
+        .collect();
+
+    let mut shots = Vec::new();
+    for (index, touch) in touches.iter().enumerate() {
+        let Some(team) = touch.team else { continue };
+        let Some(ball) = ball_by_frame.get(&touch.frame) else { continue };
+
+        let direction = attack_direction(team);
+        let (vx, vy, _) = velocity(ball);
+        if vy * direction <= 0.0 || ball.location_y * direction < 0.0 {
+            continue;
+        }
+
+        let goal_y = direction * GOAL_LINE_Y * scale;
+        let time_to_goal_line = (goal_y - ball.location_y) / vy;
+        let x_at_goal_line = ball.location_x + vx * time_to_goal_line;
+        if x_at_goal_line.abs() > GOAL_HALF_WIDTH * scale {
+            continue;
+        }
+
+        let next_touch = touches[index + 1..].iter().find(|t| t.frame > touch.frame);
+        let end_frame = next_touch.map(|t| t.frame).unwrap_or(usize::MAX);
+        let scored = goals
+            .iter()
+            .any(|goal| goal.team == team && goal.frame > touch.frame && goal.frame <= end_frame);
+
+        let outcome = if scored {
+            ShotOutcome::Goal
+        } else if next_touch.is_some_and(|t| t.team.is_some_and(|t| t != team)) {
+            ShotOutcome::Saved
+        } else {
+            ShotOutcome::Missed
+        };
+
+        shots.push(Event {
+            kind: EventKind::Shot,
+            outcome: Some(outcome),
+            xg: Some(expected_goals(ball.location_x / scale, ball.location_y / scale, team)),
+            ..touch.clone()
+        });
+    }
+
+    shots
+}
+
+/// A simple expected-goals estimate from the shot location: the wider the goal mouth
+/// appears and the closer the ball is, the more likely the shot goes in.
+pub fn expected_goals(x: f64, y: f64, team: u32) -> f64 {
+    let goal_y = attack_direction(team) * GOAL_LINE_Y;
+    let distance = ((goal_y - y).powi(2) + x.powi(2)).sqrt();
+    let left = (GOAL_HALF_WIDTH - x).atan2((goal_y - y).abs());
+    let right = (-GOAL_HALF_WIDTH - x).atan2((goal_y - y).abs());
+    let opening_angle = (left - right).abs();
+
+    let xg = (1.0 - (-1.6 * opening_angle).exp()) * (-distance / 4000.0).exp();
+    xg.clamp(0.01, 0.99)
+}
+
+pub fn load_goals(file_path: &str) -> Result<Vec<Goal>, Box<dyn Error>> {
+    let mut reader = Reader::from_path(file_path)?;
+    let mut goals = Vec::new();
+    for result in reader.deserialize() {
+        goals.push(result?);
+    }
+    Ok(goals)
+}
+
+pub fn load_demos(file_path: &str) -> Result<Vec<Demo>, Box<dyn Error>> {
+    let mut reader = Reader::from_path(file_path)?;
+    let mut demos = Vec::new();
+    for result in reader.deserialize() {
+        demos.push(result?);
+    }
+    Ok(demos)
+}
+
+pub fn load_events(file_path: &str) -> Result<Vec<Event>, Box<dyn Error>> {
+    let mut reader = Reader::from_path(file_path)?;
+    let mut events = Vec::new();
+    for result in reader.deserialize() {
+        events.push(result?);
+    }
+    Ok(events)
+}
+
+pub fn save_events(events: &[Event], file_path: &str) -> Result<(), Box<dyn Error>> {
+    let mut writer = Writer::from_path(file_path)?;
+    for event in events {
+        writer.serialize(event)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+fn velocity(state: &GameState) -> (f64, f64, f64) {
+    (state.linear_velocity_x, state.linear_velocity_y, state.linear_velocity_z)
+}
+
+fn distance(a: &GameState, b: &GameState) -> f64 {
+    ((a.location_x - b.location_x).powi(2)
+        + (a.location_y - b.location_y).powi(2)
+        + (a.location_z - b.location_z).powi(2))
+    .sqrt()
+}
+
+/// True when the ball turned by more than 15 degrees or gained more than 15% speed.
+fn is_deflection(before: (f64, f64, f64), after: (f64, f64, f64)) -> bool {
+    let speed_before = (before.0.powi(2) + before.1.powi(2) + before.2.powi(2)).sqrt();
+    let speed_after = (after.0.powi(2) + after.1.powi(2) + after.2.powi(2)).sqrt();
+
+    if speed_before < f64::EPSILON {
+        return speed_after > f64::EPSILON;
+    }
+    if speed_after < f64::EPSILON {
+        return false;
+    }
+
+    let cos_angle = (before.0 * after.0 + before.1 * after.1 + before.2 * after.2) / (speed_before * speed_after);
+    cos_angle < 15f64.to_radians().cos() || speed_after > speed_before * 1.15
+}
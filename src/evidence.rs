@@ -0,0 +1,164 @@
+use crate::coaching::FrameRange;
+use crate::digest;
+use crate::plot::{self, GameState};
+use std::collections::{BTreeMap, HashSet};
+use std::error::Error;
+use std::ops::Range;
+
+/// Longest window a citation may span, in seconds; anything longer isn't a single moment.
+const MAX_WINDOW_SECONDS: f64 = 30.0;
+
+/// Checks every frame citation in `markdown` against the match and renders what it points at:
+/// a snapshot for a single frame, a GIF clip for a window. The image goes on the line
+/// below the citation, saved as `./output/<output_name>.evidence_<start>-<end>.gif` (or
+/// `.evidence_<frame>.png`). Citations outside the replay are marked as such instead.
+pub fn attach_evidence(markdown: &str, data: &[GameState], output_name: &str) -> String {
+    let mut frame_times: BTreeMap<usize, f64> = BTreeMap::new();
+    for state in data {
+        frame_times.entry(state.frame).or_insert(state.time);
+    }
+    let start = frame_times.values().copied().fold(f64::INFINITY, f64::min);
+
+    let mut rendered: HashSet<String> = HashSet::new();
+    let mut lines = Vec::new();
+    for line in markdown.lines() {
+        let found = citations(line);
+        if found.is_empty() {
+            lines.push(line.to_string());
+            continue;
+        }
+
+        let mut annotated = String::new();
+        let mut evidence = Vec::new();
+        let mut copied = 0;
+        for (span, ranges) in found {
+            annotated.push_str(&line[copied..span.end]);
+            copied = span.end;
+            for range in ranges {
+                match check(&frame_times, range) {
+                    Ok((from, to)) => {
+                        let label = if range.start_frame == range.end_frame {
+                            format!("frame {} ({})", range.start_frame, digest::clock(from - start))
+                        } else {
+                            format!(
+                                "frames {}-{} ({}-{})",
+                                range.start_frame,
+                                range.end_frame,
+                                digest::clock(from - start),
+                                digest::clock(to - start)
+                            )
+                        };
+                        match render(data, range, output_name, &mut rendered) {
+                            Ok(file_name) => evidence.push(format!("![{}]({})", label, file_name)),
+                            Err(e) => eprintln!("Error rendering evidence for {}: {}", label, e),
+                        }
+                    }
+                    Err(reason) => annotated.push_str(&format!(" _(citation not in this replay: {})_", reason)),
+                }
+            }
+        }
+        annotated.push_str(&line[copied..]);
+        lines.push(annotated);
+
+        let indent = list_indent(line);
+        lines.extend(evidence.into_iter().map(|image| format!("{}{}", indent, image)));
+    }
+
+    let mut text = lines.join("\n");
+    if markdown.ends_with('\n') {
+        text.push('\n');
+    }
+    text
+}
+
+/// The citations in `text` with their byte spans. Citations look like `[frames 1200-1350]`,
+/// `[frame 1200]` or `[frames 1200-1350, 2400-2500]`.
+fn citations(text: &str) -> Vec<(Range<usize>, Vec<FrameRange>)> {
+    let mut found = Vec::new();
+    let mut offset = 0;
+    while let Some(open) = text[offset..].find('[') {
+        let open = offset + open;
+        let Some(close) = text[open..].find(']') else { break };
+        let close = open + close;
+        if let Some(ranges) = parse_ranges(&text[open + 1..close]) {
+            found.push((open..close + 1, ranges));
+        }
+        offset = open + 1;
+    }
+    found
+}
+
+/// Parses `frames 1200-1350, 2400` without the brackets.
+fn parse_ranges(citation: &str) -> Option<Vec<FrameRange>> {
+    let lower = citation.trim().to_lowercase();
+    let list = lower.strip_prefix("frames ").or_else(|| lower.strip_prefix("frame "))?;
+    list.split(',')
+        .map(|range| {
+            let range = range.trim();
+            let (start, end) = range.split_once('-').unwrap_or((range, range));
+            Some(FrameRange {
+                start_frame: start.trim().parse().ok()?,
+                end_frame: end.trim().parse().ok()?,
+            })
+        })
+        .collect()
+}
+
+/// The match time at both ends of `range`, if it is a window of this replay.
+fn check(frame_times: &BTreeMap<usize, f64>, range: FrameRange) -> Result<(f64, f64), String> {
+    let (Some((&first, _)), Some((&last, _))) = (frame_times.first_key_value(), frame_times.last_key_value()) else {
+        return Err("no frames".to_string());
+    };
+    if range.start_frame > range.end_frame {
+        return Err(format!("frame {} comes after {}", range.start_frame, range.end_frame));
+    }
+    if range.start_frame < first || range.end_frame > last {
+        return Err(format!("the replay has frames {} to {}", first, last));
+    }
+
+    let time_at = |frame: usize| frame_times.range(..=frame).next_back().map(|(_, time)| *time).unwrap_or_default();
+    let (from, to) = (time_at(range.start_frame), time_at(range.end_frame));
+    if to - from > MAX_WINDOW_SECONDS {
+        return Err(format!("{:.0}s is longer than {:.0}s", to - from, MAX_WINDOW_SECONDS));
+    }
+    Ok((from, to))
+}
+
+/// Draws the evidence for `range` unless this run already has, returning the file name.
+fn render(
+    data: &[GameState],
+    range: FrameRange,
+    output_name: &str,
+    rendered: &mut HashSet<String>,
+) -> Result<String, Box<dyn Error>> {
+    let file_name = if range.start_frame == range.end_frame {
+        format!("{}.evidence_{}.png", output_name, range.start_frame)
+    } else {
+        format!("{}.evidence_{}-{}.gif", output_name, range.start_frame, range.end_frame)
+    };
+    if rendered.contains(&file_name) {
+        return Ok(file_name);
+    }
+
+    let output_file = format!("./output/{}", file_name);
+    if range.start_frame == range.end_frame {
+        plot::plot_snapshot(data, range.start_frame, &output_file, true)?;
+    } else {
+        plot::plot_clip(data, range.start_frame, range.end_frame, &output_file)?;
+    }
+    println!("Evidence saved to: {}", output_file);
+    rendered.insert(file_name.clone());
+    Ok(file_name)
+}
+
+/// Indentation that keeps an image inside the list item on `line`, if it is one.
+fn list_indent(line: &str) -> String {
+    let content = line.trim_start();
+    let leading = &line[..line.len() - content.len()];
+    let marker = match content.split_once(". ") {
+        Some((number, _)) if !number.is_empty() && number.chars().all(|c| c.is_ascii_digit()) => number.len() + 2,
+        _ if content.starts_with("- ") || content.starts_with("* ") => 2,
+        _ => 0,
+    };
+    format!("{}{}", leading, " ".repeat(marker))
+}
@@ -31,32 +31,29 @@ pub fn extract_replay(input: &str) -> io::Result<String> {
             .status()?;
 
         if !wget_status.success() {
-            return Err(Error::new(
-                ErrorKind::Other,
+            return Err(Error::other(
                 "Failed to download Rattletrap.",
             ));
         }
 
         // Extract the tar.gz
         let tar_status = Command::new("tar")
-            .args(&["-xzf", tar_file])
+            .args(["-xzf", tar_file])
             .status()?;
 
         if !tar_status.success() {
-            return Err(Error::new(
-                ErrorKind::Other,
+            return Err(Error::other(
                 "Failed to extract Rattletrap.",
             ));
         }
 
         // Make the binary executable
         let chmod_status = Command::new("chmod")
-            .args(&["+x", rattletrap_name])
+            .args(["+x", rattletrap_name])
             .status()?;
 
         if !chmod_status.success() {
-            return Err(Error::new(
-                ErrorKind::Other,
+            return Err(Error::other(
                 "Failed to set executable permissions for Rattletrap.",
             ));
         }
@@ -102,7 +99,7 @@ pub fn extract_replay(input: &str) -> io::Result<String> {
                     "Failed to extract replay data. Error: {}",
                     String::from_utf8_lossy(&output.stderr)
                 );
-                return Err(io::Error::new(io::ErrorKind::Other, "Rattletrap failed"));
+                return Err(io::Error::other("Rattletrap failed"));
             }
         }
         Err(e) => {
@@ -130,8 +127,11 @@ pub fn extract_replay(input: &str) -> io::Result<String> {
     Ok(match_guid)
 }
 
+/// The sections `parse_replay` writes, each to `./output/<match_guid>.<section>.json`.
+pub const SECTIONS: [&str; 5] = ["header", "goals", "player_stats", "highlights", "replay.frames"];
 
-fn parse_replay(data: Value, match_guid: String) -> Result<(), Box<dyn std::error::Error>> {
+/// Splits rattletrap's JSON for a replay into the files of `SECTIONS`.
+pub fn parse_replay(data: Value, match_guid: String) -> Result<(), Box<dyn std::error::Error>> {
 
     let output_dir = "output";
     fs::create_dir_all(output_dir)?;
@@ -2,4 +2,15 @@ pub mod extract;
 pub mod convert;
 pub mod query;
 pub mod ai;
-pub mod plot;
\ No newline at end of file
+pub mod plot;
+pub mod events;
+pub mod viewer;
+pub mod stats;
+pub mod coaching;
+pub mod digest;
+pub mod prompt;
+pub mod evidence;
+pub mod agent;
+pub mod chat;
+pub mod templates;
+pub mod profile;
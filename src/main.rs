@@ -1,8 +1,19 @@
 mod extract;
 mod convert;
 mod plot;
+mod events;
+mod viewer;
+mod stats;
 mod query;
 mod ai;
+mod coaching;
+mod digest;
+mod prompt;
+mod evidence;
+mod agent;
+mod chat;
+mod templates;
+mod profile;
 
 use std::env;
 use std::fs;
@@ -10,7 +21,6 @@ use std::process;
 use serde_json::Value;
 use std::io::Write;
 
-use tokio;
 
 #[tokio::main]
 async fn main() {
@@ -19,11 +29,27 @@ async fn main() {
     if args.len() < 2 {
         println!("Usage: rattlebrain <command> [options]");
         println!("Commands:");
-        println!(" analysis <path/some.replay> - Analyze replay data. (runs extract->convert->plot->query)");
-        println!(" query <match_guid> [focus] - Query AI for replay insights.");
+        println!(" analysis <path/some.replay> [AI options] - Analyze replay data. (runs extract->convert->events->plot->query)");
+        println!(" query <match_guid> [focus] [AI options] - Query AI for replay insights. focus: all (default), strategy, mechanics, decision_making, or <focus>.md in ./templates (see templates=<dir>).");
+        println!(" chat <match_guid> [provider] [AI options] - Ask follow-up questions about a match (saved to ./output/<match_guid>.chat.md).");
+        println!(" providers [AI options] - List the configured AI providers, in query order.");
         println!(" extract <path/some.replay> - Extract replay data to CSV.");
         println!(" convert <path/some.replay.json> - Convert replay JSON to processed data.");
-        println!(" plot <<path/some.replay.csv> - Plot replay data.");
+        println!(" events <match_guid> - Detect touches, shots and demolitions from converted data.");
+        println!(" stats <match_guid> [<match_guid> ...] - Time spent in each third of the field, combined across matches.");
+        println!(" plot <<path/some.replay.csv> [--from <seconds>] [--to <seconds>] [--normalize] [--player <name>] - Plot replay data.");
+        println!(" viewer <match_guid> - Export an interactive HTML replay viewer.");
+        println!(" plot snapshot <match_guid> <frame> [--velocity] - Plot every car and the ball at a single frame.");
+        println!("AI options:");
+        println!(" --config <file.json> - Provider settings (default: $RATTLEBRAIN_CONFIG or ./rattlebrain.json).");
+        println!(" --set <provider>.<field>=<value> - Override a provider setting, e.g. --set openai.temperature=0.2");
+        println!(" --set profile.<rank|mode|goals>=<value> - Pitch the coaching at a rank (e.g. Diamond), with goals separated by ';'. The mode (1v1, 2v2, 3v3) comes from the replay, else from profile.mode.");
+        println!(" --player <name|id> - Coach one player (id = position in the roster, blue first); feedback goes to ./output/<match_guid>.<player>.feedback.md");
+        println!(" --agent - Let the providers investigate the match with tools before answering (transcript in ./output/<match_guid>.agent.md)");
+        println!(" --no-cache - Don't reuse or save answers (cached in ./output/cache for a week, see cache_ttl).");
+        println!(" --refresh - Ask the providers again and replace the cached answers.");
+        println!(" --images - Attach the match's plots for providers that accept images (not with --structured or --agent).");
+        println!(" --structured - Ask for JSON coaching (strengths, weaknesses, drills) saved to ./output/<match_guid>.coaching.json");
         return;
     }
 
@@ -77,14 +103,115 @@ async fn main() {
                 Err(e) => eprintln!("Error converting replay: {}", e),
             }
         }
+        "events" => {
+            if args.len() < 3 {
+                println!("Usage: rattlebrain events <match_guid>");
+                return;
+            }
+            let match_guid = &args[2];
+            println!("Detecting match events...");
+            match events::detect_events(match_guid) {
+                Ok(_) => println!("Events command completed successfully."),
+                Err(e) => eprintln!("Error detecting events: {}", e),
+            }
+        }
+        "providers" => {
+            let config = load_config(&args);
+            let registry = ai::registry::Registry::from_config(&config);
+            if registry.is_empty() {
+                println!("No AI providers configured.");
+            }
+            for (index, provider) in registry.providers().iter().enumerate() {
+                let capabilities = provider.capabilities();
+                println!(
+                    "{}. {} (system prompt: {}, images: {}, tools: {}, structured output: {}, streaming: {})",
+                    index + 1,
+                    provider.name(),
+                    capabilities.system_prompt,
+                    capabilities.images,
+                    capabilities.tools,
+                    capabilities.structured_output,
+                    capabilities.streaming
+                );
+            }
+        }
+        "stats" => {
+            if args.len() < 3 {
+                println!("Usage: rattlebrain stats <match_guid> [<match_guid> ...]");
+                return;
+            }
+            let mut matches = Vec::new();
+            for match_guid in &args[2..] {
+                match stats::positioning(match_guid) {
+                    Ok(thirds) => matches.push(thirds),
+                    Err(e) => eprintln!("Error computing positioning for {}: {}", match_guid, e),
+                }
+            }
+
+            println!("{:<24} {:>10} {:>10} {:>10}", "Player", "Defensive", "Neutral", "Offensive");
+            for row in stats::aggregate_field_thirds(&matches) {
+                println!(
+                    "{:<24} {:>9.1}% {:>9.1}% {:>9.1}%",
+                    row.player_name,
+                    row.defensive_third * 100.0,
+                    row.neutral_third * 100.0,
+                    row.offensive_third * 100.0
+                );
+            }
+        }
+        "viewer" => {
+            if args.len() < 3 {
+                println!("Usage: rattlebrain viewer <match_guid>");
+                return;
+            }
+            let match_guid = &args[2];
+            println!("Exporting replay viewer...");
+            match viewer::export_viewer(match_guid) {
+                Ok(path) => println!("Viewer command completed successfully: {}", path),
+                Err(e) => eprintln!("Error exporting viewer: {}", e),
+            }
+        }
+        "plot" if args.get(2).map(String::as_str) == Some("snapshot") => {
+            if args.len() < 5 {
+                println!("Usage: rattlebrain plot snapshot <match_guid> <frame> [--velocity]");
+                return;
+            }
+            let match_guid = &args[3];
+            let frame: usize = match args[4].parse() {
+                Ok(frame) => frame,
+                Err(_) => {
+                    eprintln!("Invalid frame: {}", args[4]);
+                    process::exit(1);
+                }
+            };
+            let show_velocity = args.iter().any(|arg| arg == "--velocity");
+
+            let csv_file = format!("./output/{}.replay.frames.json.csv", match_guid);
+            let snapshot_file = format!("./output/{}.snapshot_{}.png", match_guid, frame);
+            println!("Plotting snapshot at frame {}...", frame);
+            match plot::load_frames(&csv_file)
+                .and_then(|data| plot::plot_snapshot(&data, frame, &snapshot_file, show_velocity))
+            {
+                Ok(_) => println!("Snapshot saved to: {}", snapshot_file),
+                Err(e) => eprintln!("Error plotting snapshot: {}", e),
+            }
+        }
         "plot" => {
             if args.len() < 3 {
-                println!("Usage: rattlebrain plot <csv>");
+                println!("Usage: rattlebrain plot <csv> [--from <seconds>] [--to <seconds>] [--normalize] [--player <name>]");
                 return;
             }
             let csv_file = &args[2];
+            let mut options = plot::PlotOptions::from_env();
+            options.start_time = flag_value(&args, "--from").and_then(|v| v.parse().ok());
+            options.end_time = flag_value(&args, "--to").and_then(|v| v.parse().ok());
+            options.normalize |= args.iter().any(|arg| arg == "--normalize");
+            if let Some(player_name) = flag_value(&args, "--player") {
+                options.player_name = Some(player_name.to_string());
+            }
+
             println!("Plotting CSV...");
-            match plot::plot_csv(csv_file) {
+            match plot::plot_csv_with_options(csv_file, &options) {
                 Ok(_response) => println!("Plot command completed successfully: "),
                 Err(e) => eprintln!("Error plotting: {}", e),
             }
@@ -112,81 +239,52 @@ async fn main() {
             };
 
             // Step 2: Convert 
-            let replay_file = format!("./output/{}.replay.frames.json", match_guid);
-            let player_statistics_file = format!("./output/{}.player_stats.json", match_guid);
-            let goals_file = format!("./output/{}.goals.json", match_guid);
-            let highlights_file = format!("./output/{}.highlights.json", match_guid);
-
-            // Process each file
-            for file in [replay_file, player_statistics_file, goals_file, highlights_file].iter() {
-                process_conversion(file);
+            if let Err(e) = convert::convert_match(&match_guid) {
+                eprintln!("Error during conversion: {}", e);
+                process::exit(1);
+            }
+            delete_extracted_files(&match_guid);
+
+            // Step 3: Events
+            println!("Detecting match events...");
+            if let Err(e) = events::detect_events(&match_guid) {
+                eprintln!("Error detecting events: {}", e);
             }
-            delete_json_files("./output");
 
-            // Step 3: Plot
+            if let Err(e) = stats::positioning(&match_guid) {
+                eprintln!("Error computing positioning: {}", e);
+            }
+
+            let player = player_arg(&args, &match_guid);
+
+            // Step 4: Plot
             let csv_file = format!("./output/{}.replay.frames.json.csv",match_guid);
+            let mut plot_options = plot::PlotOptions::from_env();
+            if player.is_some() {
+                plot_options.player_name = player.clone();
+            }
             println!("Plotting data from csv: {}", csv_file);
-            if let Err(e) = plot::plot_csv(&csv_file) {
+            if let Err(e) = plot::plot_csv_with_options(&csv_file, &plot_options) {
                 eprintln!("Error during plotting: {}", e);
                 process::exit(1);
             }
 
-            // Step 4: AI
+            // Step 5: AI
             let focus =  "all".to_string();
+            let config = load_config(&args);
+            let structured = args.iter().any(|arg| arg == "--structured");
+            let agent = args.iter().any(|arg| arg == "--agent");
 
             println!("Querying AI for insights...");
-            match query::query_ai(&match_guid, &focus).await {
-                Ok(response) => {
-                    let feedback_file_path = format!("./output/{}.feedback.md", match_guid);
-
-                    // Save the AI response to the feedback file
-                    match fs::write(&feedback_file_path, &response) {
-                        Ok(_) => {
-                            println!("AI feedback saved to: {}", feedback_file_path);
-
-                            // Append image links to the feedback file
-                            let image_pattern = format!("./output/{}*.png", match_guid);
-                            let image_paths = match glob::glob(&image_pattern) {
-                                Ok(paths) => paths.filter_map(Result::ok).collect::<Vec<_>>(),
-                                Err(e) => {
-                                    eprintln!("Error finding images: {}", e);
-                                    Vec::new()
-                                }
-                            };
-
-                            if !image_paths.is_empty() {
-                                let mut image_markdown = String::new();
-                                for image_path in image_paths {
-                                    let image_file_name = image_path.file_name().unwrap_or_default().to_string_lossy();
-                                    image_markdown.push_str(&format!(
-                                        "![{}]({})\n",
-                                        match_guid,
-                                        image_file_name
-                                    ));
-                                }
-
-                            // Open the file in append mode and add the image markdown
-                            let mut feedback_file = match fs::OpenOptions::new()
-                                .append(true)
-                                .open(&feedback_file_path)
-                            {
-                                Ok(file) => file,
-                                Err(e) => {
-                                    eprintln!("Failed to open feedback file for appending: {}", e);
-                                    return;
-                                }
-                            };
-
-                            if let Err(e) = feedback_file.write_all(image_markdown.as_bytes()) {
-                                eprintln!("Failed to append images to feedback: {}", e);
-                            } else {
-                                println!("Images appended to feedback file.");
-                            }
-                            }
-                        }
-                        Err(e) => eprintln!("Failed to save AI feedback: {}", e),
-                    }
-                }
+            let response = if agent {
+                query::query_agent(&match_guid, &focus, player.as_deref(), &config).await
+            } else if structured {
+                query::query_coaching(&match_guid, &focus, player.as_deref(), &config).await
+            } else {
+                query::query_ai(&match_guid, &focus, player.as_deref(), &config).await
+            };
+            match response {
+                Ok(response) => save_feedback(&match_guid, player.as_deref(), &response),
                 Err(e) => eprintln!("Error querying AI: {}", e),
             }
         }
@@ -198,64 +296,42 @@ async fn main() {
 
             let match_guid = &args[2];
             // Set focus to "all" if not provided, otherwise pass the provided value
-            let focus = if args.len() > 3 { &args[3] } else { "all" };
+            let focus = match args.get(3) {
+                Some(focus) if !focus.starts_with("--") => focus.as_str(),
+                _ => "all",
+            };
+            let config = load_config(&args);
+            let structured = args.iter().any(|arg| arg == "--structured");
+            let agent = args.iter().any(|arg| arg == "--agent");
+            let player = player_arg(&args, match_guid);
 
             println!("Querying AI for insights...");
-            match query::query_ai(match_guid, focus).await {
-                Ok(response) => {
-                    let feedback_file_path = format!("./output/{}.feedback.md", match_guid);
-
-                    // Save the AI response to the feedback file
-                    match fs::write(&feedback_file_path, &response) {
-                        Ok(_) => {
-                            println!("AI feedback saved to: {}", feedback_file_path);
-
-                            // Append image links to the feedback file
-                            let image_pattern = format!("./output/{}*.png", match_guid);
-                            let image_paths = match glob::glob(&image_pattern) {
-                                Ok(paths) => paths.filter_map(Result::ok).collect::<Vec<_>>(),
-                                Err(e) => {
-                                    eprintln!("Error finding images: {}", e);
-                                    Vec::new()
-                                }
-                            };
-
-                            if !image_paths.is_empty() {
-                                let mut image_markdown = String::new();
-                                for image_path in image_paths {
-                                    let image_file_name = image_path.file_name().unwrap_or_default().to_string_lossy();
-                                    image_markdown.push_str(&format!(
-                                        "![{}]({})\n",
-                                        match_guid,
-                                        image_file_name
-                                    ));
-                                }
-
-                            // Open the file in append mode and add the image markdown
-                            let mut feedback_file = match fs::OpenOptions::new()
-                                .append(true)
-                                .open(&feedback_file_path)
-                            {
-                                Ok(file) => file,
-                                Err(e) => {
-                                    eprintln!("Failed to open feedback file for appending: {}", e);
-                                    return;
-                                }
-                            };
-
-                            if let Err(e) = feedback_file.write_all(image_markdown.as_bytes()) {
-                                eprintln!("Failed to append images to feedback: {}", e);
-                            } else {
-                                println!("Images appended to feedback file.");
-                            }
-                            }
-                        }
-                        Err(e) => eprintln!("Failed to save AI feedback: {}", e),
-                    }
-                }
+            let response = if agent {
+                query::query_agent(match_guid, focus, player.as_deref(), &config).await
+            } else if structured {
+                query::query_coaching(match_guid, focus, player.as_deref(), &config).await
+            } else {
+                query::query_ai(match_guid, focus, player.as_deref(), &config).await
+            };
+            match response {
+                Ok(response) => save_feedback(match_guid, player.as_deref(), &response),
                 Err(e) => eprintln!("Error querying AI: {}", e),
             }
         }
+        "chat" => {
+            if args.len() < 3 {
+                println!("Usage: rattlebrain chat <match_guid> [provider]");
+                return;
+            }
+            let match_guid = &args[2];
+            let provider = args.get(3).map(String::as_str).filter(|arg| !arg.starts_with("--"));
+            let config = load_config(&args);
+            let player = player_arg(&args, match_guid);
+
+            if let Err(e) = chat::chat(match_guid, provider, player.as_deref(), &config).await {
+                eprintln!("Error chatting about match: {}", e);
+            }
+        }
         _ => {
             println!("Unknown command: {}", command);
             println!("Usage: rattlebrain <command> [options]");
@@ -263,49 +339,137 @@ async fn main() {
     }
 }
 
-fn process_conversion(file_path: &str) {
-    println!("Converting replay data to CSV: {}", file_path);
+/// Returns the value following `flag` on the command line, if present.
+fn flag_value<'a>(args: &'a [String], flag: &str) -> Option<&'a str> {
+    args.iter()
+        .position(|arg| arg == flag)
+        .and_then(|index| args.get(index + 1))
+        .map(String::as_str)
+}
+
+/// Every value following `flag` on the command line, for flags that may repeat.
+fn flag_values<'a>(args: &'a [String], flag: &str) -> Vec<&'a str> {
+    args.windows(2)
+        .filter(|pair| pair[0] == flag)
+        .map(|pair| pair[1].as_str())
+        .collect()
+}
 
-    let file_content = match fs::read_to_string(file_path) {
-        Ok(content) => content,
+/// Loads the AI provider config from `--config <file>` and `--set provider.field=value`
+/// flags, exiting with the validation error if it is invalid. `--no-cache` and `--refresh`
+/// take precedence over the cache setting.
+fn load_config(args: &[String]) -> ai::config::Config {
+    let mut config = match ai::config::Config::load(flag_value(args, "--config"), &flag_values(args, "--set")) {
+        Ok(config) => config,
         Err(e) => {
-            eprintln!("Error reading input file: {}", e);
+            eprintln!("Error loading AI config: {}", e);
             process::exit(1);
         }
     };
+    if args.iter().any(|arg| arg == "--no-cache") {
+        config.cache = ai::cache::CacheMode::Off;
+    } else if args.iter().any(|arg| arg == "--refresh") {
+        config.cache = ai::cache::CacheMode::Refresh;
+    }
+    if args.iter().any(|arg| arg == "--images") {
+        config.attach_images = true;
+    }
+    config
+}
 
-    let json_data: Value = match serde_json::from_str(&file_content) {
-        Ok(data) => data,
+/// Resolves `--player <name|id>` against the players of a converted match, exiting if
+/// no such player played.
+fn player_arg(args: &[String], match_guid: &str) -> Option<String> {
+    let name_or_id = flag_value(args, "--player")?;
+    let csv_file = format!("./output/{}.replay.frames.json.csv", match_guid);
+    match plot::load_frames(&csv_file).and_then(|data| digest::find_player(&data, name_or_id)) {
+        Ok(player) => Some(player),
         Err(e) => {
-            eprintln!("Error parsing JSON: {}", e);
+            eprintln!("Error selecting player: {}", e);
             process::exit(1);
         }
+    }
+}
+
+/// Writes the AI response to `./output/<match_guid>.feedback.md`, or
+/// `./output/<match_guid>.<player>.feedback.md` for a single player, with evidence for the
+/// frames it cites (see `evidence::attach_evidence`) and followed by links to the match plots.
+fn save_feedback(match_guid: &str, player: Option<&str>, response: &str) {
+    let output_name = query::output_name(match_guid, player);
+    let feedback_file_path = format!("./output/{}.feedback.md", output_name);
+
+    // Render the moments the coaches cited next to the lines citing them
+    let csv_file = format!("./output/{}.replay.frames.json.csv", match_guid);
+    let response = match plot::load_frames(&csv_file) {
+        Ok(data) => evidence::attach_evidence(response, &data, &output_name),
+        Err(e) => {
+            eprintln!("Skipping evidence for cited frames: {}", e);
+            response.to_string()
+        }
+    };
+
+    // Save the AI response to the feedback file
+    if let Err(e) = fs::write(&feedback_file_path, response) {
+        eprintln!("Failed to save AI feedback: {}", e);
+        return;
+    }
+    println!("AI feedback saved to: {}", feedback_file_path);
+
+    // Append image links to the feedback file
+    let image_pattern = format!("./output/{}*.png", match_guid);
+    let image_paths = match glob::glob(&image_pattern) {
+        Ok(paths) => paths
+            .filter_map(Result::ok)
+            .filter(|path| !path.to_string_lossy().contains(".evidence_"))
+            .collect::<Vec<_>>(),
+        Err(e) => {
+            eprintln!("Error finding images: {}", e);
+            Vec::new()
+        }
     };
 
-    if let Err(e) = convert::convert_replay(json_data, file_path) {
-        eprintln!("Error during conversion: {}", e);
-        process::exit(1);
+    if image_paths.is_empty() {
+        return;
     }
 
-    println!("Conversion completed successfully for file: {}", file_path);
+    let mut image_markdown = String::new();
+    for image_path in image_paths {
+        let image_file_name = image_path.file_name().unwrap_or_default().to_string_lossy();
+        image_markdown.push_str(&format!(
+            "![{}]({})\n",
+            plot::label(&image_file_name),
+            image_file_name
+        ));
+    }
+
+    // Open the file in append mode and add the image markdown
+    let mut feedback_file = match fs::OpenOptions::new()
+        .append(true)
+        .open(&feedback_file_path)
+    {
+        Ok(file) => file,
+        Err(e) => {
+            eprintln!("Failed to open feedback file for appending: {}", e);
+            return;
+        }
+    };
+
+    if let Err(e) = feedback_file.write_all(image_markdown.as_bytes()) {
+        eprintln!("Failed to append images to feedback: {}", e);
+    } else {
+        println!("Images appended to feedback file.");
+    }
 }
 
-fn delete_json_files(output_dir: &str) {
-    match fs::read_dir(output_dir) {
-        Ok(entries) => {
-            for entry in entries {
-                if let Ok(entry) = entry {
-                    let path = entry.path();
-                    if path.extension().and_then(|ext| ext.to_str()) == Some("json") {
-                        if let Err(e) = fs::remove_file(&path) {
-                            eprintln!("Failed to delete file {}: {}", path.display(), e);
-                        } else {
-                            println!("Deleted file: {}", path.display());
-                        }
-                    }
-                }
-            }
+/// Deletes the JSON files `extract` wrote for a match once they are converted, leaving the
+/// other outputs, such as the coaching report, alone.
+fn delete_extracted_files(match_guid: &str) {
+    for section in extract::SECTIONS {
+        let path = format!("./output/{}.{}.json", match_guid, section);
+        if let Err(e) = fs::remove_file(&path) {
+            eprintln!("Failed to delete file {}: {}", path, e);
+        } else {
+            println!("Deleted file: {}", path);
         }
-        Err(e) => eprintln!("Failed to read directory {}: {}", output_dir, e),
     }
 }
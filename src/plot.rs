@@ -1,32 +1,92 @@
+use crate::events::{self, Event, EventKind, ShotOutcome};
 use serde::Deserialize;
 use std::collections::HashSet;
+use std::path::Path;
 use std::env;
 use std::error::Error;
 use csv::Reader;
+use plotters::coord::Shift;
 use plotters::prelude::*;
 use std::collections::HashMap;
 
-#[derive(Debug, Deserialize)]
+/// Size of snapshot diagrams and clips, in pixels.
+const SNAPSHOT_SIZE: (u32, u32) = (900, 1100);
+/// Match time between two images of a clip, in seconds.
+const CLIP_STEP: f64 = 0.25;
+/// Clips stop after this many images, 15 seconds at `CLIP_STEP`.
+const MAX_CLIP_IMAGES: usize = 60;
+
+#[derive(Debug, Clone, Deserialize)]
 pub struct GameState {
-    // time: f64,
-    team: Option<u32>,
-    player_name: String,
-    location_x: f64,
-    location_y: f64,
-    // location_z: f64,
-    // rotation_x: f64,
-    // rotation_y: f64,
-    // rotation_z: f64,
-    // rotation_w: f64,
+    pub frame: usize,
+    pub time: f64,
+    #[serde(deserialize_with = "csv::invalid_option")]
+    pub team: Option<u32>,
+    pub player_name: String,
+    #[serde(deserialize_with = "csv::invalid_option")]
+    pub boost: Option<u32>,
+    pub location_x: f64,
+    pub location_y: f64,
+    pub location_z: f64,
+    pub rotation_x: f64,
+    pub rotation_y: f64,
+    pub rotation_z: f64,
+    pub rotation_w: f64,
     // angular_velocity_x: f64,
     // angular_velocity_y: f64,
     // angular_velocity_z: f64,
-    // linear_velocity_x: f64,
-    // linear_velocity_y: f64,
-    // linear_velocity_z: f64,
+    pub linear_velocity_x: f64,
+    pub linear_velocity_y: f64,
+    pub linear_velocity_z: f64,
 }
 
-pub fn plot_csv(file_path: &str) -> Result<Vec<GameState>, Box<dyn Error>> {
+impl GameState {
+    pub fn is_ball(&self) -> bool {
+        self.player_name.is_empty() || self.player_name.to_lowercase() == "_ball_"
+    }
+
+    /// The horizontal direction the car's nose points, from its rotation quaternion.
+    /// Rows without a valid quaternion (such as spawn rows) have no facing.
+    pub fn facing(&self) -> Option<(f64, f64)> {
+        let (x, y, z, w) = (self.rotation_x, self.rotation_y, self.rotation_z, self.rotation_w);
+        let norm = (x * x + y * y + z * z + w * w).sqrt();
+        if (norm - 1.0).abs() > 0.1 {
+            return None;
+        }
+
+        let forward_x = 1.0 - 2.0 * (y * y + z * z);
+        let forward_y = 2.0 * (x * y + w * z);
+        let length = (forward_x * forward_x + forward_y * forward_y).sqrt();
+        if length < f64::EPSILON {
+            return None;
+        }
+        Some((forward_x / length, forward_y / length))
+    }
+
+    /// Rotates the state 180 degrees about the center of the field, mirroring Y (and X)
+    /// so that an orange player appears to attack +Y like blue does.
+    pub fn mirror(&mut self) {
+        self.location_x = -self.location_x;
+        self.location_y = -self.location_y;
+        self.linear_velocity_x = -self.linear_velocity_x;
+        self.linear_velocity_y = -self.linear_velocity_y;
+
+        // Pre-multiply by a half turn about the vertical axis
+        let (x, y, z, w) = (self.rotation_x, self.rotation_y, self.rotation_z, self.rotation_w);
+        self.rotation_x = -y;
+        self.rotation_y = x;
+        self.rotation_z = w;
+        self.rotation_w = -z;
+    }
+
+    /// Boost as a percentage of a full tank.
+    pub fn boost_percent(&self) -> Option<u32> {
+        self.boost.map(|boost| boost * 100 / 255)
+    }
+}
+
+/// Reads the frames CSV produced by `convert` into memory.
+pub fn load_frames(file_path: &str) -> Result<Vec<GameState>, Box<dyn Error>> {
     let mut reader = Reader::from_path(file_path)?;
     let mut data = Vec::new();
 
@@ -35,6 +95,63 @@ pub fn plot_csv(file_path: &str) -> Result<Vec<GameState>, Box<dyn Error>> {
         data.push(record);
     }
 
+    Ok(data)
+}
+
+/// Mirrors every orange row so that both teams attack +Y, making offensive and defensive
+/// regions line up between teams. The ball has no team and is left as is.
+pub fn normalize_sides(data: &mut [GameState]) {
+    for state in data.iter_mut().filter(|s| s.team == Some(1)) {
+        state.mirror();
+    }
+}
+
+/// Options for `plot_csv_with_options`.
+#[derive(Debug, Clone, Default)]
+pub struct PlotOptions {
+    /// Limit player-specific plots to this player.
+    pub player_name: Option<String>,
+    /// Only draw the ball trajectory from this match time, in seconds since the first frame
+    /// like the times in the digest, agent transcripts and feedback.
+    pub start_time: Option<f64>,
+    /// Only draw the ball trajectory up to this match time, in seconds since the first frame.
+    pub end_time: Option<f64>,
+    /// Mirror orange so both teams attack the same direction (see `normalize_sides`).
+    pub normalize: bool,
+}
+
+impl PlotOptions {
+    /// Reads the player name from `PLAYER_NAME` and side normalization from `NORMALIZE_SIDES`.
+    pub fn from_env() -> Self {
+        PlotOptions {
+            player_name: env::var("PLAYER_NAME").ok(),
+            normalize: env::var("NORMALIZE_SIDES").is_ok_and(|v| v == "1" || v == "true"),
+            ..Default::default()
+        }
+    }
+
+    fn in_window(&self, time: f64) -> bool {
+        self.start_time.is_none_or(|start| time >= start) && self.end_time.is_none_or(|end| time <= end)
+    }
+}
+
+/// What a plot shows, from its file name: `heatmap` for `<csv>.png`, otherwise the suffix
+/// after the CSV name, e.g. `ball side` for `<csv>_ball_side.png`.
+pub fn label(image_file_name: &str) -> String {
+    let stem = image_file_name.trim_end_matches(".png");
+    match stem.rsplit_once(".csv") {
+        Some((_, "")) => "heatmap".to_string(),
+        Some((_, suffix)) => suffix.trim_start_matches('_').replace('_', " "),
+        None => stem.to_string(),
+    }
+}
+
+pub fn plot_csv_with_options(file_path: &str, options: &PlotOptions) -> Result<Vec<GameState>, Box<dyn Error>> {
+    let mut data = load_frames(file_path)?;
+    if options.normalize {
+        normalize_sides(&mut data);
+    }
+
     // Discover unique team numbers
     let mut teams: Vec<u32> = data
         .iter()
@@ -46,22 +163,37 @@ pub fn plot_csv(file_path: &str) -> Result<Vec<GameState>, Box<dyn Error>> {
 
     let mut team_colors = HashMap::new();
     if teams.len() >= 2 {
-        team_colors.insert(Some(teams[0]), RGBColor(255, 165, 0)); // Orange team
-        team_colors.insert(Some(teams[1]), RGBColor(0, 0, 255));   // Blue team
+        team_colors.insert(Some(teams[0]), RGBColor(0, 0, 255));   // Blue team
+        team_colors.insert(Some(teams[1]), RGBColor(255, 165, 0)); // Orange team
     } else {
         return Err("Not enough teams found in the dataset to plot heatmaps.".into());
     }
 
-    if let Ok(player_name) = env::var("PLAYER_NAME") {
+    if let Some(player_name) = &options.player_name {
         let player_plot_combo = format!("{}.png", file_path);
-        plot_combined(&data, &player_plot_combo, &player_name, &team_colors)?;
+        plot_combined(&data, &player_plot_combo, player_name, &team_colors)?;
     } else {
         let combined_heatmap = format!("{}.png", file_path);
         plot_combined_heatmap(&data, &combined_heatmap, &team_colors)?;
     }
 
+    // Event overlays, when `events` has been run for this match
+    let events_file = file_path.replace(".replay.frames.json.csv", ".events.csv");
+    let mut events = if events_file != file_path && Path::new(&events_file).exists() {
+        events::load_events(&events_file)?
+    } else {
+        Vec::new()
+    };
+    if options.normalize {
+        events::normalize_sides(&mut events);
+    }
+    plot_event_maps(&data, &events, file_path, options.player_name.as_deref(), &team_colors)?;
+
     let ball_plot = format!("{}_ball.png", file_path);
-    plot_ball(&data, &ball_plot)?;
+    plot_ball(&data, &events, &ball_plot, options)?;
+
+    let ball_side_plot = format!("{}_ball_side.png", file_path);
+    plot_ball_side(&data, &ball_side_plot, options)?;
 
     println!("Plots generated.");
     Ok(data)
@@ -124,10 +256,10 @@ fn plot_combined_heatmap(
         let team_color = team_colors.get(&team_id).unwrap_or(&RGBColor(128, 128, 128)); // Default to gray
 
         // Draw the heatmap by filling grid cells
-        for x in 0..grid_size_x {
-            for y in 0..grid_size_y {
-                if grid[x][y] > 0 {
-                    let intensity = grid[x][y] as f64 / max_density as f64;
+        for (x, column) in grid.iter().enumerate() {
+            for (y, &count) in column.iter().enumerate() {
+                if count > 0 {
+                    let intensity = count as f64 / max_density as f64;
                     let cell_color = team_color.mix(intensity);
 
                     let x_start = min_x + x as f64 * cell_width;
@@ -149,14 +281,181 @@ fn plot_combined_heatmap(
 }
 
 
-fn plot_ball(data: &[GameState], output_file: &str) -> Result<(), Box<dyn Error>> {
-    let root = BitMapBackend::new(output_file, (800, 600)).into_drawing_area();
+/// Ball samples inside the time window, in frame order.
+pub fn ball_path<'a>(data: &'a [GameState], options: &PlotOptions) -> Vec<&'a GameState> {
+    let start = data.iter().map(|s| s.time).fold(f64::INFINITY, f64::min);
+    let mut ball: Vec<&GameState> = data
+        .iter()
+        .filter(|s| s.is_ball() && options.in_window(s.time - start))
+        .collect();
+    ball.sort_by_key(|s| s.frame);
+    ball
+}
+
+fn speed(state: &GameState) -> f64 {
+    (state.linear_velocity_x.powi(2) + state.linear_velocity_y.powi(2) + state.linear_velocity_z.powi(2)).sqrt()
+}
+
+/// Pairs of consecutive ball samples, skipping gaps such as goal resets.
+fn ball_segments<'a>(ball: &[&'a GameState]) -> Vec<(&'a GameState, &'a GameState)> {
+    ball.windows(2)
+        .filter(|pair| pair[1].frame - pair[0].frame <= 10)
+        .map(|pair| (pair[0], pair[1]))
+        .collect()
+}
+
+/// The team of the player who last touched the ball at `time`, if anyone has.
+fn possession_at(touches: &[&Event], time: f64) -> Option<u32> {
+    touches
+        .iter()
+        .take_while(|touch| touch.time <= time)
+        .last()
+        .and_then(|touch| touch.team)
+}
+
+fn plot_ball(data: &[GameState], events: &[Event], output_file: &str, options: &PlotOptions) -> Result<(), Box<dyn Error>> {
+    let root = BitMapBackend::new(output_file, (1600, 600)).into_drawing_area();
     root.fill(&WHITE)?;
 
     let (min_x, max_x, min_y, max_y) = calculate_bounds(data);
+    let ball = ball_path(data, options);
+    let segments = ball_segments(&ball);
+    let max_speed = ball.iter().map(|s| speed(s)).fold(1.0, f64::max);
+
+    let touches: Vec<&Event> = events.iter().filter(|e| e.kind == EventKind::Touch).collect();
+
+    // Without touches there is no possession to split on, so draw everything in one chart
+    let panels: Vec<(Option<u32>, &str)> = if touches.is_empty() {
+        vec![(None, "Ball Trajectory")]
+    } else {
+        vec![(Some(0), "Ball Trajectory: Blue Possession"), (Some(1), "Ball Trajectory: Orange Possession")]
+    };
+    let areas = root.split_evenly((1, panels.len()));
+
+    for ((team, caption), area) in panels.into_iter().zip(areas.iter()) {
+        let mut chart = ChartBuilder::on(area)
+            .caption(caption, ("sans-serif", 24))
+            .margin(10)
+            .x_label_area_size(40)
+            .y_label_area_size(40)
+            .build_cartesian_2d(min_x..max_x, min_y..max_y)?;
+
+        chart.configure_mesh().draw()?;
+
+        // Segments are colored by ball speed, dark when slow and yellow when fast
+        chart.draw_series(
+            segments
+                .iter()
+                .filter(|(a, _)| team.is_none() || possession_at(&touches, a.time) == team)
+                .map(|(a, b)| {
+                    let color = ViridisRGB.get_color_normalized(speed(a), 0.0, max_speed);
+                    PathElement::new(
+                        vec![(a.location_x, a.location_y), (b.location_x, b.location_y)],
+                        color.stroke_width(2),
+                    )
+                }),
+        )?;
+    }
+
+    root.present()?;
+    Ok(())
+}
+
+/// Side view of the ball along the length of the field, showing how high play goes.
+fn plot_ball_side(data: &[GameState], output_file: &str, options: &PlotOptions) -> Result<(), Box<dyn Error>> {
+    let root = BitMapBackend::new(output_file, (1200, 400)).into_drawing_area();
+    root.fill(&WHITE)?;
+
+    let (_, _, min_y, max_y) = calculate_bounds(data);
+    let ball = ball_path(data, options);
+    let segments = ball_segments(&ball);
+    let max_speed = ball.iter().map(|s| speed(s)).fold(1.0, f64::max);
+    let max_z = ball.iter().map(|s| s.location_z).fold(0.0, f64::max) * 1.1 + 1.0;
+
+    let mut chart = ChartBuilder::on(&root)
+        .caption("Ball Height (side view)", ("sans-serif", 24))
+        .margin(10)
+        .x_label_area_size(40)
+        .y_label_area_size(60)
+        .build_cartesian_2d(min_y..max_y, 0.0..max_z)?;
+
+    chart
+        .configure_mesh()
+        .x_desc("location_y")
+        .y_desc("location_z")
+        .draw()?;
+
+    chart.draw_series(segments.iter().map(|(a, b)| {
+        let color = ViridisRGB.get_color_normalized(speed(a), 0.0, max_speed);
+        PathElement::new(
+            vec![(a.location_y, a.location_z), (b.location_y, b.location_z)],
+            color.stroke_width(2),
+        )
+    }))?;
+
+    root.present()?;
+    Ok(())
+}
+
+/// Draws the shot, touch and demolition maps next to `file_path`, optionally limited to one player.
+pub fn plot_event_maps(
+    data: &[GameState],
+    events: &[Event],
+    file_path: &str,
+    player_name: Option<&str>,
+    team_colors: &HashMap<Option<u32>, RGBColor>,
+) -> Result<(), Box<dyn Error>> {
+    let bounds = calculate_bounds(data);
+    let involves_player = |event: &&Event| match player_name {
+        Some(name) => event.player_name == name || event.victim_name.as_deref() == Some(name),
+        None => true,
+    };
+
+    let shots: Vec<&Event> = events
+        .iter()
+        .filter(|e| e.kind == EventKind::Shot)
+        .filter(involves_player)
+        .collect();
+    let touches: Vec<&Event> = events
+        .iter()
+        .filter(|e| e.kind == EventKind::Touch)
+        .filter(involves_player)
+        .collect();
+    let demos: Vec<&Event> = events
+        .iter()
+        .filter(|e| e.kind == EventKind::Demo)
+        .filter(involves_player)
+        .collect();
+
+    let suffix = player_name.map(|name| format!(": {}", name)).unwrap_or_default();
+
+    if !shots.is_empty() {
+        let shot_plot = format!("{}_shots.png", file_path);
+        plot_shot_map(&shots, &shot_plot, bounds, &suffix)?;
+    }
+    if !touches.is_empty() {
+        let touch_plot = format!("{}_touches.png", file_path);
+        plot_touch_map(&touches, &touch_plot, bounds, &suffix)?;
+    }
+    if !demos.is_empty() {
+        let demo_plot = format!("{}_demos.png", file_path);
+        plot_demo_map(&demos, &demo_plot, bounds, &suffix, team_colors)?;
+    }
+
+    Ok(())
+}
+
+fn plot_shot_map(
+    shots: &[&Event],
+    output_file: &str,
+    (min_x, max_x, min_y, max_y): (f64, f64, f64, f64),
+    suffix: &str,
+) -> Result<(), Box<dyn Error>> {
+    let root = BitMapBackend::new(output_file, (1200, 800)).into_drawing_area();
+    root.fill(&WHITE)?;
 
     let mut chart = ChartBuilder::on(&root)
-        .caption("Ball Movement", ("sans-serif", 30))
+        .caption(format!("Shot Map{}", suffix), ("sans-serif", 30))
         .margin(10)
         .x_label_area_size(40)
         .y_label_area_size(40)
@@ -164,19 +463,130 @@ fn plot_ball(data: &[GameState], output_file: &str) -> Result<(), Box<dyn Error>
 
     chart.configure_mesh().draw()?;
 
-    // Filter data for the ball (player_name is empty or "ball")
-    let ball_positions: Vec<(f64, f64)> = data
-        .iter()
-        .filter(|s| s.player_name.is_empty() || s.player_name.to_lowercase() == "_ball_")
-        .map(|state| (state.location_x, state.location_y))
-        .collect();
+    // Marker size grows with the expected goals of the shot
+    let outcomes = [
+        (ShotOutcome::Goal, "Goal", RGBColor(0, 160, 0)),
+        (ShotOutcome::Saved, "Saved", RGBColor(0, 120, 220)),
+        (ShotOutcome::Missed, "Missed", RGBColor(220, 0, 0)),
+    ];
+    for (outcome, label, color) in outcomes {
+        chart
+            .draw_series(
+                shots
+                    .iter()
+                    .filter(|shot| shot.outcome == Some(outcome))
+                    .map(|shot| {
+                        let radius = 4 + (shot.xg.unwrap_or(0.0) * 20.0) as i32;
+                        Circle::new((shot.location_x, shot.location_y), radius, color.mix(0.7).filled())
+                    }),
+            )?
+            .label(label)
+            .legend(move |(x, y)| Circle::new((x, y), 5, color.filled()));
+    }
 
-    // Draw the ball's positions
-    chart.draw_series(
-        ball_positions
-            .iter()
-            .map(|&(x, y)| Circle::new((x, y), 1, RGBColor(0, 0, 0).filled())),
-    )?;
+    chart
+        .configure_series_labels()
+        .background_style(WHITE.mix(0.8))
+        .border_style(BLACK)
+        .draw()?;
+
+    root.present()?;
+    Ok(())
+}
+
+fn plot_touch_map(
+    touches: &[&Event],
+    output_file: &str,
+    (min_x, max_x, min_y, max_y): (f64, f64, f64, f64),
+    suffix: &str,
+) -> Result<(), Box<dyn Error>> {
+    let root = BitMapBackend::new(output_file, (1200, 800)).into_drawing_area();
+    root.fill(&WHITE)?;
+
+    let mut chart = ChartBuilder::on(&root)
+        .caption(format!("Touch Map{}", suffix), ("sans-serif", 30))
+        .margin(10)
+        .x_label_area_size(40)
+        .y_label_area_size(40)
+        .build_cartesian_2d(min_x..max_x, min_y..max_y)?;
+
+    chart.configure_mesh().draw()?;
+
+    let mut players: Vec<&str> = touches.iter().map(|t| t.player_name.as_str()).collect();
+    players.sort();
+    players.dedup();
+
+    for (index, player) in players.into_iter().enumerate() {
+        let color = Palette99::pick(index).to_rgba();
+        chart
+            .draw_series(
+                touches
+                    .iter()
+                    .filter(|t| t.player_name == player)
+                    .map(|t| Circle::new((t.location_x, t.location_y), 4, color.filled())),
+            )?
+            .label(player)
+            .legend(move |(x, y)| Circle::new((x, y), 5, color.filled()));
+    }
+
+    chart
+        .configure_series_labels()
+        .background_style(WHITE.mix(0.8))
+        .border_style(BLACK)
+        .draw()?;
+
+    root.present()?;
+    Ok(())
+}
+
+fn plot_demo_map(
+    demos: &[&Event],
+    output_file: &str,
+    (min_x, max_x, min_y, max_y): (f64, f64, f64, f64),
+    suffix: &str,
+    team_colors: &HashMap<Option<u32>, RGBColor>,
+) -> Result<(), Box<dyn Error>> {
+    let root = BitMapBackend::new(output_file, (1200, 800)).into_drawing_area();
+    root.fill(&WHITE)?;
+
+    let mut chart = ChartBuilder::on(&root)
+        .caption(format!("Demolitions{}", suffix), ("sans-serif", 30))
+        .margin(10)
+        .x_label_area_size(40)
+        .y_label_area_size(40)
+        .build_cartesian_2d(min_x..max_x, min_y..max_y)?;
+
+    chart.configure_mesh().draw()?;
+
+    // Each demo is drawn where the victim was, in the attacker's team color
+    let mut teams: Vec<Option<u32>> = demos.iter().map(|d| d.team).collect();
+    teams.sort();
+    teams.dedup();
+
+    for team in teams {
+        let color = *team_colors.get(&team).unwrap_or(&RGBColor(128, 128, 128));
+        let label = match team {
+            Some(0) => "Blue demos".to_string(),
+            Some(1) => "Orange demos".to_string(),
+            Some(other) => format!("Team {} demos", other),
+            None => "Unknown team demos".to_string(),
+        };
+        chart
+            .draw_series(
+                demos
+                    .iter()
+                    .filter(|d| d.team == team)
+                    .map(|d| Cross::new((d.location_x, d.location_y), 8, color.stroke_width(3))),
+            )?
+            .label(label)
+            .legend(move |(x, y)| Cross::new((x, y), 5, color.stroke_width(2)));
+    }
+
+    chart
+        .configure_series_labels()
+        .background_style(WHITE.mix(0.8))
+        .border_style(BLACK)
+        .draw()?;
 
     root.present()?;
     Ok(())
@@ -234,10 +644,10 @@ fn plot_combined(
         let team_color = team_colors.get(&team_id).unwrap_or(&RGBColor(128, 128, 128)); // Default to gray
 
         // Draw the heatmap by filling grid cells
-        for x in 0..grid_size_x {
-            for y in 0..grid_size_y {
-                if grid[x][y] > 0 {
-                    let intensity = grid[x][y] as f64 / max_density as f64;
+        for (x, column) in grid.iter().enumerate() {
+            for (y, &count) in column.iter().enumerate() {
+                if count > 0 {
+                    let intensity = count as f64 / max_density as f64;
                     let cell_color = team_color.mix(intensity);
 
                     let x_start = min_x + x as f64 * cell_width;
@@ -284,3 +694,161 @@ fn plot_combined(
     root.present()?;
     Ok(())
 }
+
+/// The latest known state of the ball and every car at `frame`.
+pub fn snapshot_at(data: &[GameState], frame: usize) -> Vec<&GameState> {
+    let mut latest: HashMap<&str, &GameState> = HashMap::new();
+    for state in data.iter().filter(|s| s.frame <= frame) {
+        let key = if state.is_ball() { "_ball_" } else { state.player_name.as_str() };
+        match latest.get(key) {
+            Some(existing) if existing.frame > state.frame => {}
+            _ => {
+                latest.insert(key, state);
+            }
+        }
+    }
+
+    let mut snapshot: Vec<&GameState> = latest.into_values().collect();
+    snapshot.sort_by(|a, b| a.team.cmp(&b.team).then(a.player_name.cmp(&b.player_name)));
+    snapshot
+}
+
+/// Draws a top-down diagram of every car and the ball at `frame`.
+/// Cars show their facing, name and boost; `show_velocity` adds where each object
+/// is heading over the next half second.
+pub fn plot_snapshot(
+    data: &[GameState],
+    frame: usize,
+    output_file: &str,
+    show_velocity: bool,
+) -> Result<(), Box<dyn Error>> {
+    let root = BitMapBackend::new(output_file, SNAPSHOT_SIZE).into_drawing_area();
+    draw_snapshot(&root, data, frame, show_velocity)?;
+    root.present()?;
+    Ok(())
+}
+
+/// Animates `start_frame..=end_frame` as a GIF of snapshots (see `plot_snapshot`), one
+/// every `CLIP_STEP` seconds of match time and at most `MAX_CLIP_IMAGES` of them.
+pub fn plot_clip(data: &[GameState], start_frame: usize, end_frame: usize, output_file: &str) -> Result<(), Box<dyn Error>> {
+    let mut times: Vec<(usize, f64)> = data
+        .iter()
+        .filter(|s| s.is_ball() && s.frame >= start_frame && s.frame <= end_frame)
+        .map(|s| (s.frame, s.time))
+        .collect();
+    times.sort_by_key(|(frame, _)| *frame);
+
+    let mut frames: Vec<usize> = Vec::new();
+    let mut next_time = f64::NEG_INFINITY;
+    for (frame, time) in times {
+        if time >= next_time && frames.len() < MAX_CLIP_IMAGES {
+            frames.push(frame);
+            next_time = time + CLIP_STEP;
+        }
+    }
+    if frames.is_empty() {
+        return Err(format!("No ball positions found between frames {} and {}", start_frame, end_frame).into());
+    }
+
+    let root = BitMapBackend::gif(output_file, SNAPSHOT_SIZE, (CLIP_STEP * 1000.0) as u32)?.into_drawing_area();
+    for frame in frames {
+        draw_snapshot(&root, data, frame, true)?;
+        root.present()?;
+    }
+    Ok(())
+}
+
+fn draw_snapshot<DB: DrawingBackend>(
+    root: &DrawingArea<DB, Shift>,
+    data: &[GameState],
+    frame: usize,
+    show_velocity: bool,
+) -> Result<(), Box<dyn Error>>
+where
+    DB::ErrorType: 'static,
+{
+    let snapshot = snapshot_at(data, frame);
+    if snapshot.is_empty() {
+        return Err(format!("No positions found at or before frame {}", frame).into());
+    }
+
+    let scale = events::position_scale(data);
+    let half_width = events::FIELD_HALF_WIDTH * scale;
+    let goal_line = events::GOAL_LINE_Y * scale;
+    let goal_half_width = events::GOAL_HALF_WIDTH * scale;
+    let goal_depth = events::GOAL_DEPTH * scale;
+
+    root.fill(&WHITE)?;
+
+    let time = snapshot.iter().map(|s| s.time).fold(0.0, f64::max);
+    let mut chart = ChartBuilder::on(root)
+        .caption(format!("Frame {} ({:.1}s)", frame, time), ("sans-serif", 30))
+        .margin(10)
+        .build_cartesian_2d(
+            -half_width * 1.05..half_width * 1.05,
+            -(goal_line + goal_depth) * 1.02..(goal_line + goal_depth) * 1.02,
+        )?;
+
+    // Field outline, halfway line and both goals
+    let field_style = RGBColor(120, 120, 120).stroke_width(2);
+    chart.draw_series([
+        Rectangle::new([(-half_width, -goal_line), (half_width, goal_line)], field_style),
+        Rectangle::new([(-goal_half_width, goal_line), (goal_half_width, goal_line + goal_depth)], field_style),
+        Rectangle::new([(-goal_half_width, -goal_line - goal_depth), (goal_half_width, -goal_line)], field_style),
+    ])?;
+    chart.draw_series([PathElement::new(vec![(-half_width, 0.0), (half_width, 0.0)], field_style)])?;
+
+    let arrow_length = 350.0 * scale;
+    for state in snapshot {
+        let position = (state.location_x, state.location_y);
+
+        if show_velocity {
+            let heading = (
+                state.location_x + state.linear_velocity_x * 0.5,
+                state.location_y + state.linear_velocity_y * 0.5,
+            );
+            chart.draw_series([PathElement::new(vec![position, heading], RGBColor(150, 150, 150).stroke_width(1))])?;
+        }
+
+        if state.is_ball() {
+            chart.draw_series([
+                Circle::new(position, 10, RGBColor(220, 220, 220).filled()),
+                Circle::new(position, 10, BLACK.stroke_width(2)),
+            ])?;
+            continue;
+        }
+
+        let color = match state.team {
+            Some(0) => RGBColor(0, 0, 255),
+            Some(1) => RGBColor(255, 165, 0),
+            _ => RGBColor(128, 128, 128),
+        };
+
+        if let Some((forward_x, forward_y)) = state.facing() {
+            let tip = (position.0 + forward_x * arrow_length, position.1 + forward_y * arrow_length);
+            let head = arrow_length * 0.3;
+            let left = (
+                tip.0 - head * (forward_x - forward_y * 0.5),
+                tip.1 - head * (forward_y + forward_x * 0.5),
+            );
+            let right = (
+                tip.0 - head * (forward_x + forward_y * 0.5),
+                tip.1 - head * (forward_y - forward_x * 0.5),
+            );
+            chart.draw_series([
+                PathElement::new(vec![position, tip], color.stroke_width(3)),
+                PathElement::new(vec![left, tip, right], color.stroke_width(3)),
+            ])?;
+        }
+
+        let label = match state.boost_percent() {
+            Some(boost) => format!("{} ({}%)", state.player_name, boost),
+            None => state.player_name.clone(),
+        };
+        chart.draw_series([
+            EmptyElement::at(position) + Circle::new((0, 0), 8, color.filled()) + Text::new(label, (10, -18), ("sans-serif", 16)),
+        ])?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,202 @@
+use crate::prompt::Section;
+use serde::Deserialize;
+use serde_json::Value;
+use std::error::Error;
+
+/// A game mode, by the number of players on each team.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    Duel,
+    Doubles,
+    Standard,
+}
+
+impl Mode {
+    /// The mode of a match with `team_size` players a side; none for modes without coaching
+    /// of their own, such as 4v4 Chaos.
+    pub fn from_team_size(team_size: u32) -> Option<Mode> {
+        match team_size {
+            1 => Some(Mode::Duel),
+            2 => Some(Mode::Doubles),
+            3 => Some(Mode::Standard),
+            _ => None,
+        }
+    }
+
+    /// Parses `1v1`, `duel`, `2v2`, `doubles`, `3v3` or `standard`.
+    pub fn parse(value: &str) -> Result<Mode, String> {
+        match value.trim().to_lowercase().as_str() {
+            "1v1" | "duel" => Ok(Mode::Duel),
+            "2v2" | "doubles" => Ok(Mode::Doubles),
+            "3v3" | "standard" => Ok(Mode::Standard),
+            _ => Err(format!("Unknown game mode: {} (expected 1v1, 2v2 or 3v3)", value)),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Mode::Duel => "1v1 (Duel)",
+            Mode::Doubles => "2v2 (Doubles)",
+            Mode::Standard => "3v3 (Standard)",
+        }
+    }
+
+    /// What to look at in this mode.
+    fn analysis(&self) -> &'static str {
+        match self {
+            Mode::Duel => "There are no teammates to rotate with: look at challenges, shadow defence, boost control, possession and when to commit or wait.",
+            Mode::Doubles => "Look at the two-player rotation: who challenges and who covers, the spacing between them, and boost for the player going back.",
+            Mode::Standard => "Look at the three-player rotation (1st, 2nd and 3rd man), passing and positioning for teammates, and double commits.",
+        }
+    }
+
+    /// The match data worth sending for this mode: rotation roles say nothing in 1v1.
+    pub fn select(&self, sections: Vec<Section>) -> Vec<Section> {
+        sections
+            .into_iter()
+            .filter(|section| *self != Mode::Duel || !section.title.starts_with("Rotation roles"))
+            .collect()
+    }
+}
+
+/// A competitive rank, Bronze to Supersonic Legend, without its division.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Rank {
+    Bronze,
+    Silver,
+    Gold,
+    Platinum,
+    Diamond,
+    Champion,
+    GrandChampion,
+    SupersonicLegend,
+}
+
+impl Rank {
+    /// Parses a rank name such as `Diamond`, `Diamond 2`, `Grand Champion`, `GC` or `SSL`.
+    pub fn parse(value: &str) -> Result<Rank, String> {
+        let name = value
+            .trim()
+            .to_lowercase()
+            .trim_end_matches(|c: char| c.is_ascii_digit() || c.is_whitespace() || c == 'i' || c == 'v')
+            .replace([' ', '_', '-'], "");
+        match name.as_str() {
+            "bronze" => Ok(Rank::Bronze),
+            "silver" => Ok(Rank::Silver),
+            "gold" => Ok(Rank::Gold),
+            "plat" | "platinum" => Ok(Rank::Platinum),
+            "diamond" => Ok(Rank::Diamond),
+            "champ" | "champion" => Ok(Rank::Champion),
+            "gc" | "grandchamp" | "grandchampion" => Ok(Rank::GrandChampion),
+            "ssl" | "supersoniclegend" => Ok(Rank::SupersonicLegend),
+            _ => Err(format!("Unknown rank: {} (expected Bronze to Supersonic Legend)", value)),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Rank::Bronze => "Bronze",
+            Rank::Silver => "Silver",
+            Rank::Gold => "Gold",
+            Rank::Platinum => "Platinum",
+            Rank::Diamond => "Diamond",
+            Rank::Champion => "Champion",
+            Rank::GrandChampion => "Grand Champion",
+            Rank::SupersonicLegend => "Supersonic Legend",
+        }
+    }
+
+    /// How deep the coaching should go at this rank.
+    fn depth(&self) -> &'static str {
+        match self {
+            Rank::Bronze | Rank::Silver | Rank::Gold => "stick to fundamentals such as ball control, boost pickups, hitting the ball to the opponents' half and staying out of teammates' way; explain any game terms and leave out advanced mechanics.",
+            Rank::Platinum | Rank::Diamond => "work on consistent rotation, reading bounces, shot quality, recoveries and small boost pads; bring up mechanics like fast aerials and power shots only where they would have won the play.",
+            Rank::Champion | Rank::GrandChampion | Rank::SupersonicLegend => "assume solid fundamentals and mechanics; go into fine decision-making, pressure and tempo, and advanced mechanics where they would have changed the outcome.",
+        }
+    }
+}
+
+/// Who the coaching is for: the rank they play at or aim for, the mode when the replay
+/// doesn't say, and what they want to get better at.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Profile {
+    pub rank: Option<Rank>,
+    /// Only used for replays whose header has no team size.
+    pub mode: Option<Mode>,
+    /// e.g. `improve rotations`.
+    pub goals: Vec<String>,
+}
+
+/// The `profile` section of the config file.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ProfileSettings {
+    rank: Option<String>,
+    mode: Option<String>,
+    #[serde(default)]
+    goals: Vec<String>,
+}
+
+impl Profile {
+    /// Parses a config file section such as
+    /// `{"rank": "Diamond", "mode": "2v2", "goals": ["improve rotations"]}`.
+    pub fn from_json(value: Value) -> Result<Self, Box<dyn Error>> {
+        let settings: ProfileSettings = serde_json::from_value(value)?;
+        Ok(Profile {
+            rank: settings.rank.as_deref().map(Rank::parse).transpose()?,
+            mode: settings.mode.as_deref().map(Mode::parse).transpose()?,
+            goals: settings.goals,
+        })
+    }
+
+    /// Sets `rank`, `mode` or `goals` (separated by `;`); an empty value clears it.
+    pub fn set(&mut self, field: &str, value: &str) -> Result<(), Box<dyn Error>> {
+        let value = value.trim();
+        match field {
+            "rank" => self.rank = (!value.is_empty()).then(|| Rank::parse(value)).transpose()?,
+            "mode" => self.mode = (!value.is_empty()).then(|| Mode::parse(value)).transpose()?,
+            "goals" => {
+                self.goals = value
+                    .split(';')
+                    .map(str::trim)
+                    .filter(|goal| !goal.is_empty())
+                    .map(str::to_string)
+                    .collect()
+            }
+            _ => return Err(format!("Unknown profile setting: {}", field).into()),
+        }
+        Ok(())
+    }
+
+    /// The mode of a match: from the `team_size` in its header, else the configured one.
+    pub fn mode(&self, team_size: Option<u32>) -> Option<Mode> {
+        team_size.and_then(Mode::from_team_size).or(self.mode)
+    }
+
+    /// The instructions tailoring the coaching to `mode`, the rank and the goals.
+    pub fn instructions(&self, mode: Option<Mode>) -> Vec<String> {
+        let mut instructions = Vec::new();
+        if let Some(mode) = mode {
+            instructions.push(format!("This is a {} match. {}", mode.name(), mode.analysis()));
+        }
+        if let Some(rank) = self.rank {
+            instructions.push(format!("Pitch the coaching at {} level: {}", rank.name(), rank.depth()));
+        }
+        if !self.goals.is_empty() {
+            instructions.push(format!(
+                "Goals being worked on: {}. Say how this match went for each of them, and what to practice next.",
+                self.goals.join("; ")
+            ));
+        }
+        instructions
+    }
+}
+
+/// The players per team from a converted match's header, see `convert`.
+pub fn team_size(match_guid: &str) -> Option<u32> {
+    let header_csv_path = format!("./output/{}.header.json.csv", match_guid);
+    let mut reader = csv::Reader::from_path(header_csv_path).ok()?;
+    let column = reader.headers().ok()?.iter().position(|name| name == "team_size")?;
+    let record = reader.records().next()?.ok()?;
+    record.get(column)?.trim().parse().ok()
+}
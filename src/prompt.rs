@@ -0,0 +1,126 @@
+/// How important a prompt section is; lower priorities are cut first when a prompt
+/// doesn't fit the model's budget.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+    /// Instructions the answer can't do without; never cut.
+    Required,
+    Stats,
+    Events,
+    Frames,
+}
+
+/// A titled block of the prompt, cut line by line when it doesn't fit.
+#[derive(Debug, Clone)]
+pub struct Section {
+    pub title: String,
+    pub priority: Priority,
+    pub lines: Vec<String>,
+}
+
+impl Section {
+    pub fn new(title: &str, priority: Priority, lines: Vec<String>) -> Self {
+        Section {
+            title: title.to_string(),
+            priority,
+            lines,
+        }
+    }
+
+    fn heading(&self) -> String {
+        if self.title.is_empty() {
+            String::new()
+        } else {
+            format!("## {}\n", self.title)
+        }
+    }
+}
+
+/// A prompt fitted to a budget, with a note for every section that was cut.
+#[derive(Debug, Clone)]
+pub struct Assembled {
+    pub text: String,
+    pub tokens: usize,
+    pub dropped: Vec<String>,
+}
+
+/// The whole prompt, nothing cut.
+pub fn render(sections: &[Section]) -> String {
+    let counts: Vec<Option<usize>> = sections.iter().map(|section| Some(section.lines.len())).collect();
+    render_counts(sections, &counts)
+}
+
+/// Fits `sections` into `budget` tokens as measured by `estimate`. Sections are filled in
+/// priority order (stats before events before frames); the first one that doesn't fit is
+/// truncated and anything after it is dropped. The text keeps the sections' original order.
+pub fn assemble(sections: &[Section], budget: usize, estimate: &dyn Fn(&str) -> usize) -> Assembled {
+    let mut order: Vec<usize> = (0..sections.len()).collect();
+    order.sort_by_key(|&index| sections[index].priority);
+
+    let mut remaining = budget;
+    let mut kept: Vec<Option<usize>> = vec![None; sections.len()];
+    let mut dropped = Vec::new();
+    let mut full = false;
+
+    for index in order {
+        let section = &sections[index];
+        let required = section.priority == Priority::Required;
+        if full && !required {
+            dropped.push(format!("dropped {}", section.title));
+            continue;
+        }
+
+        let mut cost = estimate(&section.heading());
+        if cost > remaining && !required {
+            full = true;
+            dropped.push(format!("dropped {}", section.title));
+            continue;
+        }
+
+        let mut count = 0;
+        for line in &section.lines {
+            let line_cost = estimate(line) + 1;
+            if cost + line_cost > remaining && !required {
+                full = true;
+                break;
+            }
+            cost += line_cost;
+            count += 1;
+        }
+
+        if count < section.lines.len() {
+            dropped.push(format!(
+                "truncated {} to {} of {} lines",
+                section.title,
+                count,
+                section.lines.len()
+            ));
+        }
+        remaining = remaining.saturating_sub(cost);
+        kept[index] = Some(count);
+    }
+
+    let text = render_counts(sections, &kept);
+    Assembled {
+        tokens: estimate(&text),
+        text,
+        dropped,
+    }
+}
+
+/// Renders the first `count` lines of each section, skipping sections without a count.
+fn render_counts(sections: &[Section], counts: &[Option<usize>]) -> String {
+    let mut text = String::new();
+    for (section, count) in sections.iter().zip(counts) {
+        let Some(count) = *count else { continue };
+        text.push_str(&section.heading());
+        for line in &section.lines[..count] {
+            text.push_str(line);
+            text.push('\n');
+        }
+        if count < section.lines.len() {
+            text.push_str(&format!("({} more lines omitted)\n", section.lines.len() - count));
+        }
+        text.push('\n');
+    }
+    text
+}
@@ -1,22 +1,278 @@
-use crate::ai::openai;
-use crate::ai::anthropic;
-use crate::ai::google;
-
+use crate::ai::config::Config;
+use crate::ai::image::{self, Image};
+use crate::ai::provider::{Provider, ProviderError};
+use crate::ai::registry::Registry;
+use crate::coaching::{self, CoachingReport};
+use futures::future::join_all;
+use serde_json::json;
+use std::future::Future;
+
+use crate::agent::{self, MatchTools};
+use crate::digest;
+use crate::profile;
+use crate::prompt::{self, Priority, Section};
+use crate::templates;
 use std::fs;
-use std::env;
 use std::io;
-use base64::{Engine as _};
-use std::io::Write;
-use csv::{ReaderBuilder, Writer};
-use std::error::Error;
-use std::collections::HashMap;
+use std::env;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// How long to wait for all providers together, unless `RATTLEBRAIN_DEADLINE` says otherwise.
+const DEFAULT_DEADLINE_SECS: u64 = 600;
+
+/// What one provider answered, or why it didn't, and how long it took.
+#[derive(Debug)]
+pub struct ProviderResult<T = String> {
+    pub name: String,
+    pub elapsed: Duration,
+    pub outcome: Result<T, String>,
+}
+
+/// Queries every AI provider configured in the environment, see `Registry::from_config`.
+pub async fn query_ai(match_guid: &str, focus: &str, player: Option<&str>, config: &Config) -> io::Result<String> {
+    query_ai_with(&Registry::from_config(config), config, match_guid, focus, player).await
+}
+
+/// Queries each provider in `registry` and combines their answers into one report. When
+/// more than one answers, `config.synthesizer` merges them into a unified report first.
+/// With `config.attach_images`, the match's plots go to the providers that accept images.
+pub async fn query_ai_with(
+    registry: &Registry,
+    config: &Config,
+    match_guid: &str,
+    focus: &str,
+    player: Option<&str>,
+) -> io::Result<String> {
+    let query = build_query(registry, config, match_guid, focus, player)?;
+    let images = if config.attach_images { plots(match_guid) } else { Vec::new() };
+    let started = Instant::now();
+    let results = query_providers(registry, &query, &images, deadline()).await;
+
+    if results.iter().all(|result| result.outcome.is_err()) {
+        return Err(io::Error::other("No AI provider answered."));
+    }
+    let synthesis = synthesize(registry, config.synthesizer.as_deref(), &results, time_left(started)).await;
+    Ok(with_usage(registry, render_report(&results, synthesis.as_ref())))
+}
+
+/// Asks one provider to merge the other answers into a single report of consensus and
+/// disagreements. Uses `synthesizer` if it is registered, otherwise the first provider
+/// that answered. Returns `None` when there is nothing to merge.
+pub async fn synthesize(
+    registry: &Registry,
+    synthesizer: Option<&str>,
+    results: &[ProviderResult],
+    deadline: Duration,
+) -> Option<ProviderResult> {
+    let answers: Vec<(&str, &str)> = results
+        .iter()
+        .filter_map(|result| Some((result.name.as_str(), result.outcome.as_deref().ok()?)))
+        .collect();
+    if answers.len() < 2 {
+        return None;
+    }
+
+    let named = synthesizer.and_then(|name| {
+        let provider = registry.providers().iter().find(|p| p.name().eq_ignore_ascii_case(name));
+        if provider.is_none() {
+            eprintln!("Synthesizer {} is not configured, using the first provider that answered", name);
+        }
+        provider
+    });
+    let provider = named.or_else(|| {
+        registry
+            .providers()
+            .iter()
+            .find(|p| answers.iter().any(|(name, _)| *name == p.name()))
+    })?;
+
+    let mut prompt = String::from(
+        "Several Rocket League coaches reviewed the same match. Merge their feedback into one report \
+         without repeating yourself. Use these sections:\n\
+         ## Consensus - points most coaches agree on, noting which coaches made them.\n\
+         ## Disagreements - points where coaches contradict each other, with each side and which is better supported.\n\
+         ## Recommendations - a single prioritized list of what to practice next.\n\
+         Keep the coaches' [frames START-END] citations next to the points they support.\n",
+    );
+    for (name, answer) in &answers {
+        prompt.push_str(&format!("\n=== Feedback from {} ===\n{}\n", name, answer));
+    }
+
+    println!("Synthesizing {} responses with {}...", answers.len(), provider.name());
+    let started = Instant::now();
+    let printer = LinePrinter::new(provider.name());
+    let on_text = |text: &str| printer.push(text);
+    let outcome = match tokio::time::timeout(deadline, provider.query_streaming(&prompt, &on_text)).await {
+        Ok(Ok(report)) => Ok(report),
+        Ok(Err(e)) => Err(e.to_string()),
+        Err(_) => Err("deadline exceeded".to_string()),
+    };
+    printer.finish();
+    if let Err(e) = &outcome {
+        eprintln!("Error synthesizing with {}: {}", provider.name(), e);
+    }
+    Some(ProviderResult {
+        name: provider.name().to_string(),
+        elapsed: started.elapsed(),
+        outcome,
+    })
+}
+
+/// Like `query_ai`, but asks for structured coaching, see `query_coaching_with`.
+pub async fn query_coaching(match_guid: &str, focus: &str, player: Option<&str>, config: &Config) -> io::Result<String> {
+    query_coaching_with(&Registry::from_config(config), config, match_guid, focus, player).await
+}
+
+/// Asks each provider for a `CoachingReport`, saves the valid ones to
+/// `./output/<output_name>.coaching.json` (see `output_name`), and renders them into one markdown report.
+/// Answers that don't match the schema count as failures.
+pub async fn query_coaching_with(
+    registry: &Registry,
+    config: &Config,
+    match_guid: &str,
+    focus: &str,
+    player: Option<&str>,
+) -> io::Result<String> {
+    let query = build_query(registry, config, match_guid, focus, player)?;
+    let schema = coaching::report_schema();
+    let results = run_providers(registry, deadline(), |provider| {
+        let (query, schema) = (&query, &schema);
+        async move {
+            let response = provider.query_structured(&fit_prompt(provider, query), schema).await?;
+            coaching::parse_report(&response)
+        }
+    })
+    .await;
+
+    let reports: Vec<_> = results
+        .iter()
+        .filter_map(|result| {
+            let report = result.outcome.as_ref().ok()?;
+            Some(json!({ "provider": result.name, "report": report }))
+        })
+        .collect();
+    if reports.is_empty() {
+        return Err(io::Error::other("No AI provider answered with a valid coaching report."));
+    }
+
+    let coaching_file_path = format!("./output/{}.coaching.json", output_name(match_guid, player));
+    let coaching_json = serde_json::to_string_pretty(&reports).map_err(io::Error::other)?;
+    fs::write(&coaching_file_path, coaching_json)?;
+    println!("Structured coaching saved to: {}", coaching_file_path);
+
+    let rendered: Vec<ProviderResult> = results
+        .into_iter()
+        .map(|result| ProviderResult {
+            name: result.name,
+            elapsed: result.elapsed,
+            outcome: result.outcome.map(|report: CoachingReport| coaching::render_markdown(&report)),
+        })
+        .collect();
+    Ok(with_usage(registry, render_report(&rendered, None)))
+}
+
+/// Like `query_ai`, but lets the providers investigate the match with tools, see `query_agent_with`.
+pub async fn query_agent(match_guid: &str, focus: &str, player: Option<&str>, config: &Config) -> io::Result<String> {
+    query_agent_with(&Registry::from_config(config), config, match_guid, focus, player).await
+}
+
+/// Gives each provider the match overview and tools to look up stats, events, positions
+/// and possession (see `agent::MatchTools`), and lets it investigate for up to
+/// `config.agent_steps` turns before writing feedback. The conversations are saved to
+/// `./output/<output_name>.agent.md`; the answers are combined as in `query_ai_with`.
+/// Providers without tool calling answer from the overview alone.
+pub async fn query_agent_with(
+    registry: &Registry,
+    config: &Config,
+    match_guid: &str,
+    focus: &str,
+    player: Option<&str>,
+) -> io::Result<String> {
+    check_providers(registry)?;
+    let brief = brief(config, match_guid, focus, player)?;
+    let data = digest::load_match(match_guid).map_err(|e| io::Error::other(format!("Error loading match data: {}", e)))?;
+    let overview = digest::sections(&data.frames, &data.goals, &data.events, player)
+        .into_iter()
+        .find(|section| section.title == "Match overview")
+        .map(|section| section.lines)
+        .unwrap_or_default();
+    let tools = MatchTools::new(data);
+
+    let mode = config.profile.mode(profile::team_size(match_guid));
+    let mut task = instructions(config, &brief, mode, player);
+    task.push(format!(
+        "Investigate the match with the tools before writing your feedback, so that every point rests on stats, events, positions or possession you looked up. You have {} turns of tool calls.",
+        config.agent_steps
+    ));
+    task.push(String::new());
+    task.push("Match overview (times are match clock, f = frame number):".to_string());
+    task.extend(overview);
+    let task = task.join("\n");
+
+    let started = Instant::now();
+    let results = run_providers(registry, deadline(), |provider| {
+        agent::investigate(provider, &tools, &task, config.agent_steps)
+    })
+    .await;
+
+    let mut transcripts = String::from("# Agent transcripts\n");
+    for result in &results {
+        if let Ok(transcript) = &result.outcome {
+            transcripts.push('\n');
+            transcripts.push_str(&agent::render_transcript(&result.name, transcript));
+        }
+    }
+    fs::create_dir_all("./output")?;
+    let transcript_file_path = format!("./output/{}.agent.md", output_name(match_guid, player));
+    fs::write(&transcript_file_path, transcripts)?;
+    println!("Agent transcripts saved to: {}", transcript_file_path);
+
+    let results: Vec<ProviderResult> = results
+        .into_iter()
+        .map(|result| ProviderResult {
+            name: result.name,
+            elapsed: result.elapsed,
+            outcome: result.outcome.map(|transcript| transcript.answer),
+        })
+        .collect();
+    if results.iter().all(|result| result.outcome.is_err()) {
+        return Err(io::Error::other("No AI provider answered."));
+    }
+    let synthesis = synthesize(registry, config.synthesizer.as_deref(), &results, time_left(started)).await;
+    Ok(with_usage(registry, render_report(&results, synthesis.as_ref())))
+}
+
+fn check_providers(registry: &Registry) -> io::Result<()> {
+    if registry.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            "No AI providers configured. Please set one or more of the following: OPENAI_API_KEY, ANTHROPIC_API_KEY, GEMINI_API_KEY, LOCAL_AI_BASE_URL.",
+        ));
+    }
+    Ok(())
+}
+
+/// Builds the coaching prompt for a converted match as prioritized sections, and saves it
+/// in full to `./output/<output_name>.query.txt`. Each provider gets it cut to fit, see `fit_prompt`.
+/// The brief and the match data it carries come from the template for `focus`, see
+/// `templates::Template`. With a `player`, the prompt asks for coaching of that player alone.
+/// The coaching is pitched at `config.profile`, and the match data suits the mode from the
+/// header's team size, see `profile::Profile`.
+fn build_query(
+    registry: &Registry,
+    config: &Config,
+    match_guid: &str,
+    focus: &str,
+    player: Option<&str>,
+) -> io::Result<Vec<Section>> {
+    check_providers(registry)?;
+    let brief = brief(config, match_guid, focus, player)?;
 
-pub async fn query_ai(match_guid: &str, focus: &str) -> io::Result<String> {
     // Define file paths based on the match_guid
     let player_stats_csv_path = format!("./output/{}.player_stats.json.csv", match_guid);
     let goals_csv_path = format!("./output/{}.goals.json.csv", match_guid);
     let highlights_csv_path = format!("./output/{}.highlights.json.csv", match_guid);
-    let frames_csv_path = format!("./output/{}.replay.frames.json.csv", match_guid);
 
     // Read the contents of each CSV file
     let player_stats_csv = fs::read_to_string(&player_stats_csv_path)
@@ -25,250 +281,259 @@ pub async fn query_ai(match_guid: &str, focus: &str) -> io::Result<String> {
         .unwrap_or_else(|_| format!("Error reading {}", goals_csv_path));
     let highlights_csv = fs::read_to_string(&highlights_csv_path)
         .unwrap_or_else(|_| format!("Error reading {}", highlights_csv_path));
-    let frames_csv = fs::read_to_string(&frames_csv_path)
-        .unwrap_or_else(|_| format!("Error reading {}", frames_csv_path));
-
-    let strategy_template = if focus == "strategy" || focus == "all" {
-        format!(
-            r#"Strategy Analysis:
-            Analyze team positioning, rotations, and overall synergy.
-            "#,
-        )
-    } else {
-        String::new()
-    };
-
-    let mechanics_template = if focus == "mechanics" || focus == "all" {
-        format!(
-            r#"Mechanics Analysis:
-            Evaluate boost efficiency, aerial control, and shot accuracy.
-            "#,
-        )
-    } else {
-        String::new()
-    };
-
-    let decision_making_template = if focus == "decision_making" || focus == "all" {
-        format!(
-            r#"Decision-Making Analysis:
-            Provide insights on situational awareness and risk/reward trade-offs.
-            "#,
-        )
-    } else {
-        String::new()
-    };
-
-    let frames_subset_csv = match filter_compress_and_encode_frames(&frames_csv) {
-        Ok(compressed_base64) => compressed_base64,
-        Err(e) => format!("Error processing frames CSV: {}", e),
-    };
-
-    // Combine all selected templates
-    let query = format!(
-        r#"
-        You are a world-class Rocket League team coach providing helpful feedback for improvement.
-        {}
-        {}
-        {}
 
-        Player statistics:
-{}
-
-        Goal breakdown:
-{}
-
-        Highlights:
-{}
-
-        Nth Frame Filtered, Compressed, Base64'd Frames Data:
-{}
-        "#,
-        strategy_template, mechanics_template, decision_making_template, player_stats_csv, goals_csv, highlights_csv, frames_subset_csv
+    let mode = config.profile.mode(profile::team_size(match_guid));
+    let mut instructions = instructions(config, &brief, mode, player);
+    instructions.push(
+        "The match digest sections are computed from the replay frames; times are match clock, f = frame number."
+            .to_string(),
     );
 
-    // println!("Processing AI query: {}", query);
-    let query_file_path = format!("./output/{}.query.txt", match_guid);
-    fs::write(&query_file_path, &query).map_err(|e| {
+    let csv_lines = |csv: &str| csv.lines().map(str::to_string).collect::<Vec<_>>();
+    let mut sections = vec![
+        Section::new("Player statistics", Priority::Stats, csv_lines(&player_stats_csv)),
+        Section::new("Goal breakdown", Priority::Events, csv_lines(&goals_csv)),
+        Section::new("Highlights", Priority::Events, csv_lines(&highlights_csv)),
+    ];
+    match digest::load_match(match_guid) {
+        Ok(data) => sections.extend(digest::sections(&data.frames, &data.goals, &data.events, player)),
+        Err(e) => sections.push(Section::new(
+            "Match digest",
+            Priority::Stats,
+            vec![format!("Error building match digest: {}", e)],
+        )),
+    }
+    let mut sections = brief.select(sections);
+    if let Some(mode) = mode {
+        sections = mode.select(sections);
+    }
+    sections.insert(0, Section::new("", Priority::Required, instructions));
+
+    fs::create_dir_all("./output")?;
+    let query_file_path = format!("./output/{}.query.txt", output_name(match_guid, player));
+    fs::write(&query_file_path, prompt::render(&sections)).inspect_err(|_e| {
         eprintln!("Failed to save query to file: {}", query_file_path);
-        e
     })?;
 
-    // Detect available AI providers and collect responses
-    let mut responses = Vec::new();
-    let header_response = "# Rattlebrain Replay Analysis\n\n\n".to_string();
-    responses.push(header_response);
+    Ok(sections)
+}
 
-    // OpenAI
-    if let Ok(openai_key) = env::var("OPENAI_API_KEY") {
-        println!("Using OpenAI with key: {}****", &openai_key[0..8]);
-        match openai::query_openai(&query).await {
-            Ok(response) => responses.push(format!("## OpenAI response\n\n {}", response)),
-            Err(e) => eprintln!("Error querying OpenAI: {}", e),
-        }
+/// The template for `focus` filled in for the match, see `templates::load`.
+fn brief(config: &Config, match_guid: &str, focus: &str, player: Option<&str>) -> io::Result<templates::Brief> {
+    templates::load(&config.templates, focus)
+        .and_then(|template| template.render(match_guid, player))
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+}
+
+/// What the coach is asked to do: the template's brief, the coaching profile for the
+/// match's `mode`, then, for one `player`, to coach them alone, and how to cite moments of the match.
+fn instructions(config: &Config, brief: &templates::Brief, mode: Option<profile::Mode>, player: Option<&str>) -> Vec<String> {
+    let mut instructions = brief.instructions.clone();
+    instructions.extend(config.profile.instructions(mode));
+    if let Some(player) = player {
+        instructions.push(format!(
+            "Coach {} only: address them directly, and bring up teammates and opponents only where it explains {}'s play.",
+            player, player
+        ));
     }
+    instructions.push(
+        "Cite the moments you refer to by frame number (f in the match data) as [frames START-END], e.g. [frames 1200-1350], or [frame N] for a single instant."
+            .to_string(),
+    );
+    instructions
+}
 
-    // Anthropic
-    if let Ok(claude_key) = env::var("ANTHROPIC_API_KEY") {
-        println!("Using Anthropic with key: {}****", &claude_key[0..8]);
-        match anthropic::query_anthropic(&query).await {
-            Ok(response) => responses.push(format!("## Anthropic response\n\n {}", response)),
-            Err(e) => eprintln!("Error querying Claude: {}", e),
-        }
+/// Cuts the prompt down to what `provider` can take, logging what had to go.
+fn fit_prompt(provider: &dyn Provider, sections: &[Section]) -> String {
+    let budget = provider.prompt_budget();
+    let assembled = prompt::assemble(sections, budget, &|text| provider.estimate_tokens(text));
+    println!("Prompt for {}: ~{} of {} tokens", provider.name(), assembled.tokens, budget);
+    for note in &assembled.dropped {
+        println!("  {} to fit {}", note, provider.name());
     }
+    assembled.text
+}
 
-    // Gemini
-    if let Ok(gemini_key) = env::var("GEMINI_API_KEY") {
-        println!("Using Google with key: {}****", &gemini_key[0..8]);
-        match google::query_gemini(&query).await {
-            Ok(response) => responses.push(format!("## Gemini response\n\n {}", response)),
-            Err(e) => eprintln!("Error querying Gemini: {}", e),
+/// The base name of the files for a match: `<match_guid>`, or `<match_guid>.<player>` when
+/// coaching a single player, with anything but letters, digits, `-` and `_` in the name
+/// replaced by `_`.
+pub fn output_name(match_guid: &str, player: Option<&str>) -> String {
+    match player {
+        Some(player) => {
+            let player: String = player
+                .chars()
+                .map(|c| if c.is_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+                .collect();
+            format!("{}.{}", match_guid, player)
         }
+        None => match_guid.to_string(),
     }
+}
 
-    // Handle case where no providers are configured
-    if responses.is_empty() {
-        return Err(io::Error::new(
-            io::ErrorKind::NotFound,
-            "No AI providers configured. Please set one or more of the following: OPENAI_API_KEY, ANTHROPIC_API_KEY, GEMINI_API_KEY, COPILOT_API_KEY.",
-        ));
+/// The plots of a match to attach to the prompt, see `image::plots`.
+fn plots(match_guid: &str) -> Vec<Image> {
+    let images = image::plots(match_guid);
+    if images.is_empty() {
+        println!("No plots of {} to attach; run plot first", match_guid);
+    } else {
+        println!("Attaching {} plots for the providers that accept images", images.len());
     }
+    images
+}
 
+/// How long to wait for the providers, and the synthesis after them, from
+/// `RATTLEBRAIN_DEADLINE` in seconds.
+fn deadline() -> Duration {
+    env::var("RATTLEBRAIN_DEADLINE")
+        .ok()
+        .and_then(|secs| secs.parse().ok())
+        .map(Duration::from_secs)
+        .unwrap_or(Duration::from_secs(DEFAULT_DEADLINE_SECS))
+}
 
-    // Combine all responses
-    let combined_response = responses.join("\n");
-    Ok(combined_response)
+/// What is left of the deadline of a run that started querying the providers at `started`.
+fn time_left(started: Instant) -> Duration {
+    deadline().saturating_sub(started.elapsed())
 }
 
+/// Queries every provider at once and waits at most `deadline` for all of them. Providers
+/// that fail or run out of time are reported as errors rather than failing the rest.
+/// `images` are attached, with a caption, for the providers that accept images.
+pub async fn query_providers(
+    registry: &Registry,
+    sections: &[Section],
+    images: &[Image],
+    deadline: Duration,
+) -> Vec<ProviderResult> {
+    run_providers(registry, deadline, |provider| async move {
+        let printer = LinePrinter::new(provider.name());
+        let mut prompt = fit_prompt(provider, sections);
+        let images = if provider.capabilities().images { images } else { &[] };
+        if let Some(caption) = image::caption(images) {
+            prompt = format!("{}\n\n{}", prompt, caption);
+        }
+        let answer = provider
+            .query_streaming_with_images(&prompt, images, &|text| printer.push(text))
+            .await;
+        printer.finish();
+        answer
+    })
+    .await
+}
 
+/// Prints a streamed answer as its lines complete, each prefixed with the provider's name
+/// so that answers streaming at the same time stay readable.
+struct LinePrinter {
+    prefix: String,
+    pending: Mutex<String>,
+}
 
-// Function to filter, compress, and Base64 encode the frames CSV
-fn filter_compress_and_encode_frames(csv_content: &str) -> Result<String, Box<dyn std::error::Error>> {
-    // Step 1: Filter and clean the CSV
-    let filtered_csv = filter_frames_csv(csv_content)?;
+impl LinePrinter {
+    fn new(name: &str) -> Self {
+        LinePrinter {
+            prefix: format!("[{}] ", name),
+            pending: Mutex::default(),
+        }
+    }
 
-    // Step 2: Compress the filtered CSV using gzip
-    let compressed_csv = compress_csv(&filtered_csv)?;
+    fn push(&self, text: &str) {
+        let mut pending = self.pending.lock().unwrap();
+        pending.push_str(text);
+        while let Some(end) = pending.find('\n') {
+            println!("{}{}", self.prefix, &pending[..end]);
+            pending.drain(..=end);
+        }
+    }
 
-    // Step 3: Base64 encode the compressed CSV
-    let base64_encoded_csv = base64::engine::general_purpose::STANDARD.encode(&compressed_csv);
+    /// Prints what's left of the last line.
+    fn finish(&self) {
+        let mut pending = self.pending.lock().unwrap();
+        if !pending.is_empty() {
+            println!("{}{}", self.prefix, pending);
+            pending.clear();
+        }
+    }
+}
 
-    Ok(base64_encoded_csv)
+/// Runs `call` against every provider concurrently, timing each and giving up on any
+/// still running at the deadline. Results are in registry order.
+async fn run_providers<'a, T, F, Fut>(registry: &'a Registry, deadline: Duration, call: F) -> Vec<ProviderResult<T>>
+where
+    F: Fn(&'a dyn Provider) -> Fut,
+    Fut: Future<Output = Result<T, ProviderError>>,
+{
+    let deadline = tokio::time::Instant::now() + deadline;
+    let queries = registry.providers().iter().map(|provider| {
+        let call = call(provider.as_ref());
+        async move {
+            let started = Instant::now();
+            let outcome = match tokio::time::timeout_at(deadline, call).await {
+                Ok(Ok(response)) => Ok(response),
+                Ok(Err(e)) => Err(e.to_string()),
+                Err(_) => Err("deadline exceeded".to_string()),
+            };
+            let result = ProviderResult {
+                name: provider.name().to_string(),
+                elapsed: started.elapsed(),
+                outcome,
+            };
+            match &result.outcome {
+                Ok(_) => println!("{} answered in {:.1}s", result.name, result.elapsed.as_secs_f64()),
+                Err(e) => eprintln!("Error querying {}: {}", result.name, e),
+            }
+            result
+        }
+    });
+    join_all(queries).await
 }
 
-// Function to compress CSV content
-fn compress_csv(content: &str) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
-    use flate2::write::GzEncoder;
-    use flate2::Compression;
+/// The combined report: a timing table, the unified report if the synthesis succeeded,
+/// and each provider's answer in registry order (as an appendix after a unified report).
+pub fn render_report(results: &[ProviderResult], synthesis: Option<&ProviderResult>) -> String {
+    let mut responses = Vec::new();
+    let header_response = "# Rattlebrain Replay Analysis\n\n\n".to_string();
+    responses.push(header_response);
 
-    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
-    encoder.write_all(content.as_bytes())?;
-    let compressed_data = encoder.finish()?;
-    Ok(compressed_data)
-}
+    let mut timing = String::from("| Provider | Status | Time |\n|---|---|---|\n");
+    for result in results {
+        timing.push_str(&timing_row(&result.name, result));
+    }
+    if let Some(synthesis) = synthesis {
+        timing.push_str(&timing_row(&format!("{} (synthesis)", synthesis.name), synthesis));
+    }
+    responses.push(timing);
 
+    let mut heading = "##";
+    if let Some(ProviderResult { name, outcome: Ok(report), .. }) = synthesis {
+        responses.push(format!("## Unified report\n\n_Synthesized by {}._\n\n{}\n", name, report));
+        responses.push("## Appendix: provider responses\n".to_string());
+        heading = "###";
+    }
 
-fn filter_frames_csv(csv_content: &str) -> Result<String, Box<dyn Error>> {
-    // Read the CSV
-    let mut reader = ReaderBuilder::new().has_headers(true).from_reader(csv_content.as_bytes());
-
-    // Prepare a writer for the filtered CSV
-    let mut wtr = Writer::from_writer(Vec::new());
-
-    // Write headers for the filtered CSV
-    wtr.write_record(&[
-        "frame",
-        "player",
-        "boost",
-        "location_x",
-        "location_y",
-        "location_z",
-        "rotation_x",
-        "rotation_y",
-        "rotation_z",
-        "rotation_w",
-        "angular_velocity_magnitude",
-        "linear_velocity_magnitude",
-    ])?;
-
-    // Group rows by frame number
-    let mut frame_groups: HashMap<String, Vec<csv::StringRecord>> = HashMap::new();
-    for result in reader.records() {
-        let record = result?;
-
-        // Skip rows where location is (0, 0, 0) unless the player is "_ball_"
-        let player_name = record.get(3).unwrap_or("_unknown_");
-        let location_x: f64 = record.get(5).unwrap_or("0").parse().unwrap_or(0.0);
-        let location_y: f64 = record.get(6).unwrap_or("0").parse().unwrap_or(0.0);
-        let location_z: f64 = record.get(7).unwrap_or("0").parse().unwrap_or(0.0);
-
-        if location_x == 0.0 && location_y == 0.0 && location_z == 0.0 && player_name != "_ball_" {
-            continue;
+    for result in results {
+        if let Ok(response) = &result.outcome {
+            responses.push(format!("{} {} response\n\n {}", heading, result.name, response));
         }
-        // Add record to the group for the corresponding frame
-        let frame = record.get(0).unwrap_or_default().to_string();
-        frame_groups.entry(frame).or_default().push(record);
     }
 
-    // Collect and sort all unique frames numerically
-    let mut sorted_frames: Vec<_> = frame_groups.keys().cloned().collect();
-    sorted_frames.sort_by(|a, b| a.parse::<usize>().unwrap_or(usize::MAX).cmp(&b.parse::<usize>().unwrap_or(usize::MAX)));
-
-    // Process every nth frame
-    let nth = 30;
-    for (i, frame) in sorted_frames.into_iter().enumerate() {
-        if i % nth == 0 {
-            if let Some(records) = frame_groups.get(&frame) {
-                for record in records {
-                    let player_name = record.get(3).unwrap_or("_unknown_");
-                    let boost = record.get(4).unwrap_or("0").parse().unwrap_or(0);
-                    let location_x: f64 = record.get(5).unwrap_or("0").parse().unwrap_or(0.0);
-                    let location_y: f64 = record.get(6).unwrap_or("0").parse().unwrap_or(0.0);
-                    let location_z: f64 = record.get(7).unwrap_or("0").parse().unwrap_or(0.0);
-                    let rotation_x: f64 = record.get(8).unwrap_or("0").parse().unwrap_or(0.0);
-                    let rotation_y: f64 = record.get(9).unwrap_or("0").parse().unwrap_or(0.0);
-                    let rotation_z: f64 = record.get(10).unwrap_or("0").parse().unwrap_or(0.0);
-                    let rotation_w: f64 = record.get(11).unwrap_or("0").parse().unwrap_or(0.0);
-
-                    let angular_velocity_x: f64 = record.get(12).unwrap_or("0").parse().unwrap_or(0.0);
-                    let angular_velocity_y: f64 = record.get(13).unwrap_or("0").parse().unwrap_or(0.0);
-                    let angular_velocity_z: f64 = record.get(14).unwrap_or("0").parse().unwrap_or(0.0);
-
-                    let linear_velocity_x: f64 = record.get(15).unwrap_or("0").parse().unwrap_or(0.0);
-                    let linear_velocity_y: f64 = record.get(16).unwrap_or("0").parse().unwrap_or(0.0);
-                    let linear_velocity_z: f64 = record.get(17).unwrap_or("0").parse().unwrap_or(0.0);
-
-                    // Compute velocity magnitudes
-                    let angular_velocity_magnitude = (angular_velocity_x.powi(2)
-                        + angular_velocity_y.powi(2)
-                        + angular_velocity_z.powi(2))
-                    .sqrt();
-                    let linear_velocity_magnitude = (linear_velocity_x.powi(2)
-                        + linear_velocity_y.powi(2)
-                        + linear_velocity_z.powi(2))
-                    .sqrt();
-
-                    // Write filtered and aggregated row
-                    wtr.write_record(&[
-                        frame.clone(),
-                        player_name.to_string(),
-                        boost.to_string(),
-                        location_x.to_string(),
-                        location_y.to_string(),
-                        location_z.to_string(),
-                        rotation_x.to_string(),
-                        rotation_y.to_string(),
-                        rotation_z.to_string(),
-                        rotation_w.to_string(),
-                        angular_velocity_magnitude.to_string(),
-                        linear_velocity_magnitude.to_string(),
-                    ])?;
-                }
-            }
-        }
+    // Combine all responses
+    responses.join("\n")
+}
+
+/// Prints what the run's requests used and cost, and appends it to `report`.
+fn with_usage(registry: &Registry, mut report: String) -> String {
+    let summary = registry.meter().summary();
+    if !summary.is_empty() {
+        println!("Usage:\n{}", summary);
+        report.push_str(&format!("\n## Usage\n\n{}", summary));
     }
+    report
+}
 
-    // Return the filtered CSV as a String
-    let filtered_csv = String::from_utf8(wtr.into_inner()?)?;
-    // println!("{}", filtered_csv);
-    Ok(filtered_csv)
+fn timing_row(label: &str, result: &ProviderResult) -> String {
+    let status = match &result.outcome {
+        Ok(_) => "answered".to_string(),
+        Err(e) => format!("failed: {}", e.replace('|', "\\|").replace('\n', " ")),
+    };
+    format!("| {} | {} | {:.1}s |\n", label, status, result.elapsed.as_secs_f64())
 }
@@ -0,0 +1,108 @@
+use crate::events::{self, GOAL_LINE_Y};
+use crate::plot::{self, GameState};
+use csv::Writer;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::error::Error;
+
+/// How much of a match each player spent in each third of the field, seen from their own
+/// team's attacking direction.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FieldThirds {
+    pub player_name: String,
+    pub team: Option<u32>,
+    pub samples: usize,
+    pub defensive_third: f64,
+    pub neutral_third: f64,
+    pub offensive_third: f64,
+}
+
+/// Computes field thirds for a converted match and writes them to
+/// `./output/<match_guid>.positioning.csv`.
+pub fn positioning(match_guid: &str) -> Result<Vec<FieldThirds>, Box<dyn Error>> {
+    let frames_csv_path = format!("./output/{}.replay.frames.json.csv", match_guid);
+    let positioning_csv_path = format!("./output/{}.positioning.csv", match_guid);
+
+    let mut data = plot::load_frames(&frames_csv_path)?;
+    plot::normalize_sides(&mut data);
+    let thirds = field_thirds(&data);
+
+    let mut writer = Writer::from_path(&positioning_csv_path)?;
+    for row in &thirds {
+        writer.serialize(row)?;
+    }
+    writer.flush()?;
+
+    println!("Saved positioning: {}", positioning_csv_path);
+    Ok(thirds)
+}
+
+/// Share of each player's position samples in the defensive, neutral and offensive thirds.
+/// Expects side-normalized data (see `plot::normalize_sides`), where everyone attacks +Y.
+pub fn field_thirds(data: &[GameState]) -> Vec<FieldThirds> {
+    let third = GOAL_LINE_Y * 2.0 / 3.0 * events::position_scale(data);
+    let mut counts: BTreeMap<&str, (Option<u32>, [usize; 3])> = BTreeMap::new();
+
+    for state in data.iter().filter(|s| !s.is_ball()) {
+        // Spawn rows without a position would all land in the neutral third
+        if state.location_x == 0.0 && state.location_y == 0.0 && state.location_z == 0.0 {
+            continue;
+        }
+
+        let entry = counts.entry(state.player_name.as_str()).or_insert((state.team, [0; 3]));
+        let index = if state.location_y < -third / 2.0 {
+            0
+        } else if state.location_y > third / 2.0 {
+            2
+        } else {
+            1
+        };
+        entry.1[index] += 1;
+    }
+
+    counts
+        .into_iter()
+        .map(|(player_name, (team, [defensive, neutral, offensive]))| {
+            let samples = defensive + neutral + offensive;
+            let share = |count: usize| count as f64 / samples.max(1) as f64;
+            FieldThirds {
+                player_name: player_name.to_string(),
+                team,
+                samples,
+                defensive_third: share(defensive),
+                neutral_third: share(neutral),
+                offensive_third: share(offensive),
+            }
+        })
+        .collect()
+}
+
+/// Combines field thirds for the same player across several matches, weighting each
+/// match by how many samples it has. Teams are dropped since players switch sides.
+pub fn aggregate_field_thirds(matches: &[Vec<FieldThirds>]) -> Vec<FieldThirds> {
+    let mut totals: BTreeMap<&str, (usize, [f64; 3])> = BTreeMap::new();
+
+    for row in matches.iter().flatten() {
+        let entry = totals.entry(row.player_name.as_str()).or_insert((0, [0.0; 3]));
+        let weight = row.samples as f64;
+        entry.0 += row.samples;
+        entry.1[0] += row.defensive_third * weight;
+        entry.1[1] += row.neutral_third * weight;
+        entry.1[2] += row.offensive_third * weight;
+    }
+
+    totals
+        .into_iter()
+        .map(|(player_name, (samples, [defensive, neutral, offensive]))| {
+            let total = samples.max(1) as f64;
+            FieldThirds {
+                player_name: player_name.to_string(),
+                team: None,
+                samples,
+                defensive_third: defensive / total,
+                neutral_third: neutral / total,
+                offensive_third: offensive / total,
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,206 @@
+use crate::prompt::Section;
+use std::fs;
+use std::path::Path;
+
+/// Where a team's prompt templates live, unless configured otherwise.
+pub const DEFAULT_DIR: &str = "./templates";
+
+/// The templates shipped with rattlebrain, used for any focus the templates directory
+/// doesn't override.
+const BUILT_IN: [(&str, &str); 4] = [
+    ("all", include_str!("templates/all.md")),
+    ("strategy", include_str!("templates/strategy.md")),
+    ("mechanics", include_str!("templates/mechanics.md")),
+    ("decision_making", include_str!("templates/decision_making.md")),
+];
+
+/// The match data a template can place, by placeholder name and a piece of the title of the
+/// prompt section holding it.
+pub const SECTIONS: [(&str, &str); 9] = [
+    ("player_statistics", "Player statistics"),
+    ("goals", "Goal breakdown"),
+    ("highlights", "Highlights"),
+    ("overview", "Match overview"),
+    ("player_stats", "Player stats"),
+    ("comparison", "compared with teammates and opponents"),
+    ("rotation_roles", "Rotation roles"),
+    ("timeline", "Timeline"),
+    ("key_moments", "Key moments"),
+];
+
+/// The brief for one focus, read from `<focus>.md`. Its text is what the coach is asked to
+/// do, with these placeholders:
+///
+/// - `{{player}}`, `{{focus}}` and `{{match_guid}}` are replaced by their values;
+/// - `{{#player}}...{{/player}}` is kept only when coaching one player, and
+///   `{{^player}}...{{/player}}` only when coaching the whole match;
+/// - a line holding nothing but a section name from `SECTIONS`, e.g. `{{timeline}}`, puts
+///   that match data in the prompt. Without any, the prompt carries all of it.
+#[derive(Debug, Clone)]
+pub struct Template {
+    pub focus: String,
+    /// The file it was read from, or `built-in`.
+    pub source: String,
+    text: String,
+}
+
+/// A template filled in for one match.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Brief {
+    pub instructions: Vec<String>,
+    /// The names of the sections to include, in order; `None` for all of them.
+    pub sections: Option<Vec<String>>,
+}
+
+impl Template {
+    pub fn new(focus: &str, source: &str, text: &str) -> Self {
+        Template {
+            focus: focus.to_string(),
+            source: source.to_string(),
+            text: text.to_string(),
+        }
+    }
+
+    pub fn render(&self, match_guid: &str, player: Option<&str>) -> Result<Brief, String> {
+        self.fill(match_guid, player).map_err(|e| format!("Error in template {}: {}", self.source, e))
+    }
+
+    fn fill(&self, match_guid: &str, player: Option<&str>) -> Result<Brief, String> {
+        let text = blocks(&self.text, player.is_some())?;
+        let mut instructions = Vec::new();
+        let mut sections = Vec::new();
+        for line in text.lines() {
+            let placeholder = line.trim().strip_prefix("{{").and_then(|rest| rest.strip_suffix("}}"));
+            match placeholder.map(str::trim) {
+                Some(name) if is_section(name) => sections.push(name.to_string()),
+                _ => instructions.push(substitute(line, &self.focus, match_guid, player)?),
+            }
+        }
+
+        while instructions.last().is_some_and(|line| line.trim().is_empty()) {
+            instructions.pop();
+        }
+        let blank_lines = instructions.iter().take_while(|line| line.trim().is_empty()).count();
+        instructions.drain(..blank_lines);
+        Ok(Brief {
+            instructions,
+            sections: (!sections.is_empty()).then_some(sections),
+        })
+    }
+}
+
+impl Brief {
+    /// The sections of `sections` the template asked for, in its order.
+    pub fn select(&self, sections: Vec<Section>) -> Vec<Section> {
+        let Some(names) = &self.sections else {
+            return sections;
+        };
+        names
+            .iter()
+            .filter_map(|name| SECTIONS.iter().find(|(key, _)| key == name))
+            .flat_map(|(_, title)| sections.iter().filter(move |section| section.title.contains(title)).cloned())
+            .collect()
+    }
+}
+
+/// The template for `focus`: `<dir>/<focus>.md` if there is one, otherwise the built-in one.
+pub fn load(dir: &str, focus: &str) -> Result<Template, String> {
+    if focus.is_empty() || !focus.chars().all(|c| c.is_alphanumeric() || c == '-' || c == '_') {
+        return Err(format!("Invalid focus {:?}: use letters, digits, - and _", focus));
+    }
+    let path = Path::new(dir).join(format!("{}.md", focus));
+    if path.is_file() {
+        let text = fs::read_to_string(&path).map_err(|e| format!("Error reading template {}: {}", path.display(), e))?;
+        return Ok(Template::new(focus, &path.display().to_string(), &text));
+    }
+    match BUILT_IN.iter().find(|(name, _)| *name == focus) {
+        Some((_, text)) => Ok(Template::new(focus, "built-in", text)),
+        None => Err(format!("Unknown focus {}. Available: {}", focus, focuses(dir).join(", "))),
+    }
+}
+
+/// Every focus there is a template for, built-in or in `dir`, sorted.
+pub fn focuses(dir: &str) -> Vec<String> {
+    let mut focuses: Vec<String> = BUILT_IN.iter().map(|(name, _)| name.to_string()).collect();
+    if let Ok(entries) = fs::read_dir(dir) {
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.extension().is_some_and(|extension| extension == "md") {
+                if let Some(stem) = path.file_stem().and_then(|stem| stem.to_str()) {
+                    focuses.push(stem.to_string());
+                }
+            }
+        }
+    }
+    focuses.sort();
+    focuses.dedup();
+    focuses
+}
+
+fn is_section(name: &str) -> bool {
+    SECTIONS.iter().any(|(key, _)| *key == name)
+}
+
+/// Keeps or drops the `{{#player}}` and `{{^player}}` blocks of `text`. A tag on a line of
+/// its own takes the line with it.
+fn blocks(text: &str, has_player: bool) -> Result<String, String> {
+    let mut result = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("{{#").into_iter().chain(rest.find("{{^")).min() {
+        let close = rest[start..].find("}}").ok_or("unclosed {{")? + start;
+        let name = rest[start + 3..close].trim();
+        if name != "player" {
+            return Err(format!("unknown block {}", &rest[start..close + 2]));
+        }
+        let keep = rest[start + 2..].starts_with('#') == has_player;
+        let end_tag = "{{/player}}";
+        let end = rest[close..].find(end_tag).ok_or_else(|| format!("{} has no {}", &rest[start..close + 2], end_tag))? + close;
+
+        result.push_str(&rest[..start]);
+        let inner = tag_line_end(rest, start, close + 2);
+        if keep {
+            result.push_str(&rest[inner..end]);
+        }
+        rest = &rest[tag_line_end(rest, end, end + end_tag.len())..];
+    }
+    if rest.contains("{{/") {
+        return Err("a closing tag has no opening tag".to_string());
+    }
+    result.push_str(rest);
+    Ok(result)
+}
+
+/// Where the text after the tag from `start` to `end` resumes: past the newline if the tag
+/// is alone on its line.
+fn tag_line_end(text: &str, start: usize, end: usize) -> usize {
+    let line_start = text[..start].rfind('\n').map(|i| i + 1).unwrap_or(0);
+    let alone = text[line_start..start].trim().is_empty();
+    match text[end..].strip_prefix('\n') {
+        Some(_) if alone => end + 1,
+        _ => end,
+    }
+}
+
+/// Replaces the variables in `line`.
+fn substitute(line: &str, focus: &str, match_guid: &str, player: Option<&str>) -> Result<String, String> {
+    let mut result = String::new();
+    let mut rest = line;
+    while let Some(start) = rest.find("{{") {
+        let close = rest[start..].find("}}").ok_or("unclosed {{")? + start;
+        let name = rest[start + 2..close].trim();
+        result.push_str(&rest[..start]);
+        match name {
+            "focus" => result.push_str(focus),
+            "match_guid" => result.push_str(match_guid),
+            "player" => match player {
+                Some(player) => result.push_str(player),
+                None => return Err("{{player}} is only set when coaching one player; put it in {{#player}}...{{/player}}".to_string()),
+            },
+            name if is_section(name) => return Err(format!("{{{{{}}}}} must be on a line of its own", name)),
+            name => return Err(format!("unknown placeholder {{{{{}}}}}", name)),
+        }
+        rest = &rest[close + 2..];
+    }
+    result.push_str(rest);
+    Ok(result)
+}
@@ -0,0 +1,334 @@
+use crate::events::{self, FIELD_HALF_WIDTH, GOAL_DEPTH, GOAL_HALF_WIDTH, GOAL_LINE_Y};
+use crate::plot::{self, GameState};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+/// Samples per second of match time stored in the viewer.
+const SAMPLE_RATE: f64 = 10.0;
+
+/// Exports `./output/<match_guid>.viewer.html`, a self-contained top-down playback of the match.
+pub fn export_viewer(match_guid: &str) -> Result<String, Box<dyn Error>> {
+    let frames_csv_path = format!("./output/{}.replay.frames.json.csv", match_guid);
+    let goals_csv_path = format!("./output/{}.goals.json.csv", match_guid);
+    let viewer_path = format!("./output/{}.viewer.html", match_guid);
+
+    let data = plot::load_frames(&frames_csv_path)?;
+    let goals = if Path::new(&goals_csv_path).exists() {
+        events::load_goals(&goals_csv_path)?
+    } else {
+        Vec::new()
+    };
+
+    let mut match_data = resample(&data, SAMPLE_RATE);
+
+    // Bookmark each goal a few seconds before it goes in
+    let frame_times: HashMap<usize, f64> = data.iter().map(|s| (s.frame, s.time)).collect();
+    match_data["goals"] = goals
+        .iter()
+        .filter_map(|goal| {
+            frame_times.get(&goal.frame).map(|time| {
+                json!({
+                    "time": time,
+                    "team": goal.team,
+                    "player": goal.player_name,
+                })
+            })
+        })
+        .collect();
+    match_data["title"] = json!(match_guid);
+
+    fs::write(&viewer_path, render_html(&match_data))?;
+    println!("Saved viewer: {}", viewer_path);
+    Ok(viewer_path)
+}
+
+/// Resamples the frames to a fixed rate, holding each object's last known state.
+/// Positions are converted to unreal units and rounded to keep the file small.
+pub fn resample(data: &[GameState], sample_rate: f64) -> Value {
+    let scale = events::position_scale(data);
+
+    let mut rows: Vec<&GameState> = data.iter().collect();
+    rows.sort_by(|a, b| a.time.total_cmp(&b.time));
+
+    let mut players: Vec<(&str, Option<u32>)> = rows
+        .iter()
+        .filter(|s| !s.is_ball())
+        .map(|s| (s.player_name.as_str(), s.team))
+        .collect();
+    players.sort();
+    players.dedup_by_key(|(name, _)| *name);
+    let player_index: HashMap<&str, usize> = players
+        .iter()
+        .enumerate()
+        .map(|(index, (name, _))| (*name, index))
+        .collect();
+
+    let start = rows.first().map(|s| s.time).unwrap_or(0.0);
+    let end = rows.last().map(|s| s.time).unwrap_or(0.0);
+    let step = 1.0 / sample_rate;
+
+    let mut latest_players: Vec<Value> = vec![Value::Null; players.len()];
+    let mut latest_ball = Value::Null;
+    let mut samples = Vec::new();
+    let mut next_row = 0;
+    let sample_count = ((end - start) / step + 1e-9).floor() as usize + 1;
+
+    for sample in 0..sample_count {
+        let time = start + sample as f64 * step;
+        while next_row < rows.len() && rows[next_row].time <= time {
+            let state = rows[next_row];
+            let x = (state.location_x / scale).round();
+            let y = (state.location_y / scale).round();
+            if state.is_ball() {
+                latest_ball = json!([x, y, (state.location_z / scale).round()]);
+            } else if let Some(&index) = player_index.get(state.player_name.as_str()) {
+                let boost = state.boost_percent().map(Value::from).unwrap_or(Value::Null);
+                latest_players[index] = json!([x, y, boost]);
+            }
+            next_row += 1;
+        }
+
+        samples.push(json!({
+            "ball": latest_ball,
+            "players": latest_players,
+        }));
+    }
+
+    json!({
+        "start": start,
+        "step": step,
+        "field": {
+            "half_width": FIELD_HALF_WIDTH,
+            "goal_line": GOAL_LINE_Y,
+            "goal_half_width": GOAL_HALF_WIDTH,
+            "goal_depth": GOAL_DEPTH,
+        },
+        "players": players
+            .iter()
+            .map(|(name, team)| json!({ "name": name, "team": team }))
+            .collect::<Vec<_>>(),
+        "samples": samples,
+    })
+}
+
+fn render_html(match_data: &Value) -> String {
+    // Keep a "</script>" inside player names from closing the data block early
+    let data = match_data.to_string().replace("</", "<\\/");
+    VIEWER_TEMPLATE
+        .replace("__TITLE__", match_data["title"].as_str().unwrap_or("Replay"))
+        .replace("__MATCH_DATA__", &data)
+}
+
+const VIEWER_TEMPLATE: &str = r##"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<title>Rattlebrain Replay Viewer - __TITLE__</title>
+<style>
+  body { font-family: sans-serif; margin: 0; display: flex; gap: 16px; padding: 16px; background: #f4f4f4; }
+  canvas { background: #3a7d44; border-radius: 8px; }
+  #panel { min-width: 260px; }
+  #panel section { margin-bottom: 16px; }
+  #controls button, #goals button { margin: 2px; }
+  #scrub { width: 100%; }
+  .blue { color: #1f4fff; }
+  .orange { color: #e08000; }
+</style>
+</head>
+<body>
+<canvas id="field" width="600" height="880"></canvas>
+<div id="panel">
+  <h2>__TITLE__</h2>
+  <section id="controls">
+    <button id="play">Play</button>
+    <label>Speed
+      <select id="speed">
+        <option value="0.25">0.25x</option>
+        <option value="0.5">0.5x</option>
+        <option value="1" selected>1x</option>
+        <option value="2">2x</option>
+        <option value="4">4x</option>
+      </select>
+    </label>
+    <div><input id="scrub" type="range" min="0" value="0" step="1"></div>
+    <div id="clock"></div>
+  </section>
+  <section>
+    <h3>Players</h3>
+    <div id="players"></div>
+  </section>
+  <section>
+    <h3>Goals</h3>
+    <div id="goals"></div>
+  </section>
+</div>
+<script>
+const MATCH = __MATCH_DATA__;
+const TEAM_COLORS = { 0: "#1f4fff", 1: "#ff9900" };
+
+const canvas = document.getElementById("field");
+const ctx = canvas.getContext("2d");
+const scrub = document.getElementById("scrub");
+const clock = document.getElementById("clock");
+const playButton = document.getElementById("play");
+const speedSelect = document.getElementById("speed");
+
+const field = MATCH.field;
+const halfLength = field.goal_line + field.goal_depth;
+const visible = MATCH.players.map(() => true);
+let index = 0;
+let playing = false;
+let lastTick = null;
+let elapsed = 0;
+
+scrub.max = Math.max(MATCH.samples.length - 1, 0);
+
+function toCanvas(x, y) {
+  return [
+    (x + field.half_width) / (2 * field.half_width) * canvas.width,
+    (1 - (y + halfLength) / (2 * halfLength)) * canvas.height,
+  ];
+}
+
+function drawField() {
+  ctx.clearRect(0, 0, canvas.width, canvas.height);
+  ctx.strokeStyle = "rgba(255, 255, 255, 0.8)";
+  ctx.lineWidth = 2;
+  const [left, top] = toCanvas(-field.half_width, field.goal_line);
+  const [right, bottom] = toCanvas(field.half_width, -field.goal_line);
+  ctx.strokeRect(left, top, right - left, bottom - top);
+  const [, middle] = toCanvas(0, 0);
+  ctx.beginPath();
+  ctx.moveTo(left, middle);
+  ctx.lineTo(right, middle);
+  ctx.stroke();
+
+  for (const [team, sign] of [[0, -1], [1, 1]]) {
+    const [goalLeft, goalTop] = toCanvas(-field.goal_half_width, sign > 0 ? halfLength : -field.goal_line);
+    const [goalRight, goalBottom] = toCanvas(field.goal_half_width, sign > 0 ? field.goal_line : -halfLength);
+    ctx.fillStyle = TEAM_COLORS[team] + "55";
+    ctx.fillRect(goalLeft, goalTop, goalRight - goalLeft, goalBottom - goalTop);
+  }
+}
+
+function formatTime(seconds) {
+  const minutes = Math.floor(seconds / 60);
+  const rest = Math.floor(seconds % 60).toString().padStart(2, "0");
+  return minutes + ":" + rest;
+}
+
+function draw() {
+  drawField();
+  const sample = MATCH.samples[index];
+  if (!sample) return;
+
+  sample.players.forEach((state, i) => {
+    if (!state || !visible[i]) return;
+    const player = MATCH.players[i];
+    const [x, y] = toCanvas(state[0], state[1]);
+    ctx.fillStyle = TEAM_COLORS[player.team] || "#888888";
+    ctx.beginPath();
+    ctx.arc(x, y, 8, 0, 2 * Math.PI);
+    ctx.fill();
+
+    ctx.fillStyle = "#ffffff";
+    ctx.font = "12px sans-serif";
+    ctx.fillText(player.name, x + 10, y - 4);
+    if (state[2] !== null) {
+      ctx.fillStyle = "#222222";
+      ctx.fillRect(x + 10, y + 2, 40, 5);
+      ctx.fillStyle = "#ffd23f";
+      ctx.fillRect(x + 10, y + 2, 40 * state[2] / 100, 5);
+    }
+  });
+
+  if (sample.ball) {
+    const [x, y] = toCanvas(sample.ball[0], sample.ball[1]);
+    const radius = 6 + Math.min(sample.ball[2] / 300, 8);
+    ctx.fillStyle = "#eeeeee";
+    ctx.strokeStyle = "#111111";
+    ctx.beginPath();
+    ctx.arc(x, y, radius, 0, 2 * Math.PI);
+    ctx.fill();
+    ctx.stroke();
+  }
+
+  scrub.value = index;
+  clock.textContent = formatTime(MATCH.start + index * MATCH.step);
+}
+
+function seekTime(time) {
+  index = Math.max(0, Math.min(MATCH.samples.length - 1, Math.round((time - MATCH.start) / MATCH.step)));
+  elapsed = 0;
+  draw();
+}
+
+function tick(timestamp) {
+  if (!playing) return;
+  if (lastTick !== null) {
+    elapsed += (timestamp - lastTick) / 1000 * parseFloat(speedSelect.value);
+    const advance = Math.floor(elapsed / MATCH.step);
+    if (advance > 0) {
+      elapsed -= advance * MATCH.step;
+      index = Math.min(index + advance, MATCH.samples.length - 1);
+      draw();
+      if (index === MATCH.samples.length - 1) togglePlay();
+    }
+  }
+  lastTick = timestamp;
+  requestAnimationFrame(tick);
+}
+
+function togglePlay() {
+  playing = !playing;
+  playButton.textContent = playing ? "Pause" : "Play";
+  lastTick = null;
+  if (playing) requestAnimationFrame(tick);
+}
+
+playButton.addEventListener("click", togglePlay);
+scrub.addEventListener("input", () => {
+  index = parseInt(scrub.value, 10);
+  draw();
+});
+document.addEventListener("keydown", (event) => {
+  if (event.code === "Space") {
+    event.preventDefault();
+    togglePlay();
+  }
+});
+
+const playerList = document.getElementById("players");
+MATCH.players.forEach((player, i) => {
+  const label = document.createElement("label");
+  label.className = player.team === 0 ? "blue" : "orange";
+  const checkbox = document.createElement("input");
+  checkbox.type = "checkbox";
+  checkbox.checked = true;
+  checkbox.addEventListener("change", () => {
+    visible[i] = checkbox.checked;
+    draw();
+  });
+  label.appendChild(checkbox);
+  label.appendChild(document.createTextNode(" " + player.name));
+  playerList.appendChild(label);
+  playerList.appendChild(document.createElement("br"));
+});
+
+const goalList = document.getElementById("goals");
+(MATCH.goals || []).forEach((goal) => {
+  const button = document.createElement("button");
+  button.className = goal.team === 0 ? "blue" : "orange";
+  button.textContent = formatTime(goal.time) + " " + goal.player;
+  button.addEventListener("click", () => seekTime(goal.time - 5));
+  goalList.appendChild(button);
+});
+
+draw();
+</script>
+</body>
+</html>
+"##;
@@ -0,0 +1,103 @@
+mod common;
+
+use common::{scoring_match, Mock};
+use rattlebrain::agent::{self, MatchTools};
+use rattlebrain::ai::provider::Capabilities;
+use rattlebrain::ai::tools::{Message, Reply, ToolCall};
+use rattlebrain::digest::MatchData;
+use rattlebrain::events::{self, Goal};
+use serde_json::{json, Value};
+
+/// Blue dribbles up the field and scores while Orange trails behind.
+fn match_tools() -> MatchTools {
+    let frames = scoring_match();
+    let goals = vec![Goal { player_name: "Blue".to_string(), team: 0, frame: 290 }];
+    let events = events::find_events(&frames, &goals, &[]);
+    MatchTools::new(MatchData { frames, goals, events })
+}
+
+fn call(name: &str, arguments: Value) -> ToolCall {
+    ToolCall {
+        id: format!("{}_call", name),
+        name: name.to_string(),
+        arguments,
+    }
+}
+
+#[test]
+fn test_match_tools_answer_from_match_data() {
+    let tools = match_tools();
+
+    let shots = tools.call(&call("get_events", json!({ "type": "shot", "player": "blue" })));
+    assert!(shots.starts_with("[0:"), "{}", shots);
+    assert!(shots.contains("shot by Blue, scored"));
+    assert!(tools.call(&call("get_events", json!({ "type": "goal" }))).contains("f290] goal by Blue (Blue)"));
+    assert_eq!(tools.call(&call("get_events", json!({ "type": "demo" }))), "Nothing found.");
+
+    let positions = tools.call(&call("get_positions", json!({ "frame": 100 })));
+    assert!(positions.starts_with("[0:03 f100]\nball at (0, 1260, 17)"), "{}", positions);
+    assert!(positions.contains("Blue (Blue) at (0, 1060, 17) moving (0, 540, 0) uu/s, boost 66%"), "{}", positions);
+    assert!(tools.call(&call("get_positions", json!({ "frame": 5000 }))).starts_with("Error: the replay has frames 0 to 299"));
+
+    assert!(tools.call(&call("get_possession", json!({}))).starts_with("Blue 100% (Blue 100%)"));
+    assert!(tools.call(&call("get_player_stats", json!({ "player": "Orange" }))).lines().count() == 2);
+    assert_eq!(tools.call(&call("get_replay", json!({}))), "Error: unknown tool get_replay");
+}
+
+/// Asks for the positions at `frame`, without a word.
+fn look_at(frame: usize) -> Reply {
+    Reply {
+        text: String::new(),
+        tool_calls: vec![call("get_positions", json!({ "frame": frame }))],
+    }
+}
+
+#[tokio::test]
+async fn test_investigate_stops_at_step_limit() {
+    let tools = match_tools();
+    // Still calling a tool when it is out of turns
+    let last = Reply {
+        text: "Looked at 3 frames [frames 0-20].".to_string(),
+        ..look_at(30)
+    };
+    let curious = Mock::replying("Curious", look_at(0))
+        .then_reply(look_at(10))
+        .then_reply(look_at(20))
+        .then_reply(last)
+        .with_capabilities(Capabilities {
+            tools: true,
+            ..Capabilities::default()
+        });
+    let transcript = agent::investigate(&curious, &tools, "Coach Blue.", 3).await.unwrap();
+
+    assert_eq!(transcript.answer, "Looked at 3 frames [frames 0-20].");
+    let results: Vec<&Message> = transcript
+        .messages
+        .iter()
+        .filter(|m| matches!(m, Message::ToolResult { .. }))
+        .collect();
+    assert_eq!(results.len(), 3);
+    assert!(matches!(transcript.messages.last(), Some(Message::Assistant { tool_calls, .. }) if tool_calls.is_empty()));
+
+    // The last turn offers no tools, and carries the calls so far as text
+    assert_eq!(*curious.tools_offered().lock().unwrap(), vec![4, 4, 4, 0]);
+    let last_request = curious.conversations().lock().unwrap()[3].clone();
+    assert!(last_request.iter().all(|m| match m {
+        Message::User(_) => true,
+        Message::Assistant { tool_calls, .. } => tool_calls.is_empty(),
+        Message::ToolResult { .. } => false,
+    }));
+    let text: Vec<String> = last_request
+        .iter()
+        .map(|m| match m {
+            Message::User(text) | Message::Assistant { text, .. } => text.clone(),
+            Message::ToolResult { .. } => String::new(),
+        })
+        .collect();
+    assert_eq!(text.join("\n").matches("get_positions returned:").count(), 3);
+
+    let markdown = agent::render_transcript("Curious", &transcript);
+    assert!(markdown.starts_with("## Curious\n\n**Task**\n\nCoach Blue.\n"));
+    assert!(markdown.contains("- calls `get_positions({\"frame\":20})`"));
+    assert!(markdown.contains("`get_positions` returned:\n```\n[0:00 f20]\nball at (0, 0, 17)"));
+}
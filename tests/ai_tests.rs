@@ -0,0 +1,349 @@
+mod common;
+
+use common::{http_response, stub_server, Mock};
+use rattlebrain::ai::anthropic::Anthropic;
+use rattlebrain::ai::config::{Config, ProviderConfig};
+use rattlebrain::ai::google::Gemini;
+use rattlebrain::ai::http::AiError;
+use rattlebrain::ai::image::{self, Image};
+use rattlebrain::ai::openai::OpenAI;
+use rattlebrain::ai::provider::Provider;
+use rattlebrain::ai::registry::{mask_key, Registry};
+use rattlebrain::ai::tools::{Message, Tool, ToolCall};
+use rattlebrain::ai::usage::Meter;
+use serde_json::json;
+use std::sync::Arc;
+
+#[test]
+fn test_registry_select_orders_and_filters() {
+    let mut registry = Registry::new();
+    registry.register(Mock::answering("OpenAI", "Rotate back post."));
+    registry.register(Mock::answering("Anthropic", "Rotate back post."));
+    registry.register(Mock::answering("Gemini", "Rotate back post."));
+
+    registry.select(&["gemini", "OPENAI", "missing"]);
+
+    let names: Vec<&str> = registry.providers().iter().map(|p| p.name()).collect();
+    assert_eq!(names, vec!["Gemini", "OpenAI"]);
+}
+
+#[tokio::test]
+async fn test_registered_provider_is_queried() {
+    let mut registry = Registry::new();
+    assert!(registry.is_empty());
+    let mock = Mock::answering("Local", "Rotate back post.");
+    let prompts = mock.prompts();
+    registry.register(mock);
+
+    let answer = registry.providers()[0].query("hello").await.unwrap();
+    assert_eq!(answer, "Rotate back post.");
+    assert_eq!(*prompts.lock().unwrap(), vec!["hello"]);
+}
+
+#[test]
+fn test_mask_key_handles_short_keys() {
+    assert_eq!(mask_key("sk-1234567890"), "sk-12345****");
+    assert_eq!(mask_key("abc"), "abc****");
+}
+
+const COMPLETION: &str = r#"{"choices":[{"message":{"role":"assistant","content":"Rotate back post."}}]}"#;
+
+fn local_provider(address: &str) -> OpenAI {
+    let config = ProviderConfig {
+        base_url: format!("{}/v1/", address),
+        ..ProviderConfig::local()
+    };
+    OpenAI::with_config("Local", None, config)
+}
+
+#[tokio::test]
+async fn test_openai_compatible_local_server() {
+    let (address, requests) = stub_server(vec![http_response("200 OK", "", COMPLETION)]).await;
+    let provider = local_provider(&address);
+
+    let answer = provider.query("How was my positioning?").await.unwrap();
+    assert_eq!(provider.name(), "Local");
+    assert_eq!(answer, "Rotate back post.");
+
+    let request = &requests.await.unwrap()[0];
+    assert!(request.starts_with("POST /v1/chat/completions"));
+    assert!(request.contains("\"model\":\"llama3.1\""));
+    assert!(!request.to_lowercase().contains("authorization"), "no key, no auth header");
+}
+
+#[tokio::test]
+async fn test_local_server_is_trusted_with_plain_chat_only() {
+    let (address, requests) = stub_server(vec![http_response("200 OK", "", COMPLETION)]).await;
+    let config = ProviderConfig {
+        base_url: format!("{}/v1", address),
+        ..ProviderConfig::local()
+    };
+    let provider = OpenAI::local_from_env(Some(&config)).unwrap();
+    let capabilities = provider.capabilities();
+    assert!(capabilities.streaming && capabilities.system_prompt);
+    assert!(!capabilities.images && !capabilities.tools && !capabilities.structured_output);
+    assert!(local_provider(&address).capabilities().tools, "unless built with with_config");
+
+    let answer = provider.query_structured("Rate my game.", &json!({ "type": "object" })).await.unwrap();
+    assert_eq!(answer, "Rotate back post.");
+    let request = &requests.await.unwrap()[0];
+    assert!(!request.contains("json_schema"), "{}", request);
+    assert!(request.contains("matching this JSON Schema"));
+}
+
+#[tokio::test]
+async fn test_usage_is_metered_and_capped() {
+    let completion = r#"{"choices":[{"message":{"content":"Rotate back post."}}],"usage":{"prompt_tokens":1000,"completion_tokens":50}}"#;
+    let (address, requests) = stub_server(vec![http_response("200 OK", "", completion)]).await;
+    let config = ProviderConfig {
+        base_url: format!("{}/v1", address),
+        max_tokens: Some(100),
+        input_price: Some(1.0),
+        output_price: Some(2.0),
+        ..ProviderConfig::local()
+    };
+    let meter = Arc::new(Meter::new(Some(0.0012)));
+    let provider = OpenAI::with_config("Local", None, config).with_meter(meter.clone());
+
+    assert_eq!(provider.query("How was my positioning?").await.unwrap(), "Rotate back post.");
+    let error = provider.query("And my boost?").await.unwrap_err().to_string();
+    assert!(error.starts_with("Not sending to Local: it could cost up to $0.0002"), "{}", error);
+    let requests = requests.await.unwrap();
+    assert_eq!(requests.len(), 1);
+    assert!(requests[0].contains(r#""max_tokens":100"#), "{}", requests[0]);
+
+    assert_eq!(meter.calls().len(), 1);
+    let summary = meter.summary();
+    assert!(summary.contains("| Local | llama3.1 | 1 | 1000 | 50 | $0.0011 |"), "{}", summary);
+    assert!(summary.ends_with("| **Total** |  | 1 | 1000 | 50 | $0.0011 |\n"));
+
+    // Under a cap, an answer is never longer than what was reserved for it
+    assert_eq!(meter.max_tokens(&ProviderConfig::local()), Some(4096));
+    assert_eq!(Meter::default().max_tokens(&ProviderConfig::local()), None);
+}
+
+#[test]
+fn test_config_file_overrides_defaults() {
+    let mut config = Config::from_json(r#"{"openai": {"model": "gpt-4o-mini", "temperature": 0.3}, "local": {}}"#).unwrap();
+    assert_eq!(config.openai.model, "gpt-4o-mini");
+    assert_eq!(config.openai.temperature, Some(0.3));
+    assert_eq!(config.openai.base_url, ProviderConfig::openai().base_url);
+    assert_eq!(config.anthropic, ProviderConfig::anthropic());
+    assert_eq!(config.local, Some(ProviderConfig::local()));
+
+    config.set("anthropic.max_tokens=4096").unwrap();
+    assert_eq!(config.anthropic.max_tokens, Some(4096));
+    assert!(config.validate().is_ok());
+
+    assert!(Config::from_json(r#"{"openai": {"modle": "gpt-4o"}}"#).is_err(), "unknown field");
+    assert!(Config::from_json(r#"{"mistral": {}}"#).is_err(), "unknown provider");
+    assert!(config.set("openai.temperature").is_err(), "missing value");
+    assert!(config.set("openai.temperature=warm").is_err(), "not a number");
+
+    config.set("openai.temperature=1.5").unwrap();
+    assert!(config.validate().is_ok());
+    config.set("anthropic.temperature=1.5").unwrap();
+    let error = config.validate().unwrap_err().to_string();
+    assert!(error.contains("anthropic") && error.contains("between 0 and 1"), "{}", error);
+    config.set("anthropic.temperature=1").unwrap();
+
+    config.set("gemini.top_p=1.5").unwrap();
+    let error = config.validate().unwrap_err().to_string();
+    assert!(error.contains("gemini") && error.contains("top_p"), "{}", error);
+}
+
+#[tokio::test]
+async fn test_retries_rate_limits_and_types_errors() {
+    let (address, requests) = stub_server(vec![
+        http_response("429 Too Many Requests", "Retry-After: 0\r\n", r#"{"error":"slow down"}"#),
+        http_response("503 Service Unavailable", "Retry-After: 0\r\n", r#"{"error":"overloaded"}"#),
+        http_response("200 OK", "", COMPLETION),
+        http_response("401 Unauthorized", "", r#"{"error":"invalid api key"}"#),
+        http_response("200 OK", "", r#"{"choices":"none"}"#),
+    ])
+    .await;
+    let provider = local_provider(&address);
+
+    assert_eq!(provider.query("retry").await.unwrap(), "Rotate back post.");
+
+    let error = provider.query("auth").await.unwrap_err();
+    assert!(matches!(error.downcast_ref::<AiError>(), Some(AiError::Auth(_))), "{}", error);
+
+    let error = provider.query("malformed").await.unwrap_err();
+    assert!(matches!(error.downcast_ref::<AiError>(), Some(AiError::Malformed(_))), "{}", error);
+
+    assert_eq!(requests.await.unwrap().len(), 5, "401 is not retried");
+}
+
+#[tokio::test]
+async fn test_chat_round_trips_tool_calls() {
+    let openai_call = r#"{"choices":[{"message":{"role":"assistant","content":null,"tool_calls":[{"id":"call_1","type":"function","function":{"name":"get_positions","arguments":"{\"frame\":120}"}}]},"finish_reason":"tool_calls"}]}"#;
+    let claude_call = r#"{"content":[{"type":"text","text":"Checking."},{"type":"tool_use","id":"toolu_1","name":"get_positions","input":{"frame":120}}],"stop_reason":"tool_use"}"#;
+    let (address, requests) = stub_server(vec![
+        http_response("200 OK", "", openai_call),
+        http_response("200 OK", "", claude_call),
+    ])
+    .await;
+    let tools = vec![Tool {
+        name: "get_positions".to_string(),
+        description: "Positions at a frame.".to_string(),
+        parameters: json!({ "type": "object", "properties": { "frame": { "type": "integer" } } }),
+    }];
+    let call = ToolCall {
+        id: "call_0".to_string(),
+        name: "get_positions".to_string(),
+        arguments: json!({ "frame": 60 }),
+    };
+    let messages = vec![
+        Message::User("Coach me.".to_string()),
+        Message::Assistant { text: String::new(), tool_calls: vec![call.clone(), call] },
+        Message::ToolResult { call_id: "call_0".to_string(), name: "get_positions".to_string(), content: "ball at (0, 0, 93)".to_string() },
+        Message::ToolResult { call_id: "call_0".to_string(), name: "get_positions".to_string(), content: "ball at (0, 0, 93)".to_string() },
+    ];
+
+    let reply = local_provider(&address).chat(&messages, &tools).await.unwrap();
+    assert_eq!(reply.text, "");
+    assert_eq!(reply.tool_calls[0].id, "call_1");
+    assert_eq!(reply.tool_calls[0].arguments, json!({ "frame": 120 }));
+
+    let config = ProviderConfig {
+        base_url: format!("{}/v1", address),
+        ..ProviderConfig::anthropic()
+    };
+    let reply = Anthropic::with_config("key", config).chat(&messages, &tools).await.unwrap();
+    assert_eq!(reply.text, "Checking.");
+    assert_eq!(reply.tool_calls[0].name, "get_positions");
+
+    let requests = requests.await.unwrap();
+    let body = |request: &str| -> serde_json::Value { serde_json::from_str(&request[request.find("\r\n\r\n").unwrap() + 4..]).unwrap() };
+    let openai = body(&requests[0]);
+    assert_eq!(openai["tools"][0]["function"]["name"], "get_positions");
+    assert_eq!(openai["messages"][2]["tool_calls"][0]["function"]["arguments"], "{\"frame\":60}");
+    assert_eq!(openai["messages"][3]["role"], "tool");
+    let claude = body(&requests[1]);
+    assert_eq!(claude["tools"][0]["input_schema"]["type"], "object");
+    assert_eq!(claude["messages"].as_array().unwrap().len(), 3, "tool results share one user turn");
+    assert_eq!(claude["messages"][2]["content"][1]["type"], "tool_result");
+}
+
+#[tokio::test]
+async fn test_streams_answers_and_falls_back() {
+    let openai_events = concat!(
+        "data: {\"choices\":[{\"delta\":{\"role\":\"assistant\",\"content\":\"\"}}]}\n\n",
+        "data: {\"choices\":[{\"delta\":{\"content\":\"Rotate \"}}]}\n\n",
+        "data: {\"choices\":[{\"delta\":{\"content\":\"back post.\"},\"finish_reason\":\"stop\"}]}\n\n",
+        "data: {\"choices\":[],\"usage\":{\"prompt_tokens\":12,\"completion_tokens\":3}}\n\n",
+        "data: [DONE]\n\n",
+    );
+    let claude_events = concat!(
+        "event: message_start\r\ndata: {\"type\":\"message_start\",\"message\":{\"usage\":{\"input_tokens\":20,\"output_tokens\":1}}}\r\n\r\n",
+        "event: ping\r\ndata: {\"type\":\"ping\"}\r\n\r\n",
+        "event: content_block_delta\r\ndata: {\"type\":\"content_block_delta\",\"index\":0,\"delta\":{\"type\":\"text_delta\",\"text\":\"Boost \"}}\r\n\r\n",
+        "event: content_block_delta\r\ndata: {\"type\":\"content_block_delta\",\"index\":0,\"delta\":{\"type\":\"text_delta\",\"text\":\"less.\"}}\r\n\r\n",
+        "event: message_delta\r\ndata: {\"type\":\"message_delta\",\"delta\":{\"stop_reason\":\"end_turn\"},\"usage\":{\"output_tokens\":4}}\r\n\r\n",
+        "event: message_stop\r\ndata: {\"type\":\"message_stop\"}\r\n\r\n",
+    );
+    let (address, requests) = stub_server(vec![
+        http_response("200 OK", "", openai_events),
+        http_response("400 Bad Request", "", r#"{"error":"stream is not supported"}"#),
+        http_response("200 OK", "", COMPLETION),
+        http_response("200 OK", "", claude_events),
+    ])
+    .await;
+    let pieces = std::sync::Mutex::new(Vec::new());
+    let on_text = |text: &str| pieces.lock().unwrap().push(text.to_string());
+
+    let meter = Arc::new(Meter::default());
+    let openai = local_provider(&address).with_meter(meter.clone());
+    assert_eq!(openai.query_streaming("Coach me.", &on_text).await.unwrap(), "Rotate back post.");
+    assert_eq!(openai.query_streaming("Coach me.", &on_text).await.unwrap(), "Rotate back post.");
+
+    let config = ProviderConfig {
+        base_url: format!("{}/v1", address),
+        ..ProviderConfig::anthropic()
+    };
+    let claude = Anthropic::with_config("key", config).with_meter(meter.clone());
+    assert_eq!(claude.query_streaming("Coach me.", &on_text).await.unwrap(), "Boost less.");
+
+    assert_eq!(
+        *pieces.lock().unwrap(),
+        vec!["Rotate ", "back post.", "Rotate back post.", "Boost ", "less."]
+    );
+    let usage: Vec<(u64, u64)> = meter
+        .calls()
+        .iter()
+        .map(|call| (call.usage.input_tokens, call.usage.output_tokens))
+        .collect();
+    assert_eq!(usage, vec![(12, 3), (0, 0), (20, 4)]);
+
+    let requests = requests.await.unwrap();
+    assert!(requests[0].contains("\"stream\":true"));
+    assert!(!requests[2].contains("\"stream\""), "the fallback doesn't stream");
+    assert!(requests[3].contains("\"stream\":true"));
+}
+
+#[tokio::test]
+async fn test_images_are_attached_in_each_format() {
+    let plot = Image {
+        label: "heatmap".to_string(),
+        media_type: "image/png".to_string(),
+        data: "iVBORw0KGgo=".to_string(),
+    };
+    let gemini_answer = r#"{"candidates":[{"content":{"parts":[{"text":"Stay wide."}]}}]}"#;
+    let claude_answer = r#"{"content":[{"type":"text","text":"Stay wide."}],"stop_reason":"end_turn"}"#;
+    let (address, requests) = stub_server(vec![
+        http_response("200 OK", "", COMPLETION),
+        http_response("200 OK", "", claude_answer),
+        http_response("200 OK", "", gemini_answer),
+    ])
+    .await;
+    let images = [plot.clone()];
+
+    let meter = Arc::new(Meter::default());
+    let openai = local_provider(&address).with_meter(meter.clone());
+    assert_eq!(openai.query_with_images("Read the plot.", &images).await.unwrap(), "Rotate back post.");
+    let config = ProviderConfig {
+        base_url: format!("{}/v1", address),
+        ..ProviderConfig::anthropic()
+    };
+    assert_eq!(Anthropic::with_config("key", config).query_with_images("Read the plot.", &images).await.unwrap(), "Stay wide.");
+    let config = ProviderConfig {
+        base_url: format!("{}/v1beta", address),
+        ..ProviderConfig::gemini()
+    };
+    assert_eq!(Gemini::with_config("key", config).query_with_images("Read the plot.", &images).await.unwrap(), "Stay wide.");
+    assert!(Mock::answering("Local", "Stay wide.").query_with_images("Read the plot.", &images).await.is_err(), "no image support");
+
+    let requests = requests.await.unwrap();
+    assert!(requests[0].contains(r#"{"image_url":{"url":"data:image/png;base64,iVBORw0KGgo="},"type":"image_url"}"#));
+    assert!(requests[1].contains(r#"{"source":{"data":"iVBORw0KGgo=","media_type":"image/png","type":"base64"},"type":"image"}"#));
+    assert!(requests[2].contains(r#"{"inline_data":{"data":"iVBORw0KGgo=","mime_type":"image/png"}}"#));
+
+    let body = r#"{"messages":[{"content":"Read the plot.","data":"iVBORw0KGgo="}]}"#;
+    assert_eq!(image::request_tokens(str::len, body, &images), body.len() - plot.data.len() + image::IMAGE_TOKENS);
+}
+
+#[tokio::test]
+async fn test_gemini_key_stays_out_of_urls_and_errors() {
+    let key = "gemini-secret-key";
+    let answer = r#"{"candidates":[{"content":{"parts":[{"text":"Stay wide."}]}}]}"#;
+    let (address, requests) = stub_server(vec![http_response("200 OK", "", answer)]).await;
+    let config = ProviderConfig {
+        base_url: format!("{}/v1beta", address),
+        ..ProviderConfig::gemini()
+    };
+    assert_eq!(Gemini::with_config(key, config).query("Hi").await.unwrap(), "Stay wide.");
+    let request = requests.await.unwrap().remove(0);
+    let request_line = request.lines().next().unwrap();
+    assert!(!request_line.contains(key), "{}", request_line);
+    assert!(request.to_lowercase().contains(&format!("x-goog-api-key: {}", key)));
+
+    // Nothing listens on port 1, so the connection fails
+    let config = ProviderConfig {
+        base_url: "http://127.0.0.1:1/v1beta".to_string(),
+        ..ProviderConfig::gemini()
+    };
+    let error = Gemini::with_config(key, config).query("Hi").await.unwrap_err().to_string();
+    assert!(error.starts_with("Network error"), "{}", error);
+    assert!(!error.contains(key), "{}", error);
+}
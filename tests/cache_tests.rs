@@ -0,0 +1,80 @@
+mod common;
+
+use common::Mock;
+use rattlebrain::ai::cache::{CacheMode, Cached};
+use rattlebrain::ai::config::Config;
+use rattlebrain::ai::provider::Provider;
+use rattlebrain::ai::tools::Message;
+use std::fs;
+use std::time::Duration;
+
+fn cached(dir: &str, mode: CacheMode, ttl: Duration, provider: Mock) -> Cached {
+    Cached::new(Box::new(provider), dir, mode, ttl)
+}
+
+#[tokio::test]
+async fn test_cached_answers_are_reused_per_request() {
+    let dir = "./output/cache_tests";
+    let _ = fs::remove_dir_all(dir);
+    let week = Duration::from_secs(7 * 24 * 60 * 60);
+    // Every answer is different, so a repeated answer must have come from the cache
+    let model_a = |answer: &str| Mock::answering("Counter", answer).with_settings("model-a");
+
+    let mock = model_a("answer 1").then("answer 2").then_fail("failed").then("answer 3").then("answer 8");
+    let prompts = mock.prompts();
+    let provider = cached(dir, CacheMode::Use, week, mock);
+    assert_eq!(provider.query("Coach Blue.").await.unwrap(), "answer 1");
+    assert_eq!(provider.query("Coach Blue.").await.unwrap(), "answer 1");
+    assert_eq!(provider.query("Coach Orange.").await.unwrap(), "answer 2");
+    assert!(provider.query("fail").await.is_err());
+    let chat = [Message::User("Coach Blue.".to_string())];
+    assert_eq!(provider.chat(&chat, &[]).await.unwrap().text, "answer 3");
+    assert_eq!(provider.chat(&chat, &[]).await.unwrap().text, "answer 3");
+    assert_eq!(prompts.lock().unwrap().len(), 4);
+
+    // Other settings, an expired answer or a refresh all ask again
+    let other_model = cached(dir, CacheMode::Use, week, Mock::answering("Counter", "answer 4").with_settings("model-b"));
+    assert_eq!(other_model.query("Coach Blue.").await.unwrap(), "answer 4");
+    let expired = cached(dir, CacheMode::Use, Duration::ZERO, model_a("answer 5"));
+    assert_eq!(expired.query("Coach Blue.").await.unwrap(), "answer 5");
+    let refresh = cached(dir, CacheMode::Refresh, week, model_a("answer 6"));
+    assert_eq!(refresh.query("Coach Blue.").await.unwrap(), "answer 6");
+    assert_eq!(provider.query("Coach Blue.").await.unwrap(), "answer 6");
+
+    let off = cached(dir, CacheMode::Off, week, model_a("answer 7"));
+    assert_eq!(off.query("Coach Red.").await.unwrap(), "answer 7");
+    assert_eq!(provider.query("Coach Red.").await.unwrap(), "answer 8");
+
+    assert_eq!(fs::read_dir(dir).unwrap().count(), 5);
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_cached_answer_is_only_reused_for_its_own_request() {
+    let dir = "./output/cache_collision_tests";
+    let _ = fs::remove_dir_all(dir);
+    let week = Duration::from_secs(7 * 24 * 60 * 60);
+    let provider = cached(dir, CacheMode::Use, week, Mock::answering("Counter", "answer 1").then("answer 2"));
+    assert_eq!(provider.query("Coach Blue.").await.unwrap(), "answer 1");
+
+    // As if another prompt's answer had been saved under the same file name
+    let path = fs::read_dir(dir).unwrap().next().unwrap().unwrap().path();
+    let mut entry: serde_json::Value = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+    assert_eq!(entry["request"], "query\nCoach Blue.");
+    entry["request"] = "query\nCoach Orange.".into();
+    fs::write(&path, entry.to_string()).unwrap();
+    assert_eq!(provider.query("Coach Blue.").await.unwrap(), "answer 2");
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_cache_settings() {
+    let config = Config::from_json(r#"{"cache": "refresh", "cache_ttl": 3600}"#).unwrap();
+    assert_eq!(config.cache, CacheMode::Refresh);
+    assert_eq!(config.cache_ttl, 3600);
+
+    let config = Config::load(None, &["cache=off", "cache_ttl=60"]).unwrap();
+    assert_eq!((config.cache, config.cache_ttl), (CacheMode::Off, 60));
+    assert!(Config::load(None, &["cache=sometimes"]).is_err());
+}
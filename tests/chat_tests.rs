@@ -0,0 +1,35 @@
+mod common;
+
+use common::Mock;
+use rattlebrain::agent::MatchTools;
+use rattlebrain::chat;
+use rattlebrain::digest::MatchData;
+use std::fs;
+use std::io::Cursor;
+
+#[tokio::test]
+async fn test_converse_keeps_history_and_log() {
+    let log_path = "./output/converse_keeps_history_and_log.chat.md";
+    fs::create_dir_all("./output").unwrap();
+    let _ = fs::remove_file(log_path);
+
+    let coach = Mock::answering("Coach", "Answer 1.").then("Answer 2.");
+    let prompts = coach.prompts();
+    let tools = MatchTools::new(MatchData { frames: Vec::new(), goals: Vec::new(), events: Vec::new() });
+    let input = Cursor::new("Why did we concede the second goal?\n\n  What should I drill?  \nexit\nNot asked.\n");
+    let messages = chat::converse(&coach, &tools, "Match digest here.", input, log_path, 3).await.unwrap();
+
+    assert_eq!(messages.len(), 4);
+    let prompts = prompts.lock().unwrap();
+    assert_eq!(prompts.len(), 2);
+    assert!(prompts[0].contains("User:\nMatch digest here.\n\nQuestion: Why did we concede the second goal?\n"));
+    assert!(prompts[1].contains("You:\nAnswer 1.\n"));
+    assert!(prompts[1].ends_with("User:\nWhat should I drill?\n"));
+
+    let log = fs::read_to_string(log_path).unwrap();
+    assert_eq!(
+        log,
+        "\n## Coach\n\n**You**\n\nWhy did we concede the second goal?\n\n**Coach**\n\nAnswer 1.\n\n**You**\n\nWhat should I drill?\n\n**Coach**\n\nAnswer 2.\n"
+    );
+    fs::remove_file(log_path).unwrap();
+}
@@ -0,0 +1,41 @@
+use rattlebrain::coaching::{self, FrameRange};
+
+const REPORT: &str = r#"{
+    "summary": "Good pressure, loose rotations.",
+    "players": [{
+        "player_name": "Blue",
+        "strengths": [{ "point": "Fast kickoffs", "frames": [{ "start_frame": 0, "end_frame": 90 }] }],
+        "weaknesses": [{ "point": "Double commits", "frames": [] }],
+        "drills": [{ "name": "Shadow defense", "description": "Ten minutes in free play." }]
+    }]
+}"#;
+
+#[test]
+fn test_parse_report_validates_schema() {
+    let fenced = format!("```json\n{}\n```", REPORT);
+    let report = coaching::parse_report(&fenced).unwrap();
+    assert_eq!(report.players[0].player_name, "Blue");
+    assert_eq!(report.players[0].strengths[0].frames, vec![FrameRange { start_frame: 0, end_frame: 90 }]);
+
+    let missing_drills = REPORT.replace(r#""drills": [{ "name": "Shadow defense", "description": "Ten minutes in free play." }]"#, r#""extra": 1"#);
+    let error = coaching::parse_report(&missing_drills).unwrap_err().to_string();
+    assert!(error.contains("coaching schema"), "{}", error);
+
+    let negative_frame = REPORT.replace(r#""start_frame": 0"#, r#""start_frame": -5"#);
+    let error = coaching::parse_report(&negative_frame).unwrap_err().to_string();
+    assert!(error.contains("coaching schema") && error.contains("minimum"), "{}", error);
+    assert!(!coaching::report_schema().to_string().contains("minimum"), "OpenAI's strict mode rejects it");
+    assert!(coaching::parse_report("Rotate back post.").is_err());
+}
+
+#[test]
+fn test_render_markdown_cites_frames() {
+    let report = coaching::parse_report(REPORT).unwrap();
+    let markdown = coaching::render_markdown(&report);
+
+    assert!(markdown.starts_with("Good pressure, loose rotations."));
+    assert!(markdown.contains("### Blue"));
+    assert!(markdown.contains("- Fast kickoffs [frames 0-90]"));
+    assert!(markdown.contains("- Double commits\n"));
+    assert!(markdown.contains("- **Shadow defense**: Ten minutes in free play."));
+}
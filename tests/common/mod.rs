@@ -0,0 +1,428 @@
+//! Test doubles: a scripted provider, HTTP servers that stand in for the providers' APIs so
+//! the real clients can be tested offline, and match data to feed them.
+#![allow(dead_code)]
+
+use async_trait::async_trait;
+use rattlebrain::ai::provider::{self, Capabilities, Provider, ProviderError};
+use rattlebrain::ai::tools::{Message, Reply, Tool};
+use rattlebrain::plot::GameState;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::VecDeque;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+
+/// Set to record fixtures from the real APIs instead of replaying them.
+pub const RECORD_VAR: &str = "RATTLEBRAIN_RECORD_FIXTURES";
+
+/// A provider that gives scripted answers, in order, repeating the last one. It keeps every
+/// prompt and conversation it was sent, and the tools it was offered, so tests can check
+/// what reached it. Without the `tools` capability, conversations reach it as one prompt,
+/// as they would a provider without a chat API.
+pub struct Mock {
+    name: &'static str,
+    capabilities: Capabilities,
+    settings: String,
+    delay: Duration,
+    answers: Mutex<VecDeque<Result<Reply, String>>>,
+    prompts: Arc<Mutex<Vec<String>>>,
+    conversations: Arc<Mutex<Vec<Vec<Message>>>>,
+    tools_offered: Arc<Mutex<Vec<usize>>>,
+}
+
+impl Mock {
+    pub fn answering(name: &'static str, answer: &str) -> Self {
+        Mock::new(name, Ok(text_reply(answer)))
+    }
+
+    pub fn failing(name: &'static str, error: &str) -> Self {
+        Mock::new(name, Err(error.to_string()))
+    }
+
+    /// Replies to a conversation with `reply`, which may call tools.
+    pub fn replying(name: &'static str, reply: Reply) -> Self {
+        Mock::new(name, Ok(reply))
+    }
+
+    fn new(name: &'static str, answer: Result<Reply, String>) -> Self {
+        Mock {
+            name,
+            capabilities: Capabilities::default(),
+            settings: String::new(),
+            delay: Duration::ZERO,
+            answers: Mutex::new(VecDeque::from([answer])),
+            prompts: Arc::default(),
+            conversations: Arc::default(),
+            tools_offered: Arc::default(),
+        }
+    }
+
+    /// Answers `answer` once the answers before it have been given.
+    pub fn then(self, answer: &str) -> Self {
+        self.then_reply(text_reply(answer))
+    }
+
+    /// Fails with `error` once the answers before it have been given.
+    pub fn then_fail(self, error: &str) -> Self {
+        self.answers.lock().unwrap().push_back(Err(error.to_string()));
+        self
+    }
+
+    pub fn then_reply(self, reply: Reply) -> Self {
+        self.answers.lock().unwrap().push_back(Ok(reply));
+        self
+    }
+
+    /// Waits `delay` before every answer.
+    pub fn after(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    pub fn with_capabilities(mut self, capabilities: Capabilities) -> Self {
+        self.capabilities = capabilities;
+        self
+    }
+
+    /// What it reports as its settings, which the cache keys answers by.
+    pub fn with_settings(mut self, settings: &str) -> Self {
+        self.settings = settings.to_string();
+        self
+    }
+
+    /// The prompts sent so far; still readable after the mock is registered.
+    pub fn prompts(&self) -> Arc<Mutex<Vec<String>>> {
+        self.prompts.clone()
+    }
+
+    /// The conversations sent so far, to a mock with the `tools` capability.
+    pub fn conversations(&self) -> Arc<Mutex<Vec<Vec<Message>>>> {
+        self.conversations.clone()
+    }
+
+    /// How many tools each conversation offered.
+    pub fn tools_offered(&self) -> Arc<Mutex<Vec<usize>>> {
+        self.tools_offered.clone()
+    }
+
+    async fn next(&self) -> Result<Reply, ProviderError> {
+        tokio::time::sleep(self.delay).await;
+        let mut answers = self.answers.lock().unwrap();
+        let answer = if answers.len() > 1 {
+            answers.pop_front().unwrap()
+        } else {
+            answers[0].clone()
+        };
+        answer.map_err(Into::into)
+    }
+}
+
+fn text_reply(text: &str) -> Reply {
+    Reply {
+        text: text.to_string(),
+        tool_calls: Vec::new(),
+    }
+}
+
+#[async_trait]
+impl Provider for Mock {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        self.capabilities
+    }
+
+    fn settings(&self) -> String {
+        self.settings.clone()
+    }
+
+    async fn query(&self, prompt: &str) -> Result<String, ProviderError> {
+        self.prompts.lock().unwrap().push(prompt.to_string());
+        Ok(self.next().await?.text)
+    }
+
+    async fn chat(&self, messages: &[Message], tools: &[Tool]) -> Result<Reply, ProviderError> {
+        self.tools_offered.lock().unwrap().push(tools.len());
+        if !self.capabilities.tools {
+            if !tools.is_empty() {
+                return Err(format!("{} does not support tool calling", self.name).into());
+            }
+            return Ok(text_reply(&self.query(&provider::conversation_prompt(messages)).await?));
+        }
+        self.conversations.lock().unwrap().push(messages.to_vec());
+        self.next().await
+    }
+}
+
+/// A car, or the ball with no `team`, at `location` and moving at `velocity`, 30 frames a second.
+pub fn state(frame: usize, player_name: &str, team: Option<u32>, location: (f64, f64, f64), velocity: (f64, f64, f64)) -> GameState {
+    GameState {
+        frame,
+        time: frame as f64 / 30.0,
+        team,
+        player_name: player_name.to_string(),
+        boost: team.map(|_| 170),
+        location_x: location.0,
+        location_y: location.1,
+        location_z: location.2,
+        rotation_x: 0.0,
+        rotation_y: 0.0,
+        rotation_z: 0.0,
+        rotation_w: 1.0,
+        linear_velocity_x: velocity.0,
+        linear_velocity_y: velocity.1,
+        linear_velocity_z: velocity.2,
+    }
+}
+
+/// A kickoff, then Blue dribbles up the field and scores at frame 290 while Orange trails behind.
+pub fn scoring_match() -> Vec<GameState> {
+    let mut data = Vec::new();
+    for frame in 0..300 {
+        let y = if frame < 30 { 0.0 } else { (frame - 30) as f64 * 1800.0 };
+        let vy = if frame < 30 { 0.0 } else { 54000.0 };
+        data.push(state(frame, "_ball_", None, (0.0, y, 1700.0), (0.0, vy, 0.0)));
+        data.push(state(frame, "Blue", Some(0), (0.0, y - 20000.0, 1700.0), (0.0, vy, 0.0)));
+        data.push(state(frame, "Orange", Some(1), (30000.0, y - 60000.0, 1700.0), (0.0, vy, 0.0)));
+    }
+    data
+}
+
+/// A raw HTTP response with a JSON body.
+pub fn http_response(status: &str, headers: &str, body: &str) -> String {
+    format!(
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\n{}Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        headers,
+        body.len(),
+        body
+    )
+}
+
+/// Answers one HTTP request per response, in order, and hands back the raw requests it received.
+pub async fn stub_server(responses: Vec<String>) -> (String, JoinHandle<Vec<String>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = format!("http://{}", listener.local_addr().unwrap());
+
+    let handle = tokio::spawn(async move {
+        let mut requests = Vec::new();
+        for response in responses {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let request = read_request(&mut socket).await;
+            socket.write_all(response.as_bytes()).await.unwrap();
+            requests.push(request);
+        }
+        requests
+    });
+
+    (address, handle)
+}
+
+/// Reads one whole request, body included.
+async fn read_request(socket: &mut TcpStream) -> String {
+    let mut request = Vec::new();
+    let mut buffer = [0u8; 4096];
+    loop {
+        let read = socket.read(&mut buffer).await.unwrap();
+        request.extend_from_slice(&buffer[..read]);
+        let text = String::from_utf8_lossy(&request);
+        if let Some(header_end) = text.find("\r\n\r\n") {
+            let content_length = text[..header_end]
+                .lines()
+                .find_map(|line| line.to_lowercase().strip_prefix("content-length:").map(|v| v.trim().parse::<usize>().unwrap()))
+                .unwrap_or(0);
+            if request.len() >= header_end + 4 + content_length || read == 0 {
+                break;
+            }
+        }
+        if read == 0 {
+            break;
+        }
+    }
+    String::from_utf8_lossy(&request).to_string()
+}
+
+/// One request to a provider's API and the answer it got, as saved in a fixture file.
+/// Only the method and path of the request are kept: headers carry API keys, and the
+/// prompt is whatever the code under test sends today.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Exchange {
+    pub method: String,
+    pub path: String,
+    pub status: u16,
+    /// The response body, as JSON when it is JSON and as a string otherwise (e.g. SSE).
+    pub body: Value,
+}
+
+/// A request the fixture server received.
+#[derive(Debug, Clone)]
+pub struct Received {
+    pub method: String,
+    pub path: String,
+    pub body: String,
+}
+
+/// A local server standing in for one provider's API. It replays the exchanges in
+/// `tests/fixtures/<name>.json`, answering each request with the first unused exchange for
+/// the same method and path. The checked-in fixtures are synthetic: hand-written in the shape
+/// of each API's responses, not captured from it, so their ids and token counts are made up.
+/// With `RATTLEBRAIN_RECORD_FIXTURES` set, it forwards every request to the real API at
+/// `upstream` instead, and saves what came back as the fixture.
+pub struct Fixture {
+    url: String,
+    stop: oneshot::Sender<()>,
+    handle: JoinHandle<(Vec<Received>, Vec<Exchange>)>,
+    file: PathBuf,
+    recording: bool,
+}
+
+impl Fixture {
+    pub async fn serve(name: &str, upstream: &str) -> Fixture {
+        let file = PathBuf::from(format!("tests/fixtures/{}.json", name));
+        let recording = recording();
+        let mut exchanges: Vec<Option<Exchange>> = if recording {
+            Vec::new()
+        } else {
+            let content = fs::read_to_string(&file).unwrap_or_else(|e| {
+                panic!("Can't read fixture {} ({}); record it with {}=1", file.display(), e, RECORD_VAR)
+            });
+            serde_json::from_str::<Vec<Exchange>>(&content).unwrap().into_iter().map(Some).collect()
+        };
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let upstream = upstream.trim_end_matches('/').to_string();
+        let (stop, mut stopped) = oneshot::channel();
+
+        let handle = tokio::spawn(async move {
+            let client = reqwest::Client::new();
+            let mut received = Vec::new();
+            let mut recorded = Vec::new();
+            loop {
+                let mut socket = tokio::select! {
+                    _ = &mut stopped => break,
+                    accepted = listener.accept() => accepted.unwrap().0,
+                };
+                let raw = read_request(&mut socket).await;
+                let (head, body) = raw.split_once("\r\n\r\n").unwrap_or((&raw, ""));
+                let mut start = head.lines().next().unwrap_or_default().split(' ');
+                let method = start.next().unwrap_or_default().to_string();
+                let path = start.next().unwrap_or_default().to_string();
+                let exchange = if recording {
+                    let exchange = forward(&client, &upstream, &method, &path, head, body).await;
+                    recorded.push(exchange.clone());
+                    exchange
+                } else {
+                    let unused = exchanges.iter_mut().find(|exchange| {
+                        exchange.as_ref().is_some_and(|e| e.method == method && e.path == scrub(&path))
+                    });
+                    unused.and_then(Option::take).unwrap_or_else(|| Exchange {
+                        method: method.clone(),
+                        path: path.clone(),
+                        status: 599,
+                        body: Value::String(format!("No recorded response left for {} {}", method, scrub(&path))),
+                    })
+                };
+                let text = match &exchange.body {
+                    Value::String(text) => text.clone(),
+                    json => json.to_string(),
+                };
+                let status = reqwest::StatusCode::from_u16(exchange.status).unwrap();
+                let status = format!("{} {}", status.as_u16(), status.canonical_reason().unwrap_or("Unknown"));
+                socket.write_all(http_response(&status, "", &text).as_bytes()).await.unwrap();
+                received.push(Received {
+                    method,
+                    path,
+                    body: body.to_string(),
+                });
+            }
+            let unused = exchanges.into_iter().flatten().collect();
+            (received, if recording { recorded } else { unused })
+        });
+
+        Fixture {
+            url,
+            stop,
+            handle,
+            file,
+            recording,
+        }
+    }
+
+    /// Where to point the provider instead of its API, e.g. `format!("{}/v1", fixture.url())`.
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Stops the server and returns the requests it received. When recording, saves the
+    /// fixture; when replaying, fails if a recorded exchange was never asked for.
+    pub async fn finish(self) -> Vec<Received> {
+        let _ = self.stop.send(());
+        let (received, exchanges) = self.handle.await.unwrap();
+        if self.recording {
+            fs::create_dir_all(self.file.parent().unwrap()).unwrap();
+            fs::write(&self.file, serde_json::to_string_pretty(&exchanges).unwrap() + "\n").unwrap();
+            println!("Recorded {} exchanges in {}", exchanges.len(), self.file.display());
+        } else {
+            let unused: Vec<String> = exchanges.iter().map(|e| format!("{} {}", e.method, e.path)).collect();
+            assert!(unused.is_empty(), "{} was never requested: {:?}", self.file.display(), unused);
+        }
+        received
+    }
+}
+
+/// Whether fixtures are being recorded rather than replayed.
+pub fn recording() -> bool {
+    env::var(RECORD_VAR).is_ok_and(|value| !value.is_empty() && value != "0")
+}
+
+/// The API key in `var` when recording, and a placeholder when replaying.
+pub fn api_key(var: &str) -> String {
+    if recording() {
+        env::var(var).unwrap_or_else(|_| panic!("{} is needed to record fixtures", var))
+    } else {
+        "replayed-key".to_string()
+    }
+}
+
+/// Sends a request the server received on to the real API.
+async fn forward(client: &reqwest::Client, upstream: &str, method: &str, path: &str, head: &str, body: &str) -> Exchange {
+    let mut request = client.request(method.parse().unwrap(), format!("{}{}", upstream, path));
+    for (name, value) in head.lines().skip(1).filter_map(|line| line.split_once(':')) {
+        if !["host", "content-length", "connection"].contains(&name.trim().to_lowercase().as_str()) {
+            request = request.header(name.trim(), value.trim());
+        }
+    }
+    let (status, text) = match request.body(body.to_string()).send().await {
+        Ok(response) => (response.status().as_u16(), response.text().await.unwrap_or_default()),
+        Err(e) => (599, e.to_string()),
+    };
+    Exchange {
+        method: method.to_string(),
+        path: scrub(path),
+        status,
+        body: serde_json::from_str(&text).unwrap_or(Value::String(text)),
+    }
+}
+
+/// `path` without a `key` query parameter, so an API key passed in a URL is never saved.
+fn scrub(path: &str) -> String {
+    let Some((route, query)) = path.split_once('?') else {
+        return path.to_string();
+    };
+    let kept: Vec<&str> = query.split('&').filter(|param| !param.starts_with("key=")).collect();
+    if kept.is_empty() {
+        route.to_string()
+    } else {
+        format!("{}?{}", route, kept.join("&"))
+    }
+}
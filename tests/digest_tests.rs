@@ -0,0 +1,65 @@
+mod common;
+
+use common::scoring_match;
+use rattlebrain::ai::tokens;
+use rattlebrain::digest;
+use rattlebrain::events::{self, Goal};
+use rattlebrain::prompt;
+
+#[test]
+fn test_digest_summarizes_match() {
+    let data = scoring_match();
+    let estimate = |text: &str| tokens::estimate(text, tokens::DEFAULT_CHARS_PER_TOKEN);
+    let goals = vec![Goal { player_name: "Blue".to_string(), team: 0, frame: 290 }];
+    let match_events = events::find_events(&data, &goals, &[]);
+
+    let text = prompt::assemble(&digest::sections(&data, &goals, &match_events, None), 10_000, &estimate).text;
+    assert!(text.contains("Blue team: Blue"));
+    assert!(text.contains("Score: Blue 1 - 0 Orange"));
+    assert!(text.contains("Blue (Blue): 1st 100%"));
+    assert!(text.contains("[0:00 f0] kickoff, first touch Blue"));
+    assert!(text.contains("GOAL Blue (Blue)"));
+    assert!(text.contains("before Blue's goal: ball (0, 3582, 17); Blue ("));
+    assert!(!text.contains("omitted"));
+}
+
+#[test]
+fn test_digest_fits_token_budget() {
+    let data = scoring_match();
+    let estimate = |text: &str| tokens::estimate(text, tokens::DEFAULT_CHARS_PER_TOKEN);
+    let goals = vec![Goal { player_name: "Blue".to_string(), team: 0, frame: 290 }];
+
+    let assembled = prompt::assemble(&digest::sections(&data, &goals, &[], None), 40, &estimate);
+    let text = assembled.text;
+    assert!(assembled.tokens <= 60);
+    assert!(text.starts_with("## Match overview"));
+    assert!(!text.contains("## Key moments"));
+}
+
+#[test]
+fn test_digest_for_one_player() {
+    let data = scoring_match();
+    let goals = vec![Goal { player_name: "Blue".to_string(), team: 0, frame: 290 }];
+    let match_events = events::find_events(&data, &goals, &[]);
+
+    assert_eq!(digest::find_player(&data, "orange").unwrap(), "Orange");
+    assert_eq!(digest::find_player(&data, "1").unwrap(), "Blue");
+    let missing = digest::find_player(&data, "Nobody").unwrap_err().to_string();
+    assert!(missing.contains("Players: 1 Blue (Blue), 2 Orange (Orange)"));
+
+    let sections = digest::sections(&data, &goals, &match_events, Some("Orange"));
+    let comparison = sections
+        .iter()
+        .find(|section| section.title == "Orange compared with teammates and opponents")
+        .unwrap();
+    assert_eq!(comparison.lines[0], "stat | Orange | teammates avg | opponents avg");
+    assert!(comparison.lines.contains(&"goals | 0.0 | - | 1.0".to_string()));
+
+    let timeline = |player| {
+        let sections = digest::sections(&data, &goals, &match_events, player);
+        sections.into_iter().find(|section| section.title == "Timeline").unwrap().lines
+    };
+    assert!(timeline(None).iter().any(|line| line.contains("shot by Blue")));
+    assert!(timeline(Some("Orange")).iter().any(|line| line.contains("GOAL Blue")));
+    assert!(!timeline(Some("Orange")).iter().any(|line| line.contains("shot by Blue")));
+}
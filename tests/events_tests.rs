@@ -0,0 +1,51 @@
+mod common;
+
+use common::state;
+use rattlebrain::events::{self, EventKind, Goal, ShotOutcome};
+use rattlebrain::plot::GameState;
+
+/// A blue car hits a rolling ball straight at the orange goal.
+fn blue_shot() -> Vec<GameState> {
+    vec![
+        state(10, "_ball_", None, (0.0, 300000.0, 9300.0), (0.0, -50000.0, 0.0)),
+        state(11, "Blue", Some(0), (0.0, 280000.0, 1700.0), (0.0, 100000.0, 0.0)),
+        state(11, "_ball_", None, (0.0, 295000.0, 9300.0), (0.0, 300000.0, 0.0)),
+        state(12, "_ball_", None, (0.0, 305000.0, 9300.0), (0.0, 300000.0, 0.0)),
+    ]
+}
+
+#[test]
+fn test_find_touches_credits_nearest_car() {
+    let data = blue_shot();
+    let touches = events::find_touches(&data);
+
+    assert_eq!(touches.len(), 1);
+    assert_eq!(touches[0].kind, EventKind::Touch);
+    assert_eq!(touches[0].player_name, "Blue");
+    assert_eq!(touches[0].frame, 11);
+}
+
+#[test]
+fn test_find_shots_outcome() {
+    let data = blue_shot();
+    let touches = events::find_touches(&data);
+
+    let missed = events::find_shots(&data, &touches, &[]);
+    assert_eq!(missed.len(), 1);
+    assert_eq!(missed[0].outcome, Some(ShotOutcome::Missed));
+
+    let goals = vec![Goal { player_name: "Blue".to_string(), team: 0, frame: 40 }];
+    let scored = events::find_shots(&data, &touches, &goals);
+    assert_eq!(scored[0].outcome, Some(ShotOutcome::Goal));
+    assert!(scored[0].xg.unwrap() > 0.3);
+}
+
+#[test]
+fn test_expected_goals_falls_off_with_distance() {
+    let close = events::expected_goals(0.0, 4000.0, 0);
+    let far = events::expected_goals(0.0, 0.0, 0);
+    let wide = events::expected_goals(3000.0, 4000.0, 0);
+
+    assert!(close > far);
+    assert!(close > wide);
+}
@@ -0,0 +1,60 @@
+mod common;
+
+use common::state;
+use rattlebrain::evidence;
+use rattlebrain::plot::GameState;
+use std::fs;
+use std::path::Path;
+
+/// One minute of the ball and two cars drifting up the field.
+fn match_frames() -> Vec<GameState> {
+    let mut data = Vec::new();
+    for frame in 0..1800 {
+        let y = frame as f64 * 100.0;
+        data.push(state(frame, "_ball_", None, (0.0, y, 1700.0), (0.0, 0.0, 0.0)));
+        data.push(state(frame, "Blue", Some(0), (0.0, y - 20000.0, 1700.0), (0.0, 0.0, 0.0)));
+        data.push(state(frame, "Orange", Some(1), (0.0, y + 20000.0, 1700.0), (0.0, 0.0, 0.0)));
+    }
+    data
+}
+
+#[test]
+fn test_attach_evidence_for_cited_frames() {
+    let output_name = "attach_evidence_for_cited_frames";
+    fs::create_dir_all("./output").unwrap();
+    let feedback = "\
+Blue rotated well.
+
+- Late challenge [frames 300-315], then again [frame 900].
+- Whole match [frames 0-1500]
+- Overtime [frames 5000-5100]
+";
+
+    let attached = evidence::attach_evidence(feedback, &match_frames(), output_name);
+    let lines: Vec<&str> = attached.lines().collect();
+    assert_eq!(lines[0], "Blue rotated well.");
+    assert_eq!(lines[2], "- Late challenge [frames 300-315], then again [frame 900].");
+    assert_eq!(
+        lines[3],
+        format!("  ![frames 300-315 (0:10-0:10)]({}.evidence_300-315.gif)", output_name)
+    );
+    assert_eq!(lines[4], format!("  ![frame 900 (0:30)]({}.evidence_900.png)", output_name));
+    assert_eq!(lines[5], "- Whole match [frames 0-1500] _(citation not in this replay: 50s is longer than 30s)_");
+    assert_eq!(
+        lines[6],
+        "- Overtime [frames 5000-5100] _(citation not in this replay: the replay has frames 0 to 1799)_"
+    );
+    assert!(attached.ends_with('\n'));
+
+    for file_name in ["evidence_300-315.gif", "evidence_900.png"] {
+        let path = format!("./output/{}.{}", output_name, file_name);
+        assert!(Path::new(&path).exists(), "{} was not rendered", path);
+        fs::remove_file(path).unwrap();
+    }
+}
+
+#[test]
+fn test_attach_evidence_ignores_other_brackets() {
+    let feedback = "See [the docs](https://example.com) and [frames later].";
+    assert_eq!(evidence::attach_evidence(feedback, &match_frames(), "ignored"), feedback);
+}
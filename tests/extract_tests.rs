@@ -41,7 +41,7 @@ async fn ensure_rattletrap() -> &'static str {
 
             // Extract the tar.gz
             let tar_status = Command::new("tar")
-                .args(&["-xzf", tar_file])
+                .args(["-xzf", tar_file])
                 .status()
                 .expect("Failed to execute tar");
 
@@ -52,7 +52,7 @@ async fn ensure_rattletrap() -> &'static str {
 
             // Make the binary executable
             let chmod_status = Command::new("chmod")
-                .args(&["+x", rattletrap_name])
+                .args(["+x", rattletrap_name])
                 .status()
                 .expect("Failed to execute chmod");
 
@@ -5,7 +5,7 @@ mod main_tests {
     #[test]
     fn test_no_arguments() {
         let output = Command::new("cargo")
-            .args(&["run", "--"])
+            .args(["run", "--"])
             .output()
             .expect("Failed to execute process");
 
@@ -16,7 +16,7 @@ mod main_tests {
     #[test]
     fn test_extract_command() {
         let output = Command::new("cargo")
-            .args(&["run", "--", "extract", "input.replay", "output.csv"])
+            .args(["run", "--", "extract", "input.replay", "output.csv"])
             .output()
             .expect("Failed to execute process");
 
@@ -27,7 +27,7 @@ mod main_tests {
     #[test]
     fn test_unknown_command() {
         let output = Command::new("cargo")
-            .args(&["run", "--", "unknown"])
+            .args(["run", "--", "unknown"])
             .output()
             .expect("Failed to execute process");
 
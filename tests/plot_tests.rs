@@ -0,0 +1,163 @@
+mod common;
+
+use common::state;
+use rattlebrain::events::{Event, EventKind, ShotOutcome};
+use rattlebrain::plot::{self, GameState};
+use plotters::style::RGBColor;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+fn event(kind: EventKind, player_name: &str, team: u32, outcome: Option<ShotOutcome>) -> Event {
+    Event {
+        frame: 1,
+        time: 0.1,
+        kind,
+        player_name: player_name.to_string(),
+        team: Some(team),
+        location_x: 1000.0,
+        location_y: 2000.0,
+        location_z: 9300.0,
+        outcome,
+        xg: outcome.map(|_| 0.4),
+        victim_name: None,
+    }
+}
+
+#[test]
+fn test_plot_event_maps_player_filter() {
+    let output_dir = "./output/plot_event_maps_test";
+    fs::create_dir_all(output_dir).unwrap();
+    let file_path = format!("{}/match.replay.frames.json.csv", output_dir);
+
+    let data = vec![
+        state(1, "Blue", Some(0), (-400000.0, -500000.0, 1700.0), (0.0, 0.0, 0.0)),
+        state(1, "Orange", Some(1), (400000.0, 500000.0, 1700.0), (0.0, 0.0, 0.0)),
+    ];
+    let events = vec![
+        event(EventKind::Touch, "Blue", 0, None),
+        event(EventKind::Shot, "Blue", 0, Some(ShotOutcome::Saved)),
+        event(EventKind::Touch, "Orange", 1, None),
+    ];
+    let mut team_colors = HashMap::new();
+    team_colors.insert(Some(0), RGBColor(0, 0, 255));
+    team_colors.insert(Some(1), RGBColor(255, 165, 0));
+
+    plot::plot_event_maps(&data, &events, &file_path, Some("Orange"), &team_colors).unwrap();
+
+    assert!(Path::new(&format!("{}_touches.png", file_path)).exists());
+    assert!(!Path::new(&format!("{}_shots.png", file_path)).exists(), "Orange took no shots");
+    assert!(!Path::new(&format!("{}_demos.png", file_path)).exists());
+
+    fs::remove_dir_all(output_dir).unwrap();
+}
+
+#[test]
+fn test_plot_csv_ball_trajectory_window() {
+    let output_dir = "./output/plot_ball_test";
+    fs::create_dir_all(output_dir).unwrap();
+    let file_path = format!("{}/match.replay.frames.json.csv", output_dir);
+
+    let mut csv = String::from("frame,time,team,player_name,boost,location_x,location_y,location_z,rotation_x,rotation_y,rotation_z,rotation_w,angular_velocity_x,angular_velocity_y,angular_velocity_z,linear_velocity_x,linear_velocity_y,linear_velocity_z\n");
+    for frame in 0..60 {
+        let time = frame as f64 / 30.0;
+        let y = frame as f64 * 5000.0;
+        csv.push_str(&format!("{},{},0,\"Blue\",33,0,{},1700,0,0,0,1,0,0,0,0,150000,0\n", frame, time, y - 20000.0));
+        csv.push_str(&format!("{},{},1,\"Orange\",33,0,{},1700,0,0,0,1,0,0,0,0,-150000,0\n", frame, time, 400000.0 - y));
+        csv.push_str(&format!("{},{},,\"_ball_\",,0,{},{},0,0,0,1,0,0,0,0,150000,{}\n", frame, time, y, 9300.0 + y / 10.0, frame * 100));
+    }
+    fs::write(&file_path, csv).unwrap();
+
+    let options = plot::PlotOptions {
+        start_time: Some(0.5),
+        end_time: Some(1.5),
+        ..Default::default()
+    };
+    let data = plot::plot_csv_with_options(&file_path, &options).unwrap();
+
+    assert_eq!(data.len(), 180);
+    assert!(Path::new(&format!("{}_ball.png", file_path)).exists());
+    assert!(Path::new(&format!("{}_ball_side.png", file_path)).exists());
+
+    fs::remove_dir_all(output_dir).unwrap();
+}
+
+#[test]
+fn test_ball_path_window_is_match_time() {
+    // Replay time doesn't start at zero, the match clock does
+    let data: Vec<GameState> = (0..60)
+        .map(|frame| {
+            let mut ball = state(frame, "_ball_", None, (0.0, 0.0, 9300.0), (0.0, 0.0, 0.0));
+            ball.time += 100.0;
+            ball
+        })
+        .collect();
+    let options = plot::PlotOptions {
+        start_time: Some(0.5),
+        end_time: Some(1.5),
+        ..Default::default()
+    };
+
+    let frames: Vec<usize> = plot::ball_path(&data, &options).iter().map(|s| s.frame).collect();
+    assert_eq!(frames.first(), Some(&15));
+    assert_eq!(frames.last(), Some(&45));
+}
+
+#[test]
+fn test_facing_from_quaternion() {
+    let mut car = state(1, "Blue", Some(0), (0.0, 0.0, 1700.0), (0.0, 0.0, 0.0));
+    let (x, y) = car.facing().unwrap();
+    assert!((x - 1.0).abs() < 1e-9 && y.abs() < 1e-9);
+
+    // A quarter turn about the vertical axis points the car along +Y
+    let half_angle = std::f64::consts::FRAC_PI_4;
+    car.rotation_z = half_angle.sin();
+    car.rotation_w = half_angle.cos();
+    let (x, y) = car.facing().unwrap();
+    assert!(x.abs() < 1e-9 && (y - 1.0).abs() < 1e-9);
+
+    // Spawn rows carry no quaternion
+    car.rotation_w = 0.0;
+    car.rotation_z = 0.0;
+    assert!(car.facing().is_none());
+}
+
+#[test]
+fn test_plot_snapshot_uses_latest_positions() {
+    let output_dir = "./output/plot_snapshot_test";
+    fs::create_dir_all(output_dir).unwrap();
+    let snapshot_file = format!("{}/snapshot.png", output_dir);
+
+    let mut data = vec![
+        state(1, "Blue", Some(0), (0.0, -200000.0, 1700.0), (0.0, 0.0, 0.0)),
+        state(5, "Blue", Some(0), (0.0, -100000.0, 1700.0), (0.0, 0.0, 0.0)),
+        state(9, "Blue", Some(0), (0.0, 0.0, 1700.0), (0.0, 0.0, 0.0)),
+        state(3, "Orange", Some(1), (0.0, 200000.0, 1700.0), (0.0, 0.0, 0.0)),
+        state(4, "_ball_", None, (0.0, 0.0, 1700.0), (0.0, 0.0, 0.0)),
+    ];
+    data[1].boost = Some(255);
+
+    let snapshot = plot::snapshot_at(&data, 6);
+    assert_eq!(snapshot.len(), 3);
+    let blue = snapshot.iter().find(|s| s.player_name == "Blue").unwrap();
+    assert_eq!(blue.frame, 5);
+    assert_eq!(blue.boost_percent(), Some(100));
+
+    plot::plot_snapshot(&data, 6, &snapshot_file, true).unwrap();
+    assert!(Path::new(&snapshot_file).exists());
+    assert!(plot::plot_snapshot(&data, 0, &snapshot_file, false).is_err());
+
+    fs::remove_dir_all(output_dir).unwrap();
+}
+
+#[test]
+fn test_mirror_turns_facing_around() {
+    let mut car = state(1, "Orange", Some(1), (1000.0, 2000.0, 1700.0), (0.0, 0.0, 0.0));
+    car.linear_velocity_y = 500.0;
+    car.mirror();
+
+    assert_eq!((car.location_x, car.location_y), (-1000.0, -2000.0));
+    assert_eq!(car.linear_velocity_y, -500.0);
+    let (x, y) = car.facing().unwrap();
+    assert!((x + 1.0).abs() < 1e-9 && y.abs() < 1e-9);
+}
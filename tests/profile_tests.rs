@@ -0,0 +1,108 @@
+mod common;
+
+use common::Mock;
+use rattlebrain::ai::config::Config;
+use rattlebrain::ai::registry::Registry;
+use rattlebrain::convert;
+use rattlebrain::extract;
+use rattlebrain::profile::{self, Mode, Profile, Rank};
+use rattlebrain::prompt::{Priority, Section};
+use rattlebrain::query;
+use serde_json::json;
+use std::fs;
+
+#[test]
+fn test_profile_settings() {
+    let config = Config::from_json(r#"{"profile": {"rank": "Diamond 2", "goals": ["improve rotations"]}}"#).unwrap();
+    assert_eq!(config.profile.rank, Some(Rank::Diamond));
+    assert_eq!(config.profile.mode, None);
+    assert_eq!(config.profile.goals, vec!["improve rotations"]);
+    assert!(Config::from_json(r#"{"profile": {"rank": "Wood"}}"#).is_err());
+    assert!(Config::from_json(r#"{"profile": {"division": 2}}"#).is_err());
+
+    let mut config = Config::default();
+    config.set("profile.rank=Grand Champion").unwrap();
+    config.set("profile.mode=duel").unwrap();
+    config.set("profile.goals=faster kickoffs; better saves").unwrap();
+    assert_eq!(config.profile.rank, Some(Rank::GrandChampion));
+    assert_eq!(config.profile.goals, vec!["faster kickoffs", "better saves"]);
+    assert!(config.set("profile.mode=5v5").is_err());
+    assert!(config.set("profile.division=2").is_err());
+    assert_eq!(Rank::parse("ssl"), Ok(Rank::SupersonicLegend));
+    assert_eq!(Rank::parse("Champion III"), Ok(Rank::Champion));
+
+    // The replay's team size wins over the configured mode.
+    assert_eq!(config.profile.mode(Some(3)), Some(Mode::Standard));
+    assert_eq!(config.profile.mode(Some(4)), Some(Mode::Duel));
+    assert_eq!(config.profile.mode(None), Some(Mode::Duel));
+    assert_eq!(Profile::default().mode(None), None);
+    assert!(Profile::default().instructions(None).is_empty());
+
+    let instructions = config.profile.instructions(Some(Mode::Doubles));
+    assert_eq!(instructions.len(), 3);
+    assert!(instructions[0].starts_with("This is a 2v2 (Doubles) match."));
+    assert!(instructions[1].starts_with("Pitch the coaching at Grand Champion level"));
+    assert!(instructions[2].starts_with("Goals being worked on: faster kickoffs; better saves."));
+
+    let sections = vec![
+        Section::new("Player stats", Priority::Stats, vec![]),
+        Section::new("Rotation roles (share of time)", Priority::Stats, vec![]),
+    ];
+    assert_eq!(Mode::Standard.select(sections.clone()).len(), 2);
+    let titles: Vec<String> = Mode::Duel.select(sections).into_iter().map(|section| section.title).collect();
+    assert_eq!(titles, vec!["Player stats"]);
+}
+
+#[tokio::test]
+async fn test_query_applies_profile_and_header_mode() {
+    let match_guid = "query_applies_profile";
+    let header_csv_path = format!("./output/{}.header.json.csv", match_guid);
+    fs::create_dir_all("./output").unwrap();
+    fs::write(
+        &header_csv_path,
+        "engine_version,licensee_version,patch_version,primary_player_team,team_0_score,team_1_score,team_size,unfair_team_size\n868,32,10,0,2,1,1,null\n",
+    )
+    .unwrap();
+    assert_eq!(profile::team_size(match_guid), Some(1));
+    assert_eq!(profile::team_size("no_such_match"), None);
+
+    let mut config = Config::default();
+    config.set("profile.rank=Bronze").unwrap();
+    config.set("profile.mode=3v3").unwrap();
+    config.set("profile.goals=improve rotations").unwrap();
+    let mock = Mock::answering("Canned", "Hit the ball.");
+    let prompts = mock.prompts();
+    let mut registry = Registry::new();
+    registry.register(mock);
+
+    query::query_ai_with(&registry, &config, match_guid, "all", None).await.unwrap();
+    let prompt = prompts.lock().unwrap()[0].clone();
+    assert!(prompt.contains("This is a 1v1 (Duel) match."), "{}", prompt);
+    assert!(!prompt.contains("3v3"));
+    assert!(prompt.contains("Pitch the coaching at Bronze level: stick to fundamentals"));
+    assert!(prompt.contains("Goals being worked on: improve rotations."));
+
+    fs::remove_file(header_csv_path).unwrap();
+    fs::remove_file(format!("./output/{}.query.txt", match_guid)).unwrap();
+}
+
+#[test]
+fn test_team_size_survives_extract_and_convert() {
+    let match_guid = "team_size_survives_conversion";
+    let replay = json!({
+        "header": { "body": { "engine_version": 868, "properties": { "elements": [
+            ["TeamSize", { "value": { "int": 2 } }],
+            ["MatchGuid", { "value": { "str": match_guid } }],
+        ] } } },
+        "content": { "body": { "frames": [] } },
+    });
+    extract::parse_replay(replay, match_guid.to_string()).unwrap();
+    convert::convert_match(match_guid).unwrap();
+    assert_eq!(profile::team_size(match_guid), Some(2));
+
+    for section in extract::SECTIONS {
+        let _ = fs::remove_file(format!("./output/{}.{}.json", match_guid, section));
+        let _ = fs::remove_file(format!("./output/{}.{}.json.csv", match_guid, section));
+    }
+    let _ = fs::remove_file(format!("./output/{}.demos.csv", match_guid));
+}
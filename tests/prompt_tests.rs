@@ -0,0 +1,56 @@
+use rattlebrain::ai::tokens;
+use rattlebrain::prompt::{self, Priority, Section};
+
+fn lines(prefix: &str, count: usize) -> Vec<String> {
+    (0..count).map(|i| format!("{} line {}", prefix, i)).collect()
+}
+
+/// One token per character keeps the arithmetic readable.
+fn chars(text: &str) -> usize {
+    text.chars().count()
+}
+
+#[test]
+fn test_assemble_cuts_lowest_priority_first() {
+    let sections = vec![
+        Section::new("", Priority::Required, vec!["Coach this match.".to_string()]),
+        Section::new("Frames", Priority::Frames, lines("frame", 10)),
+        Section::new("Events", Priority::Events, lines("event", 10)),
+        Section::new("Stats", Priority::Stats, lines("stat", 3)),
+    ];
+
+    let full = prompt::assemble(&sections, usize::MAX, &chars);
+    assert!(full.dropped.is_empty());
+    assert_eq!(full.text, prompt::render(&sections));
+
+    // Room for the instructions, the stats and a few events, but no frames.
+    let fitted = prompt::assemble(&sections, 120, &chars);
+    assert!(fitted.text.starts_with("Coach this match.\n"));
+    assert!(fitted.text.contains("stat line 2"));
+    assert!(fitted.text.contains("event line 0"));
+    assert!(!fitted.text.contains("event line 9"));
+    assert!(!fitted.text.contains("## Frames"));
+    assert!(fitted.text.find("## Events") < fitted.text.find("## Stats"), "sections keep their order");
+    assert_eq!(fitted.dropped.len(), 2);
+    assert!(fitted.dropped[0].starts_with("truncated Events to "));
+    assert_eq!(fitted.dropped[1], "dropped Frames");
+
+    let required_only = prompt::assemble(&sections, 1, &chars);
+    assert!(required_only.text.starts_with("Coach this match."));
+    assert!(!required_only.text.contains("##"));
+    assert_eq!(required_only.dropped, vec!["dropped Stats", "dropped Events", "dropped Frames"]);
+}
+
+#[test]
+fn test_prompt_budget_per_model() {
+    assert_eq!(tokens::estimate("abcdefg", 3.5), 2);
+    assert_eq!(tokens::context_window("claude-3-5-sonnet-20241022"), 200_000);
+    assert_eq!(tokens::context_window("llama3.1"), tokens::DEFAULT_CONTEXT_WINDOW);
+
+    assert_eq!(tokens::prompt_budget("gpt-4o", None, Some(16_000)), 112_000);
+    assert_eq!(
+        tokens::prompt_budget("llama3.1", None, None),
+        tokens::DEFAULT_CONTEXT_WINDOW - tokens::DEFAULT_ANSWER_TOKENS
+    );
+    assert_eq!(tokens::prompt_budget("gpt-4o", Some(6000), Some(16_000)), 6000);
+}
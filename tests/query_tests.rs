@@ -1,83 +1,257 @@
-// use rattlebrain::query::query_ai;
-// use std::fs;
-
-// #[test]
-// fn test_query_output_file_exists() {
-//     // Define the path to the query output file
-//     let match_guid = "383F0B0411EFAC27082CAFA884251EFF".to_string();
-//     let query_output_path = format!("output/{}.query.txt", match_guid);
-//     let focus = "all".to_string();
-
-//     // println!("Querying AI for insights...");
-//     // match query_ai(match_guid, focus).await {
-//     //     Ok(response) => {
-//     //         println!("{}", &response);
-//     //     }
-//     //     Err(e) => eprintln!("Failed to save AI feedback: {}", e),
-//     // }
-
-//     // Check if the output file exists
-//     assert!(
-//         fs::metadata(query_output_path).is_ok(),
-//         "The query output file does not exist at the expected location: {}",
-//         query_output_path
-//     );
-
-//     // Cleanupfile
-//     fs::remove_file(query_output_path).expect("Failed to remove query text file");
-// }
-
-// #[test]
-// async fn test_query_output_file_exists() {
-//     // Define the path to the query output file
-//     let match_guid = "383F0B0411EFAC27082CAFA884251EFF".to_string();
-//     let query_output_path = format!("output/{}.query.txt", match_guid);
-//     let focus = "all".to_string();
-
-//     println!("Querying AI for insights...");
-//     match query::query_ai(match_guid, focus).await {
-//         Ok(response) => {
-//             println!("{}", &response);
-//         }
-//         Err(e) => eprintln!("Failed to save AI feedback: {}", e),
-//     }
-
-//     // Check if the output file exists
-//     assert!(
-//         fs::metadata(query_output_path).is_ok(),
-//         "The query output file does not exist at the expected location: {}",
-//         query_output_path
-//     );
-
-//     // Cleanupfile
-//     fs::remove_file(query_output_path).expect("Failed to remove query text file");
-// }
-
-
-
-// #[cfg(test)]
-// mod tests {
-//     use super::*;
-//     use tokio;
-
-//     #[tokio::test]
-//     async fn test_claude_send_prompt() {
-//         let api_key = "test_api_key";
-//         let base_url = "https://api.anthropic.com/v1/complete";
-//         let claude_ai = ClaudeAI::new(api_key, base_url);
-
-//         // Replace this with a real prompt and expected response for integration testing.
-//         let prompt = "Hello, Claude!";
-//         let response = claude_ai
-//             .send_prompt(prompt, Some(100), Some(0.7))
-//             .await;
-
-//         match response {
-//             Ok(completion) => {
-//                 println!("Received response: {}", completion);
-//                 assert!(!completion.is_empty());
-//             }
-//             Err(e) => panic!("Failed to send prompt: {}", e),
-//         }
-//     }
-// }
+mod common;
+
+use common::{Fixture, Mock};
+use rattlebrain::ai::anthropic::Anthropic;
+use rattlebrain::ai::config::{Config, ProviderConfig};
+use rattlebrain::ai::google::Gemini;
+use rattlebrain::ai::openai::OpenAI;
+use rattlebrain::ai::provider::Capabilities;
+use rattlebrain::ai::registry::Registry;
+use rattlebrain::ai::usage::Meter;
+use rattlebrain::prompt::{Priority, Section};
+use rattlebrain::query;
+use std::fs;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+fn prompt() -> Vec<Section> {
+    vec![Section::new("", Priority::Required, vec!["prompt".to_string()])]
+}
+
+/// Answers structured queries with a fixed coaching report.
+fn coach() -> Mock {
+    let report = r#"{"summary": "Solid match.", "players": [{"player_name": "Blue", "strengths": [], "weaknesses": [{"point": "Late rotations", "frames": [{"start_frame": 120, "end_frame": 300}]}], "drills": []}]}"#;
+    Mock::answering("Coach", report).with_capabilities(Capabilities {
+        structured_output: true,
+        ..Capabilities::default()
+    })
+}
+
+#[tokio::test]
+async fn test_query_ai_with_custom_registry() {
+    let match_guid = "query_ai_with_custom_registry";
+    let canned = Mock::answering("Canned", "Rotate back post.");
+    let prompts = canned.prompts();
+    let mut registry = Registry::new();
+    registry.register(canned);
+
+    let report = query::query_ai_with(&registry, &Config::default(), match_guid, "all", None).await.unwrap();
+    assert!(report.starts_with("# Rattlebrain Replay Analysis"));
+    assert!(report.contains("## Canned response\n\n Rotate back post."));
+
+    let prompts = prompts.lock().unwrap();
+    assert_eq!(prompts.len(), 1);
+    assert!(prompts[0].contains("Strategy Analysis"), "{}", prompts[0]);
+    let saved = fs::read_to_string(format!("./output/{}.query.txt", match_guid)).unwrap();
+    assert_eq!(saved, prompts[0], "the prompt sent is the prompt saved");
+
+    fs::remove_file(format!("./output/{}.query.txt", match_guid)).unwrap();
+}
+
+#[tokio::test]
+async fn test_query_ai_with_no_providers() {
+    let result = query::query_ai_with(&Registry::new(), &Config::default(), "no_providers", "all", None).await;
+    assert!(result.is_err());
+}
+
+#[tokio::test]
+async fn test_synthesis_merges_responses_with_appendix() {
+    let match_guid = "synthesis_merges_responses";
+    let judge = Mock::answering("Judge", "Judge read: the first answer").then("Judge read: every answer");
+    let judge_prompts = judge.prompts();
+    let mut registry = Registry::new();
+    registry.register(Mock::answering("Canned", "Rotate back post."));
+    registry.register(Mock::failing("Down", "overloaded"));
+    registry.register(judge);
+    let config = Config {
+        synthesizer: Some("judge".to_string()),
+        ..Config::default()
+    };
+
+    let report = query::query_ai_with(&registry, &config, match_guid, "all", None).await.unwrap();
+    assert!(report.contains("| Judge (synthesis) | answered |"));
+    assert!(report.contains("## Unified report\n\n_Synthesized by Judge._"));
+    assert!(report.contains("## Appendix: provider responses"));
+    assert!(report.contains("### Canned response\n\n Rotate back post."));
+    assert!(!report.contains("### Down response"));
+
+    let unified = &report[report.find("## Unified report").unwrap()..report.find("## Appendix").unwrap()];
+    assert!(unified.contains("Judge read: every answer"));
+    let synthesis_prompt = judge_prompts.lock().unwrap()[1].clone();
+    assert!(synthesis_prompt.contains("Rotate back post."), "the synthesizer sees every answer");
+    assert!(synthesis_prompt.contains("Judge read: the first answer"));
+    assert!(!synthesis_prompt.contains("overloaded"));
+
+    let results = query::query_providers(&registry, &prompt(), &[], Duration::from_secs(1)).await;
+    let single = &results[..1];
+    assert!(query::synthesize(&registry, None, single, Duration::from_secs(1)).await.is_none());
+
+    fs::remove_file(format!("./output/{}.query.txt", match_guid)).unwrap();
+}
+
+#[tokio::test]
+async fn test_query_coaching_with_validates_answers() {
+    let match_guid = "query_coaching_with_validates_answers";
+    let mut registry = Registry::new();
+    registry.register(coach());
+    registry.register(Mock::answering("Canned", "Rotate back post."));
+
+    let report = query::query_coaching_with(&registry, &Config::default(), match_guid, "all", None).await.unwrap();
+    assert!(report.contains("## Coach response\n\n Solid match."));
+    assert!(report.contains("- Late rotations [frames 120-300]"));
+    assert!(report.contains("| Canned | failed:"), "free text is not a coaching report");
+
+    let coaching_path = format!("./output/{}.coaching.json", match_guid);
+    let saved: serde_json::Value = serde_json::from_str(&fs::read_to_string(&coaching_path).unwrap()).unwrap();
+    assert_eq!(saved[0]["provider"], "Coach");
+    assert_eq!(saved[0]["report"]["players"][0]["weaknesses"][0]["frames"][0]["end_frame"], 300);
+
+    fs::remove_file(coaching_path).unwrap();
+    fs::remove_file(format!("./output/{}.query.txt", match_guid)).unwrap();
+}
+
+#[tokio::test]
+async fn test_query_providers_concurrently_with_deadline() {
+    let delay = Duration::from_millis(300);
+    let mut registry = Registry::new();
+    registry.register(Mock::answering("First", "Boost less.").after(delay));
+    registry.register(Mock::failing("Second", "overloaded").after(delay));
+    registry.register(Mock::answering("Third", "Shadow more.").after(delay));
+    registry.register(Mock::answering("Late", "Too late.").after(Duration::from_secs(30)));
+
+    let started = Instant::now();
+    let results = query::query_providers(&registry, &prompt(), &[], Duration::from_secs(1)).await;
+    assert!(started.elapsed() < Duration::from_millis(1500), "providers ran one after another");
+
+    let names: Vec<&str> = results.iter().map(|r| r.name.as_str()).collect();
+    assert_eq!(names, vec!["First", "Second", "Third", "Late"]);
+    assert_eq!(results[0].outcome.as_deref(), Ok("Boost less."));
+    assert_eq!(results[1].outcome.as_ref().unwrap_err(), "overloaded");
+    assert_eq!(results[3].outcome.as_ref().unwrap_err(), "deadline exceeded");
+
+    let report = query::render_report(&results, None);
+    assert!(report.contains("| Second | failed: overloaded |"));
+    assert!(report.contains("## Third response\n\n Shadow more."));
+    assert!(!report.contains("## Late response"));
+}
+
+#[tokio::test]
+async fn test_query_ai_replays_recorded_providers() {
+    let match_guid = "query_ai_replays_recorded_providers";
+    let openai = Fixture::serve("query_ai_openai", "https://api.openai.com").await;
+    let anthropic = Fixture::serve("query_ai_anthropic", "https://api.anthropic.com").await;
+    let gemini = Fixture::serve("query_ai_gemini", "https://generativelanguage.googleapis.com").await;
+    let config = |base_url: String, defaults: ProviderConfig| ProviderConfig { base_url, ..defaults };
+
+    let meter = Arc::new(Meter::default());
+    let mut registry = Registry::new();
+    let openai_config = config(format!("{}/v1", openai.url()), ProviderConfig::openai());
+    let openai_key = common::api_key("OPENAI_API_KEY");
+    registry.register(OpenAI::with_config("OpenAI", Some(&openai_key), openai_config).with_meter(meter.clone()));
+    let anthropic_config = config(format!("{}/v1", anthropic.url()), ProviderConfig::anthropic());
+    registry.register(Anthropic::with_config(&common::api_key("ANTHROPIC_API_KEY"), anthropic_config).with_meter(meter.clone()));
+    let gemini_config = config(format!("{}/v1beta", gemini.url()), ProviderConfig::gemini());
+    registry.register(Gemini::with_config(&common::api_key("GEMINI_API_KEY"), gemini_config).with_meter(meter.clone()));
+    let config = Config {
+        synthesizer: Some("anthropic".to_string()),
+        ..Config::default()
+    };
+
+    let report = query::query_ai_with(&registry, &config, match_guid, "all", None).await.unwrap();
+    assert!(report.contains("| OpenAI | answered |"), "{}", report);
+    assert!(report.contains("| Anthropic (synthesis) | answered |"));
+    assert!(report.contains("## Unified report\n\n_Synthesized by Anthropic._"));
+    assert!(report.contains("All three coaches agree"));
+    assert!(report.contains("### OpenAI response\n\n Blue is slow to rotate back"));
+    assert!(report.contains("### Gemini response\n\n Orange controlled possession"));
+    let usage = meter.summary();
+    assert!(usage.contains("| OpenAI | gpt-4o | 1 | 3181 | 48 |"), "{}", usage);
+    assert!(usage.contains("| Anthropic | claude-3-5-sonnet-20241022 | 2 | 4232 | 103 |"));
+
+    let prompt = fs::read_to_string(format!("./output/{}.query.txt", match_guid)).unwrap();
+    let requests = openai.finish().await;
+    assert!(requests[0].body.contains("\"stream\":true"));
+    assert!(requests[0].body.contains("Strategy Analysis"));
+    assert!(prompt.contains("Strategy Analysis"));
+    let requests = anthropic.finish().await;
+    assert_eq!(requests.len(), 2);
+    assert!(requests[1].body.contains("Blue is slow to rotate back"), "the synthesis sees every answer");
+    assert!(requests[1].body.contains("Orange controlled possession"));
+    gemini.finish().await;
+
+    fs::remove_file(format!("./output/{}.query.txt", match_guid)).unwrap();
+}
+
+#[tokio::test]
+async fn test_query_ai_reports_provider_errors() {
+    let match_guid = "query_ai_reports_provider_errors";
+    let openai = Fixture::serve("query_errors_openai", "https://api.openai.com").await;
+    let anthropic = Fixture::serve("query_errors_anthropic", "https://api.anthropic.com").await;
+    let gemini = Fixture::serve("query_errors_gemini", "https://generativelanguage.googleapis.com").await;
+    let config = |base_url: String, defaults: ProviderConfig| ProviderConfig { base_url, ..defaults };
+
+    let mut registry = Registry::new();
+    let openai_config = config(format!("{}/v1", openai.url()), ProviderConfig::openai());
+    registry.register(OpenAI::with_config("OpenAI", Some("not-a-key"), openai_config));
+    let anthropic_config = config(format!("{}/v1", anthropic.url()), ProviderConfig::anthropic());
+    registry.register(Anthropic::with_config(&common::api_key("ANTHROPIC_API_KEY"), anthropic_config));
+    let gemini_config = config(format!("{}/v1beta", gemini.url()), ProviderConfig::gemini());
+    registry.register(Gemini::with_config(&common::api_key("GEMINI_API_KEY"), gemini_config));
+
+    let report = query::query_ai_with(&registry, &Config::default(), match_guid, "all", None).await.unwrap();
+    assert!(report.contains("| OpenAI | failed: Authentication failed:"), "{}", report);
+    assert!(report.contains("| Gemini | failed: Blocked by content filter: Gemini blocked the prompt: OTHER |"));
+    assert!(report.contains("## Anthropic response\n\n Blue's kickoffs are the weak spot."));
+    assert!(!report.contains("## Unified report"), "one answer has nothing to merge");
+
+    assert_eq!(openai.finish().await.len(), 2, "a failed stream is asked again without streaming");
+    anthropic.finish().await;
+    gemini.finish().await;
+    fs::remove_file(format!("./output/{}.query.txt", match_guid)).unwrap();
+}
+
+#[tokio::test]
+async fn test_plots_go_to_providers_that_accept_images() {
+    let match_guid = "plots_go_to_providers";
+    fs::create_dir_all("./output").unwrap();
+    let heatmap = format!("./output/{}.replay.frames.json.csv.png", match_guid);
+    let ball = format!("./output/{}.replay.frames.json.csv_ball.png", match_guid);
+    fs::write(&heatmap, b"heatmap").unwrap();
+    fs::write(&ball, b"ball").unwrap();
+    let completion = r#"{"choices":[{"message":{"content":"The heatmap shows Blue camping in net."}}]}"#;
+    let (address, requests) = common::stub_server(vec![
+        common::http_response("400 Bad Request", "", r#"{"error":"stream is not supported"}"#),
+        common::http_response("200 OK", "", completion),
+    ])
+    .await;
+
+    let text_only = Mock::answering("Text", "Rotate back post.");
+    let prompts = text_only.prompts();
+    let mut registry = Registry::new();
+    let local = ProviderConfig {
+        base_url: format!("{}/v1", address),
+        ..ProviderConfig::local()
+    };
+    registry.register(OpenAI::with_config("Local", None, local));
+    registry.register(text_only);
+    let config = Config {
+        attach_images: true,
+        synthesizer: Some("text".to_string()),
+        ..Config::default()
+    };
+
+    let report = query::query_ai_with(&registry, &config, match_guid, "all", None).await.unwrap();
+    assert!(report.contains("The heatmap shows Blue camping in net."), "{}", report);
+
+    let requests = requests.await.unwrap();
+    let request = &requests[1];
+    assert!(request.contains("The attached images are plots of this match, in order: heatmap, ball."));
+    assert!(request.contains("data:image/png;base64,aGVhdG1hcA=="));
+    assert!(request.contains("data:image/png;base64,YmFsbA=="));
+    let prompts = prompts.lock().unwrap();
+    assert!(!prompts[0].contains("attached images"), "only providers that accept images see them");
+
+    for file in [heatmap, ball, format!("./output/{}.query.txt", match_guid)] {
+        fs::remove_file(file).unwrap();
+    }
+}
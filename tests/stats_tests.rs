@@ -0,0 +1,46 @@
+mod common;
+
+use common::state;
+use rattlebrain::plot;
+use rattlebrain::stats;
+
+#[test]
+fn test_field_thirds_after_normalizing_sides() {
+    // Both players sit in front of their own goal
+    let mut data = vec![
+        state(0, "Blue", Some(0), (100000.0, -450000.0, 1700.0), (0.0, 0.0, 0.0)),
+        state(0, "Blue", Some(0), (100000.0, 450000.0, 1700.0), (0.0, 0.0, 0.0)),
+        state(0, "Orange", Some(1), (100000.0, 450000.0, 1700.0), (0.0, 0.0, 0.0)),
+        state(0, "Orange", Some(1), (100000.0, 0.0, 1700.0), (0.0, 0.0, 0.0)),
+        state(0, "_ball_", None, (100000.0, 450000.0, 1700.0), (0.0, 0.0, 0.0)),
+    ];
+    plot::normalize_sides(&mut data);
+    assert_eq!(data[2].location_y, -450000.0);
+    assert_eq!(data[2].location_x, -100000.0);
+    assert_eq!(data[4].location_y, 450000.0, "the ball is not mirrored");
+
+    let thirds = stats::field_thirds(&data);
+    assert_eq!(thirds.len(), 2);
+    assert_eq!(thirds[0].player_name, "Blue");
+    assert_eq!(thirds[0].defensive_third, 0.5);
+    assert_eq!(thirds[0].offensive_third, 0.5);
+    assert_eq!(thirds[1].player_name, "Orange");
+    assert_eq!(thirds[1].defensive_third, 0.5);
+    assert_eq!(thirds[1].neutral_third, 0.5);
+}
+
+#[test]
+fn test_aggregate_field_thirds_weights_by_samples() {
+    let first = stats::field_thirds(&[state(0, "Blue", Some(0), (100000.0, -450000.0, 1700.0), (0.0, 0.0, 0.0))]);
+    let second = stats::field_thirds(&[
+        state(0, "Blue", Some(1), (100000.0, 0.0, 1700.0), (0.0, 0.0, 0.0)),
+        state(0, "Blue", Some(1), (100000.0, 0.0, 1700.0), (0.0, 0.0, 0.0)),
+        state(0, "Blue", Some(1), (100000.0, 0.0, 1700.0), (0.0, 0.0, 0.0)),
+    ]);
+
+    let combined = stats::aggregate_field_thirds(&[first, second]);
+    assert_eq!(combined.len(), 1);
+    assert_eq!(combined[0].samples, 4);
+    assert_eq!(combined[0].defensive_third, 0.25);
+    assert_eq!(combined[0].neutral_third, 0.75);
+}
@@ -0,0 +1,95 @@
+mod common;
+
+use common::Mock;
+use rattlebrain::ai::config::Config;
+use rattlebrain::ai::registry::Registry;
+use rattlebrain::prompt::{Priority, Section};
+use rattlebrain::query;
+use rattlebrain::templates::{self, Template};
+use std::fs;
+
+#[test]
+fn test_built_in_templates_and_overrides() {
+    let dir = "./output/template_tests_overrides";
+    let _ = fs::remove_dir_all(dir);
+    fs::create_dir_all(dir).unwrap();
+    fs::write(format!("{}/mechanics.md", dir), "Only look at aerials.\n").unwrap();
+    fs::write(format!("{}/kickoffs.md", dir), "Review every kickoff.\n").unwrap();
+
+    let strategy = templates::load(dir, "strategy").unwrap();
+    assert_eq!(strategy.source, "built-in");
+    let brief = strategy.render("abc", None).unwrap();
+    assert_eq!(brief.instructions.len(), 2);
+    assert!(brief.instructions[1].starts_with("Strategy Analysis:"));
+    assert_eq!(brief.sections, None);
+
+    let mechanics = templates::load(dir, "mechanics").unwrap();
+    assert_eq!(mechanics.render("abc", None).unwrap().instructions, vec!["Only look at aerials."]);
+    assert!(templates::load(dir, "kickoffs").is_ok());
+    assert_eq!(templates::load("./no/such/dir", "mechanics").unwrap().source, "built-in");
+
+    assert_eq!(templates::focuses(dir), vec!["all", "decision_making", "kickoffs", "mechanics", "strategy"]);
+    let error = templates::load(dir, "wavedashes").unwrap_err();
+    assert!(error.contains("Unknown focus wavedashes. Available: all, decision_making, kickoffs"), "{}", error);
+    assert!(templates::load(dir, "../secrets").is_err());
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_template_placeholders() {
+    let text = "Coach the {{focus}} of match {{match_guid}}.\n{{#player}}\nTalk to {{ player }} directly.\n{{/player}}\n{{^player}}Talk to the team.{{/player}}\n\n{{timeline}}\n{{player_stats}}\n";
+    let template = Template::new("kickoffs", "kickoffs.md", text);
+
+    let brief = template.render("abc", Some("Blue")).unwrap();
+    assert_eq!(brief.instructions, vec!["Coach the kickoffs of match abc.", "Talk to Blue directly."]);
+    assert_eq!(brief.sections, Some(vec!["timeline".to_string(), "player_stats".to_string()]));
+    let brief = template.render("abc", None).unwrap();
+    assert_eq!(brief.instructions, vec!["Coach the kickoffs of match abc.", "Talk to the team."]);
+
+    let sections = vec![
+        Section::new("Player stats", Priority::Stats, vec![]),
+        Section::new("Rotation roles (share of time)", Priority::Stats, vec![]),
+        Section::new("Timeline", Priority::Events, vec![]),
+    ];
+    let titles: Vec<String> = brief.select(sections).into_iter().map(|section| section.title).collect();
+    assert_eq!(titles, vec!["Timeline", "Player stats"]);
+
+    let error = |text: &str| Template::new("x", "x.md", text).render("abc", None).unwrap_err();
+    assert_eq!(error("Hi {{coach}}"), "Error in template x.md: unknown placeholder {{coach}}");
+    assert!(error("Hi {{player}}").contains("only set when coaching one player"));
+    assert!(error("See {{timeline}} below").contains("must be on a line of its own"));
+    assert!(error("{{#player}}Hi").contains("has no {{/player}}"));
+    assert!(error("{{#team}}Hi{{/team}}").contains("unknown block {{#team}}"));
+}
+
+#[tokio::test]
+async fn test_query_uses_focus_template() {
+    let match_guid = "query_uses_focus_template";
+    let dir = "./output/template_tests_query";
+    let _ = fs::remove_dir_all(dir);
+    fs::create_dir_all(dir).unwrap();
+    fs::write(format!("{}/goals_only.md", dir), "Explain every goal of {{match_guid}}.\n{{goals}}\n").unwrap();
+    let config = Config {
+        templates: dir.to_string(),
+        ..Config::default()
+    };
+    let mock = Mock::answering("Canned", "Clear the ball.");
+    let prompts = mock.prompts();
+    let mut registry = Registry::new();
+    registry.register(mock);
+
+    query::query_ai_with(&registry, &config, match_guid, "goals_only", None).await.unwrap();
+    let prompt = prompts.lock().unwrap()[0].clone();
+    assert!(prompt.starts_with("Explain every goal of query_uses_focus_template.\n"), "{}", prompt);
+    assert!(prompt.contains("Cite the moments you refer to"));
+    assert!(prompt.contains("## Goal breakdown"));
+    assert!(!prompt.contains("## Player statistics"));
+    assert!(!prompt.contains("Strategy Analysis"));
+
+    let result = query::query_ai_with(&registry, &config, match_guid, "wavedashes", None).await;
+    assert!(result.unwrap_err().to_string().contains("Unknown focus wavedashes"));
+
+    fs::remove_dir_all(dir).unwrap();
+    fs::remove_file(format!("./output/{}.query.txt", match_guid)).unwrap();
+}
@@ -0,0 +1,30 @@
+mod common;
+
+use common::state;
+use rattlebrain::viewer;
+
+#[test]
+fn test_resample_holds_last_known_state() {
+    let data = vec![
+        state(0, "_ball_", None, (0.0, 0.0, 9300.0), (0.0, 0.0, 0.0)),
+        state(3, "Blue", Some(0), (0.0, -300000.0, 9300.0), (0.0, 0.0, 0.0)),
+        state(9, "Blue", Some(0), (0.0, -200000.0, 9300.0), (0.0, 0.0, 0.0)),
+        state(9, "_ball_", None, (0.0, 600000.0, 9300.0), (0.0, 0.0, 0.0)),
+        state(12, "Orange", Some(1), (0.0, 300000.0, 9300.0), (0.0, 0.0, 0.0)),
+    ];
+
+    let resampled = viewer::resample(&data, 10.0);
+    let samples = resampled["samples"].as_array().unwrap();
+
+    assert_eq!(resampled["players"][0]["name"], "Blue");
+    assert_eq!(resampled["players"][1]["team"], 1);
+    assert_eq!(samples.len(), 5);
+
+    // Positions are stored in unreal units, players appear once seen
+    assert_eq!(samples[0]["players"][0], serde_json::Value::Null);
+    assert_eq!(samples[1]["players"][0], serde_json::json!([0.0, -3000.0, 66]));
+    assert_eq!(samples[2]["players"][0], serde_json::json!([0.0, -3000.0, 66]));
+    assert_eq!(samples[3]["players"][0], serde_json::json!([0.0, -2000.0, 66]));
+    assert_eq!(samples[3]["ball"], serde_json::json!([0.0, 6000.0, 93.0]));
+    assert_eq!(samples[4]["players"][1], serde_json::json!([0.0, 3000.0, 66]));
+}